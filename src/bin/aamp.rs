@@ -0,0 +1,116 @@
+//! Command-line tool for converting, comparing, merging, and inspecting AAMP files. Files are
+//! read as binary if they start with the `AAMP` magic and as YAML otherwise. Merged files are
+//! written as YAML if the output path ends in `.yml` or `.yaml` and as binary otherwise.
+use aamp::names::NameTable;
+use aamp::ParameterIO;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "Usage:
+    aamp to-yaml <input> [output]            Convert a file to YAML (stdout if no output)
+    aamp to-binary <input> <output>          Convert a file to binary
+    aamp diff <base> <other>                 Print what <other> changes in <base> as YAML
+    aamp merge <base> <patch>... -o <output> Merge patches on top of a file in order
+    aamp info <input>                        Print a summary of a file";
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    match args.as_slice() {
+        ["to-yaml", input] => print(&to_yaml(&load(input)?)?)?,
+        ["to-yaml", input, output] => fs::write(output, to_yaml(&load(input)?)?)?,
+        ["to-binary", input, output] => fs::write(output, load(input)?.to_binary()?)?,
+        ["diff", base, other] => {
+            let base = load(base)?;
+            let other = load(other)?;
+            let summary = base.diff_summary(&other);
+            eprintln!(
+                "{} added, {} removed, {} modified",
+                summary.added, summary.removed, summary.modified
+            );
+            print(&to_yaml(&base.diff(&other))?)?;
+        }
+        ["merge", base, rest @ ..] => merge(base, rest)?,
+        ["info", input] => info(&load(input)?),
+        ["-h"] | ["--help"] | ["help"] => println!("{}", USAGE),
+        _ => return Err(format!("invalid arguments\n{}", USAGE).into()),
+    }
+    Ok(())
+}
+
+fn merge(base: &str, rest: &[&str]) -> Result<()> {
+    let (patches, output) = match rest {
+        [patches @ .., "-o", output] if !patches.is_empty() => (patches, *output),
+        _ => {
+            return Err(format!("merge needs at least one patch and -o <output>\n{}", USAGE).into())
+        }
+    };
+    let mut pio = load(base)?;
+    let patches = patches
+        .iter()
+        .map(|patch| load(patch))
+        .collect::<Result<Vec<ParameterIO>>>()?;
+    pio.merge_many(&patches);
+    save(&pio, output)
+}
+
+fn info(pio: &ParameterIO) {
+    println!("type: {}", pio.pio_type);
+    println!("version: {}", pio.version);
+    println!("lists: {}", pio.num_lists());
+    println!("objects: {}", pio.num_objects());
+    println!("parameters: {}", pio.num_parameters());
+}
+
+fn load(path: &str) -> Result<ParameterIO> {
+    let bytes = fs::read(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+    if bytes.starts_with(b"AAMP") {
+        Ok(ParameterIO::from_binary(&mut Cursor::new(&bytes))
+            .map_err(|err| format!("could not parse {}: {}", path, err))?)
+    } else {
+        Ok(ParameterIO::from_text(std::str::from_utf8(&bytes)?)
+            .map_err(|err| format!("could not parse {}: {}", path, err))?)
+    }
+}
+
+fn save(pio: &ParameterIO, path: &str) -> Result<()> {
+    let is_yaml = matches!(
+        Path::new(path).extension().and_then(|ext| ext.to_str()),
+        Some("yml") | Some("yaml")
+    );
+    if is_yaml {
+        fs::write(path, to_yaml(pio)?)?;
+    } else {
+        fs::write(path, pio.to_binary()?)?;
+    }
+    Ok(())
+}
+
+/// Converts to YAML with the stock names and the document's string values as names, which works
+/// with or without the `global-names` feature
+fn to_yaml(pio: &ParameterIO) -> Result<String> {
+    let mut names = NameTable::new(true);
+    names.add_string_values(pio);
+    pio.with_names(&names).to_text()
+}
+
+fn print(text: &str) -> Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(text.as_bytes())?;
+    if !text.ends_with('\n') {
+        stdout.write_all(b"\n")?;
+    }
+    Ok(())
+}
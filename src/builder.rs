@@ -0,0 +1,153 @@
+use super::names::NameTable;
+use super::{Parameter, ParameterIO, ParameterList, ParameterObject, WriteError};
+
+impl ParameterIO {
+    /// Starts building an `xml` document with `ParameterIOBuilder`
+    pub fn builder() -> ParameterIOBuilder {
+        ParameterIOBuilder::new("xml")
+    }
+}
+
+/// Builds a `ParameterIO` from names instead of hashes, e.g.
+/// `ParameterIO::builder().object("General", |o| o.param("Life", 100)).build()`.
+/// Lists, objects, and parameters are kept in the order they are added, which is also the order
+/// they are written in. Adding one with a name already used replaces it in place.
+#[derive(Debug, Clone)]
+pub struct ParameterIOBuilder {
+    pio: ParameterIO,
+    names: Vec<String>,
+}
+
+impl ParameterIOBuilder {
+    /// Starts a document with the given parameter IO type and version 0
+    pub fn new(pio_type: &str) -> ParameterIOBuilder {
+        ParameterIOBuilder {
+            pio: ParameterIO {
+                pio_type: pio_type.to_owned(),
+                ..Default::default()
+            },
+            names: vec![],
+        }
+    }
+
+    /// Sets the document's parameter IO type
+    pub fn pio_type(mut self, pio_type: &str) -> ParameterIOBuilder {
+        self.pio.pio_type = pio_type.to_owned();
+        self
+    }
+
+    /// Sets the document's version
+    pub fn version(mut self, version: u32) -> ParameterIOBuilder {
+        self.pio.version = version;
+        self
+    }
+
+    /// Adds an object to the root list, built by the given closure
+    pub fn object(
+        mut self,
+        name: &str,
+        build: impl FnOnce(ObjectBuilder) -> ObjectBuilder,
+    ) -> ParameterIOBuilder {
+        let obj = build(ObjectBuilder::default());
+        self.names.push(name.to_owned());
+        self.names.extend(obj.names);
+        self.pio.set_object(name, obj.obj);
+        self
+    }
+
+    /// Adds a list to the root list, built by the given closure
+    pub fn list(
+        mut self,
+        name: &str,
+        build: impl FnOnce(ListBuilder) -> ListBuilder,
+    ) -> ParameterIOBuilder {
+        let list = build(ListBuilder::default());
+        self.names.push(name.to_owned());
+        self.names.extend(list.names);
+        self.pio.set_list(name, list.list);
+        self
+    }
+
+    /// Finishes the document, checking it with `ParameterIO::validate`. Fails with the first
+    /// issue found, as the `WriteError` that writing the document would fail with.
+    pub fn build(self) -> Result<ParameterIO, WriteError> {
+        check(&self.pio)?;
+        Ok(self.pio)
+    }
+
+    /// Finishes the document like `build`, and adds every name used to the given table so the
+    /// document converts back to text with the same names
+    pub fn build_with_names(self, names: &mut NameTable) -> Result<ParameterIO, WriteError> {
+        check(&self.pio)?;
+        for name in &self.names {
+            names.add_name(name);
+        }
+        Ok(self.pio)
+    }
+}
+
+fn check(pio: &ParameterIO) -> Result<(), WriteError> {
+    match pio.validate().into_iter().next() {
+        Some(issue) => Err(issue.into()),
+        None => Ok(()),
+    }
+}
+
+/// Builds a `ParameterList` for `ParameterIOBuilder`
+#[derive(Debug, Clone, Default)]
+pub struct ListBuilder {
+    list: ParameterList,
+    names: Vec<String>,
+}
+
+impl ListBuilder {
+    /// Adds an object to the list, built by the given closure
+    pub fn object(
+        mut self,
+        name: &str,
+        build: impl FnOnce(ObjectBuilder) -> ObjectBuilder,
+    ) -> ListBuilder {
+        let obj = build(ObjectBuilder::default());
+        self.names.push(name.to_owned());
+        self.names.extend(obj.names);
+        self.list.set_object(name, obj.obj);
+        self
+    }
+
+    /// Adds a child list to the list, built by the given closure
+    pub fn list(
+        mut self,
+        name: &str,
+        build: impl FnOnce(ListBuilder) -> ListBuilder,
+    ) -> ListBuilder {
+        let list = build(ListBuilder::default());
+        self.names.push(name.to_owned());
+        self.names.extend(list.names);
+        self.list.set_list(name, list.list);
+        self
+    }
+
+    pub fn build(self) -> ParameterList {
+        self.list
+    }
+}
+
+/// Builds a `ParameterObject` for `ParameterIOBuilder`
+#[derive(Debug, Clone, Default)]
+pub struct ObjectBuilder {
+    obj: ParameterObject,
+    names: Vec<String>,
+}
+
+impl ObjectBuilder {
+    /// Adds a parameter to the object
+    pub fn param(mut self, name: &str, value: impl Into<Parameter>) -> ObjectBuilder {
+        self.obj.set_param(name, value.into());
+        self.names.push(name.to_owned());
+        self
+    }
+
+    pub fn build(self) -> ParameterObject {
+        self.obj
+    }
+}
@@ -0,0 +1,344 @@
+use super::merge::{merge_lists, merge_objects, MergePolicy};
+use super::{Parameter, ParameterIO, ParameterList, ParameterObject};
+use indexmap::IndexMap;
+
+/// Counts of the differences between two documents. A list, object, or parameter present only in
+/// the other document counts once as added, and one present only in this document counts once as
+/// removed, without counting anything inside it. A parameter present in both with a different
+/// value counts as modified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+}
+
+/// The structural differences between two documents, as computed by `diff` and replayed by
+/// `apply`. Unlike the patch document returned by `ParameterIO::diff`, this records removals too.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParameterIODiff {
+    /// The new version, if it changed
+    pub version: Option<u32>,
+    /// The new header flags, if they changed
+    pub flags: Option<u32>,
+    /// The new parameter IO type, if it changed
+    pub pio_type: Option<String>,
+    /// The changes to the root list
+    pub root: ListDiff,
+}
+
+impl ParameterIODiff {
+    /// Returns whether the two documents compared were the same
+    pub fn is_empty(&self) -> bool {
+        self.version.is_none()
+            && self.flags.is_none()
+            && self.pio_type.is_none()
+            && self.root.is_empty()
+    }
+
+    /// Counts the lists, objects, and parameters added, removed, or modified
+    pub fn summary(&self) -> DiffSummary {
+        let mut summary = DiffSummary::default();
+        summarize_list(&self.root, &mut summary);
+        summary
+    }
+}
+
+/// The changes to the lists and objects in a list, keyed by hash
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ListDiff {
+    pub lists: IndexMap<u32, ListChange>,
+    pub objects: IndexMap<u32, ObjectChange>,
+}
+
+impl ListDiff {
+    /// Returns whether nothing in the list changed
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty() && self.objects.is_empty()
+    }
+}
+
+/// The changes to the parameters in an object, keyed by hash
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjectDiff {
+    pub params: IndexMap<u32, ParamChange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListChange {
+    Added(ParameterList),
+    Removed,
+    Changed(ListDiff),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectChange {
+    Added(ParameterObject),
+    Removed,
+    Changed(ObjectDiff),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamChange {
+    Added(Parameter),
+    Removed,
+    Changed(Parameter),
+}
+
+/// Computes the lists, objects, and parameters added, removed, or changed in `b` relative to `a`,
+/// comparing nested lists and objects by hash
+pub fn diff(a: &ParameterIO, b: &ParameterIO) -> ParameterIODiff {
+    ParameterIODiff {
+        version: Some(b.version).filter(|version| *version != a.version),
+        flags: Some(b.flags).filter(|flags| *flags != a.flags),
+        pio_type: Some(&b.pio_type)
+            .filter(|pio_type| **pio_type != a.pio_type)
+            .cloned(),
+        root: diff_list(&a.lists, &a.objects, &b.lists, &b.objects),
+    }
+}
+
+/// Returns a copy of `base` with the changes in `diff` replayed on it. Applying the diff of `a`
+/// and `b` to `a` gives back `b`. The base need not be the document the diff was computed from,
+/// which makes it possible to combine changes from several sources: an added list or object that
+/// already exists is merged recursively into the existing one, changes to a list, object, or
+/// parameter that does not exist create it, and removals of anything missing are ignored.
+pub fn apply(base: &ParameterIO, diff: &ParameterIODiff) -> ParameterIO {
+    let mut pio = base.clone();
+    if let Some(version) = diff.version {
+        pio.version = version;
+    }
+    if let Some(flags) = diff.flags {
+        pio.flags = flags;
+    }
+    if let Some(pio_type) = &diff.pio_type {
+        pio.pio_type = pio_type.clone();
+    }
+    apply_list(&mut pio.lists, &mut pio.objects, &diff.root);
+    pio
+}
+
+fn diff_list(
+    a_lists: &IndexMap<u32, ParameterList>,
+    a_objects: &IndexMap<u32, ParameterObject>,
+    b_lists: &IndexMap<u32, ParameterList>,
+    b_objects: &IndexMap<u32, ParameterObject>,
+) -> ListDiff {
+    let mut diff = ListDiff::default();
+    for (crc, list) in b_lists {
+        match a_lists.get(crc) {
+            Some(a_list) => {
+                let list_diff =
+                    diff_list(&a_list.lists, &a_list.objects, &list.lists, &list.objects);
+                if !list_diff.is_empty() {
+                    diff.lists.insert(*crc, ListChange::Changed(list_diff));
+                }
+            }
+            None => {
+                diff.lists.insert(*crc, ListChange::Added(list.clone()));
+            }
+        }
+    }
+    for crc in a_lists.keys().filter(|crc| !b_lists.contains_key(*crc)) {
+        diff.lists.insert(*crc, ListChange::Removed);
+    }
+    for (crc, obj) in b_objects {
+        match a_objects.get(crc) {
+            Some(a_obj) => {
+                let obj_diff = diff_object(a_obj, obj);
+                if !obj_diff.params.is_empty() {
+                    diff.objects.insert(*crc, ObjectChange::Changed(obj_diff));
+                }
+            }
+            None => {
+                diff.objects.insert(*crc, ObjectChange::Added(obj.clone()));
+            }
+        }
+    }
+    for crc in a_objects.keys().filter(|crc| !b_objects.contains_key(*crc)) {
+        diff.objects.insert(*crc, ObjectChange::Removed);
+    }
+    diff
+}
+
+fn diff_object(a: &ParameterObject, b: &ParameterObject) -> ObjectDiff {
+    let mut diff = ObjectDiff::default();
+    for (crc, param) in b.params() {
+        match a.params().get(crc) {
+            Some(a_param) if a_param == param => (),
+            Some(_) => {
+                diff.params
+                    .insert(*crc, ParamChange::Changed(param.clone()));
+            }
+            None => {
+                diff.params.insert(*crc, ParamChange::Added(param.clone()));
+            }
+        }
+    }
+    for crc in a
+        .params()
+        .keys()
+        .filter(|crc| !b.params().contains_key(*crc))
+    {
+        diff.params.insert(*crc, ParamChange::Removed);
+    }
+    diff
+}
+
+fn apply_list(
+    lists: &mut IndexMap<u32, ParameterList>,
+    objects: &mut IndexMap<u32, ParameterObject>,
+    diff: &ListDiff,
+) {
+    for (crc, change) in &diff.lists {
+        match change {
+            ListChange::Added(list) => match lists.get_mut(crc) {
+                Some(existing) => {
+                    let policy = MergePolicy::default();
+                    merge_lists(&mut existing.lists, &list.lists, &policy);
+                    merge_objects(&mut existing.objects, &list.objects, &policy);
+                }
+                None => {
+                    lists.insert(*crc, list.clone());
+                }
+            },
+            ListChange::Removed => {
+                lists.shift_remove(crc);
+            }
+            ListChange::Changed(list_diff) => {
+                let list = lists.entry(*crc).or_default();
+                apply_list(&mut list.lists, &mut list.objects, list_diff);
+            }
+        }
+    }
+    for (crc, change) in &diff.objects {
+        match change {
+            ObjectChange::Added(obj) => {
+                let existing = objects.entry(*crc).or_default();
+                for (param_crc, param) in obj.params() {
+                    existing.params_mut().insert(*param_crc, param.clone());
+                }
+            }
+            ObjectChange::Removed => {
+                objects.shift_remove(crc);
+            }
+            ObjectChange::Changed(obj_diff) => {
+                apply_object(objects.entry(*crc).or_default(), obj_diff)
+            }
+        }
+    }
+}
+
+fn apply_object(obj: &mut ParameterObject, diff: &ObjectDiff) {
+    for (crc, change) in &diff.params {
+        match change {
+            ParamChange::Added(param) | ParamChange::Changed(param) => {
+                obj.params_mut().insert(*crc, param.clone());
+            }
+            ParamChange::Removed => {
+                obj.params_mut().shift_remove(crc);
+            }
+        }
+    }
+}
+
+impl ParameterIO {
+    /// Counts the lists, objects, and parameters added, removed, or modified in `other` relative
+    /// to this document, comparing nested lists and objects by hash. This is the summary of
+    /// `diff(self, other)`.
+    pub fn diff_summary(&self, other: &ParameterIO) -> DiffSummary {
+        diff(self, other).summary()
+    }
+
+    /// Returns a patch document holding only the lists, objects, and parameters that were added
+    /// or changed in `other` relative to this document. Unchanged parameters are left out, as are
+    /// lists and objects left with nothing in them. Deletions are not represented: anything
+    /// present in this document but missing from `other` is simply absent from the patch, so
+    /// merging the patch onto this document keeps it. The patch takes its version, flags, and type
+    /// from `other`. Use `diff` for a diff that records removals.
+    pub fn diff(&self, other: &ParameterIO) -> ParameterIO {
+        let (lists, objects) = patch_list(&diff(self, other).root);
+        ParameterIO {
+            version: other.version,
+            flags: other.flags,
+            pio_type: other.pio_type.clone(),
+            lists,
+            objects,
+            ..Default::default()
+        }
+    }
+}
+
+/// Builds the lists and objects of a patch document from the changes to a list, leaving out
+/// removals and anything left empty without them
+fn patch_list(diff: &ListDiff) -> (IndexMap<u32, ParameterList>, IndexMap<u32, ParameterObject>) {
+    let lists = diff
+        .lists
+        .iter()
+        .filter_map(|(crc, change)| match change {
+            ListChange::Added(list) => Some((*crc, list.clone())),
+            ListChange::Removed => None,
+            ListChange::Changed(list_diff) => {
+                let (lists, objects) = patch_list(list_diff);
+                if lists.is_empty() && objects.is_empty() {
+                    None
+                } else {
+                    Some((*crc, ParameterList { lists, objects }))
+                }
+            }
+        })
+        .collect();
+    let objects = diff
+        .objects
+        .iter()
+        .filter_map(|(crc, change)| match change {
+            ObjectChange::Added(obj) => Some((*crc, obj.clone())),
+            ObjectChange::Removed => None,
+            ObjectChange::Changed(obj_diff) => {
+                let obj = ParameterObject(
+                    obj_diff
+                        .params
+                        .iter()
+                        .filter_map(|(param_crc, change)| match change {
+                            ParamChange::Added(param) | ParamChange::Changed(param) => {
+                                Some((*param_crc, param.clone()))
+                            }
+                            ParamChange::Removed => None,
+                        })
+                        .collect(),
+                );
+                if obj.params().is_empty() {
+                    None
+                } else {
+                    Some((*crc, obj))
+                }
+            }
+        })
+        .collect();
+    (lists, objects)
+}
+
+fn summarize_list(diff: &ListDiff, summary: &mut DiffSummary) {
+    for change in diff.lists.values() {
+        match change {
+            ListChange::Added(_) => summary.added += 1,
+            ListChange::Removed => summary.removed += 1,
+            ListChange::Changed(list_diff) => summarize_list(list_diff, summary),
+        }
+    }
+    for change in diff.objects.values() {
+        match change {
+            ObjectChange::Added(_) => summary.added += 1,
+            ObjectChange::Removed => summary.removed += 1,
+            ObjectChange::Changed(obj_diff) => {
+                for change in obj_diff.params.values() {
+                    match change {
+                        ParamChange::Added(_) => summary.added += 1,
+                        ParamChange::Removed => summary.removed += 1,
+                        ParamChange::Changed(_) => summary.modified += 1,
+                    }
+                }
+            }
+        }
+    }
+}
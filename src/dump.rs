@@ -0,0 +1,75 @@
+use super::write::WriteError;
+use super::{Endian, ParameterIO};
+use std::fmt::Write;
+
+impl ParameterIO {
+    /// Serializes the document to its binary format and returns an annotated hex dump of the
+    /// result, split into the header, pio type, list, object, and parameter entries, data
+    /// section, string section, and reserved section. Each section is labelled with its offset
+    /// and size, read in the document's byte order. Intended only as a diagnostic aid for
+    /// inspecting the binary layout.
+    pub fn hex_dump(&self) -> Result<String, WriteError> {
+        let bytes = self.to_binary()?;
+        let word = |offset: usize| {
+            [
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ]
+        };
+        // The little-endian flag is in the first byte of the flags in either byte order
+        let endian = Endian::from_flags(u32::from_le_bytes(word(8)));
+        let header = |offset: usize| match endian {
+            Endian::Little => u32::from_le_bytes(word(offset)) as usize,
+            Endian::Big => u32::from_be_bytes(word(offset)) as usize,
+        };
+        let sections = [
+            ("header", 0x30),
+            ("pio type", header(0x14)),
+            ("lists", header(0x18) * 12),
+            ("objects", header(0x1C) * 8),
+            ("params", header(0x20) * 8),
+            ("data", header(0x24)),
+            ("strings", header(0x28)),
+            ("reserved", header(0x2C)),
+        ];
+        let mut dump = String::new();
+        let mut start = 0;
+        for (name, size) in sections.iter() {
+            let end = (start + size).min(bytes.len());
+            writeln!(dump, "{} @ {:#x} ({:#x} bytes)", name, start, size).unwrap();
+            for (i, row) in bytes[start..end].chunks(16).enumerate() {
+                write_row(&mut dump, start + i * 16, row);
+            }
+            start = end;
+        }
+        Ok(dump)
+    }
+}
+
+/// Writes one row of up to 16 bytes as its offset, the bytes in hex, and the bytes as ASCII
+fn write_row(dump: &mut String, offset: usize, row: &[u8]) {
+    write!(dump, "  {:08x} ", offset).unwrap();
+    for byte in row {
+        write!(dump, " {:02x}", byte).unwrap();
+    }
+    let ascii: String = row
+        .iter()
+        .map(|b| {
+            if b.is_ascii_graphic() {
+                *b as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    writeln!(
+        dump,
+        "{:width$}  |{}|",
+        "",
+        ascii,
+        width = (16 - row.len()) * 3
+    )
+    .unwrap();
+}
@@ -0,0 +1,496 @@
+use super::names::{self, NameTable};
+use super::types::*;
+use super::yaml::parse::parse_float;
+use super::{hash_name, Parameter, ParameterIO, ParameterList, ParameterObject};
+use indexmap::IndexMap;
+use serde_json::{Map, Number, Value};
+use std::convert::TryFrom;
+
+/// CRC of `param_root`, the parent of the top level lists and objects
+const ROOT_CRC: u32 = 2_767_637_356;
+
+#[derive(Debug, thiserror::Error)]
+pub enum JsonError {
+    #[error("JSON document not a valid ParameterIO: {0}")]
+    InvalidPio(String),
+    #[error("JSON document is not valid")]
+    InvalidJson(#[from] serde_json::Error),
+}
+
+type Result<T> = std::result::Result<T, JsonError>;
+
+fn invalid<T>(msg: String) -> Result<T> {
+    Err(JsonError::InvalidPio(msg))
+}
+
+impl ParameterIO {
+    /// Converts the document to a JSON representation which, unlike `to_plain_json`, keeps
+    /// everything needed to read it back with `from_json`. It is laid out like the YAML
+    /// representation: every parameter is wrapped as `{"type": "str32", "value": "..."}`, using
+    /// the YAML tag names (`bool`, `f32`, `int`, and `str` for the untagged ones), and keys are
+    /// names from the active name table and the document's string values, or decimal hashes.
+    /// Non-finite floats are written as the strings `".nan"`, `".inf"`, and `"-.inf"`.
+    #[cfg(feature = "global-names")]
+    pub fn to_json(&self) -> String {
+        self.to_json_with_names(&names::table_for(self))
+    }
+
+    /// Converts the document to a JSON representation like `to_json`, but looks up names in the
+    /// given table instead of the global one
+    pub fn to_json_with_names(&self, names: &NameTable) -> String {
+        let mut root = Map::new();
+        root.insert("version".to_owned(), self.version.into());
+        root.insert("type".to_owned(), self.pio_type.clone().into());
+        root.insert("flags".to_owned(), self.flags.into());
+        root.insert(
+            "unknown_section".to_owned(),
+            self.unknown_section.clone().into(),
+        );
+        root.insert(
+            "param_root".to_owned(),
+            list_to_tagged_json(&self.lists, &self.objects, ROOT_CRC, names),
+        );
+        serde_json::to_string_pretty(&Value::Object(root)).expect("JSON values always serialize")
+    }
+
+    /// Parses a document from the JSON representation written by `to_json`. Keys made only of
+    /// digits are read as hashes and anything else is hashed as a name. The `flags` and
+    /// `unknown_section` fields are optional and take their default values when missing.
+    pub fn from_json(text: &str) -> Result<ParameterIO> {
+        parse_json(text, &mut vec![])
+    }
+
+    /// Parses a document from JSON like `from_json`, and adds its string values to the given
+    /// name table as possible names
+    pub fn from_json_with_names(text: &str, names: &mut NameTable) -> Result<ParameterIO> {
+        let mut found = vec![];
+        let pio = parse_json(text, &mut found)?;
+        for name in &found {
+            names.add_name(name);
+        }
+        Ok(pio)
+    }
+
+    /// Converts the document to a plain JSON value without any AAMP type information. Lists and
+    /// objects become JSON objects keyed by the names found in the given table (or the decimal
+    /// hash if a name is not found), scalars become JSON numbers, strings, or booleans, and
+    /// vectors, curves, and buffers become JSON arrays. Non-finite floats become `null`.
+    ///
+    /// This conversion is one-way: the parameter types, and any names not in the table, cannot
+    /// be recovered from the result.
+    pub fn to_plain_json(&self, names: &NameTable) -> Value {
+        let mut root = Map::new();
+        root.insert("version".to_owned(), self.version.into());
+        root.insert("type".to_owned(), self.pio_type.clone().into());
+        root.insert(
+            "param_root".to_owned(),
+            list_to_json(&self.lists, &self.objects, names),
+        );
+        Value::Object(root)
+    }
+}
+
+fn list_to_json(
+    lists: &IndexMap<u32, ParameterList>,
+    objects: &IndexMap<u32, ParameterObject>,
+    names: &NameTable,
+) -> Value {
+    walk_list(
+        lists,
+        objects,
+        ROOT_CRC,
+        &|crc, _, _| names.name_or_crc(crc).into_owned(),
+        &|obj, _| object_to_json(obj, names),
+    )
+}
+
+/// Builds the JSON object for a list, with its objects and lists under `objects` and `lists`,
+/// naming each child with `key(crc, parent, index)` and converting objects with `object`
+fn walk_list(
+    lists: &IndexMap<u32, ParameterList>,
+    objects: &IndexMap<u32, ParameterObject>,
+    crc: u32,
+    key: &dyn Fn(u32, u32, usize) -> String,
+    object: &dyn Fn(&ParameterObject, u32) -> Value,
+) -> Value {
+    let mut list = Map::new();
+    list.insert(
+        "objects".to_owned(),
+        Value::Object(
+            objects
+                .iter()
+                .enumerate()
+                .map(|(i, (subcrc, obj))| (key(*subcrc, crc, i), object(obj, *subcrc)))
+                .collect(),
+        ),
+    );
+    list.insert(
+        "lists".to_owned(),
+        Value::Object(
+            lists
+                .iter()
+                .enumerate()
+                .map(|(i, (subcrc, sublist))| {
+                    (
+                        key(*subcrc, crc, i),
+                        walk_list(&sublist.lists, &sublist.objects, *subcrc, key, object),
+                    )
+                })
+                .collect(),
+        ),
+    );
+    Value::Object(list)
+}
+
+fn object_to_json(obj: &ParameterObject, names: &NameTable) -> Value {
+    Value::Object(
+        obj.params()
+            .iter()
+            .map(|(crc, param)| (names.name_or_crc(*crc).into_owned(), param_to_json(param)))
+            .collect(),
+    )
+}
+
+fn param_to_json(param: &Parameter) -> Value {
+    match param {
+        Parameter::Bool(b) => (*b).into(),
+        Parameter::F32(f) => float_to_json(*f),
+        Parameter::Int(i) => (*i).into(),
+        Parameter::U32(u) => (*u).into(),
+        Parameter::Vec2(v) => floats_to_json(&v.0),
+        Parameter::Vec3(v) => floats_to_json(&v.0),
+        Parameter::Vec4(v) => floats_to_json(&v.0),
+        Parameter::Color(v) => floats_to_json(&v.0),
+        Parameter::Quat(v) => floats_to_json(&v.0),
+        Parameter::String32(s)
+        | Parameter::String64(s)
+        | Parameter::String256(s)
+        | Parameter::StringRef(s) => s.clone().into(),
+        Parameter::Curve1(c) => curves_to_json(&[&c.curve]),
+        Parameter::Curve2(c) => curves_to_json(&[&c.curve1, &c.curve2]),
+        Parameter::Curve3(c) => curves_to_json(&[&c.curve1, &c.curve2, &c.curve3]),
+        Parameter::Curve4(c) => curves_to_json(&[&c.curve1, &c.curve2, &c.curve3, &c.curve4]),
+        Parameter::BufferInt(b) => b.buffer.clone().into(),
+        Parameter::BufferF32(b) => floats_to_json(&b.buffer),
+        Parameter::BufferU32(b) => b.buffer.clone().into(),
+        Parameter::BufferBinary(b) => b.buffer.clone().into(),
+    }
+}
+
+fn float_to_json(float: f32) -> Value {
+    serde_json::Number::from_f64(float as f64).map_or(Value::Null, Value::Number)
+}
+
+fn floats_to_json(floats: &[f32]) -> Value {
+    Value::Array(floats.iter().map(|f| float_to_json(*f)).collect())
+}
+
+/// Writes each curve as an array of its two header values followed by its 30 floats
+fn curves_to_json(curves: &[&Curve]) -> Value {
+    Value::Array(
+        curves
+            .iter()
+            .map(|curve| {
+                let mut values: Vec<Value> = vec![curve.a.into(), curve.b.into()];
+                values.extend(curve.floats.iter().map(|f| float_to_json(*f)));
+                Value::Array(values)
+            })
+            .collect(),
+    )
+}
+
+/// Returns the key for a CRC, falling back to the decimal hash when no name is known. A name made
+/// only of digits would be read back as a hash, so it is written as its hash as well.
+fn json_key(crc: u32, parent: u32, idx: usize, names: &NameTable) -> String {
+    match names
+        .get_name(crc)
+        .or_else(|| names::guess_name_with_table(names, crc, parent, idx))
+    {
+        Some(name) if name.parse::<u32>().is_err() => name,
+        _ => crc.to_string(),
+    }
+}
+
+fn list_to_tagged_json(
+    lists: &IndexMap<u32, ParameterList>,
+    objects: &IndexMap<u32, ParameterObject>,
+    crc: u32,
+    names: &NameTable,
+) -> Value {
+    walk_list(
+        lists,
+        objects,
+        crc,
+        &|subcrc, parent, i| json_key(subcrc, parent, i, names),
+        &|obj, subcrc| object_to_tagged_json(obj, subcrc, names),
+    )
+}
+
+fn object_to_tagged_json(obj: &ParameterObject, crc: u32, names: &NameTable) -> Value {
+    Value::Object(
+        obj.params()
+            .iter()
+            .enumerate()
+            .map(|(i, (subcrc, param))| (json_key(*subcrc, crc, i, names), tagged_param(param)))
+            .collect(),
+    )
+}
+
+fn tagged_param(param: &Parameter) -> Value {
+    let (tag, value): (&str, Value) = match param {
+        Parameter::Bool(b) => ("bool", (*b).into()),
+        Parameter::F32(f) => ("f32", tagged_float(*f)),
+        Parameter::Int(i) => ("int", (*i).into()),
+        Parameter::U32(u) => ("u", (*u).into()),
+        Parameter::Vec2(v) => ("vec2", tagged_floats(&v.0)),
+        Parameter::Vec3(v) => ("vec3", tagged_floats(&v.0)),
+        Parameter::Vec4(v) => ("vec4", tagged_floats(&v.0)),
+        Parameter::Color(v) => ("color", tagged_floats(&v.0)),
+        Parameter::Quat(v) => ("quat", tagged_floats(&v.0)),
+        Parameter::String32(s) => ("str32", s.clone().into()),
+        Parameter::String64(s) => ("str64", s.clone().into()),
+        Parameter::String256(s) => ("str256", s.clone().into()),
+        Parameter::StringRef(s) => ("str", s.clone().into()),
+        Parameter::Curve1(c) => ("curve", tagged_curves(&[&c.curve])),
+        Parameter::Curve2(c) => ("curve", tagged_curves(&[&c.curve1, &c.curve2])),
+        Parameter::Curve3(c) => ("curve", tagged_curves(&[&c.curve1, &c.curve2, &c.curve3])),
+        Parameter::Curve4(c) => (
+            "curve",
+            tagged_curves(&[&c.curve1, &c.curve2, &c.curve3, &c.curve4]),
+        ),
+        Parameter::BufferInt(b) => ("buffer_int", b.buffer.clone().into()),
+        Parameter::BufferF32(b) => ("buffer_f32", tagged_floats(&b.buffer)),
+        Parameter::BufferU32(b) => ("buffer_u32", b.buffer.clone().into()),
+        Parameter::BufferBinary(b) => ("buffer_binary", b.buffer.clone().into()),
+    };
+    let mut tagged = Map::new();
+    tagged.insert("type".to_owned(), tag.into());
+    tagged.insert("value".to_owned(), value);
+    Value::Object(tagged)
+}
+
+/// Writes a float with its shortest round-tripping representation, like the YAML emitter, or as
+/// the YAML spelling of a non-finite value
+fn tagged_float(float: f32) -> Value {
+    if float.is_nan() {
+        ".nan".into()
+    } else if float.is_infinite() {
+        if float > 0.0 { ".inf" } else { "-.inf" }.into()
+    } else {
+        let shortest: f64 = ryu::Buffer::new()
+            .format_finite(float)
+            .parse()
+            .expect("ryu output always parses");
+        Number::from_f64(shortest).map_or(Value::Null, Value::Number)
+    }
+}
+
+fn tagged_floats(floats: &[f32]) -> Value {
+    Value::Array(floats.iter().map(|f| tagged_float(*f)).collect())
+}
+
+/// Writes curves as one flat array like the YAML `!curve` tag: each curve's two header values
+/// followed by its 30 floats
+fn tagged_curves(curves: &[&Curve]) -> Value {
+    let mut values: Vec<Value> = vec![];
+    for curve in curves {
+        values.push(curve.a.into());
+        values.push(curve.b.into());
+        values.extend(curve.floats.iter().map(|f| tagged_float(*f)));
+    }
+    Value::Array(values)
+}
+
+fn parse_json(text: &str, found: &mut Vec<String>) -> Result<ParameterIO> {
+    let root: Value = serde_json::from_str(text)?;
+    let version = match root.get("version").and_then(Value::as_u64) {
+        Some(version) => u32::try_from(version)
+            .map_err(|_| JsonError::InvalidPio(format!("Version {} is too large", version)))?,
+        None => return invalid("Document has no version".to_owned()),
+    };
+    let pio_type = match root.get("type").and_then(Value::as_str) {
+        Some(pio_type) => pio_type.to_owned(),
+        None => return invalid("Document has no type".to_owned()),
+    };
+    let defaults = ParameterIO::default();
+    let flags = match root.get("flags") {
+        Some(flags) => match flags.as_u64().map(u32::try_from) {
+            Some(Ok(flags)) => flags,
+            _ => return invalid("Flags must be a 32-bit integer".to_owned()),
+        },
+        None => defaults.flags,
+    };
+    let unknown_section = match root.get("unknown_section") {
+        Some(section) => {
+            match parse_array(section, |v| v.as_u64().and_then(|b| u8::try_from(b).ok())) {
+                Some(bytes) => bytes,
+                None => return invalid("Unknown section must be an array of bytes".to_owned()),
+            }
+        }
+        None => defaults.unknown_section,
+    };
+    let param_root = match root.get("param_root") {
+        Some(param_root) => parse_list(param_root, found)?,
+        None => return invalid("Document has no param_root".to_owned()),
+    };
+    Ok(ParameterIO {
+        version,
+        flags,
+        pio_type,
+        lists: param_root.lists,
+        objects: param_root.objects,
+        unknown_section,
+        shared_data: defaults.shared_data,
+    })
+}
+
+fn as_map<'a>(value: &'a Value, what: &str) -> Result<&'a Map<String, Value>> {
+    value
+        .as_object()
+        .ok_or_else(|| JsonError::InvalidPio(format!("{} must be a JSON object", what)))
+}
+
+fn key_crc(key: &str) -> u32 {
+    key.parse::<u32>().unwrap_or_else(|_| hash_name(key))
+}
+
+fn parse_list(value: &Value, found: &mut Vec<String>) -> Result<ParameterList> {
+    let list = as_map(value, "List")?;
+    let mut parsed = ParameterList::default();
+    if let Some(objects) = list.get("objects") {
+        for (key, obj) in as_map(objects, "List objects")? {
+            parsed
+                .objects
+                .insert(key_crc(key), parse_object(key, obj, found)?);
+        }
+    }
+    if let Some(lists) = list.get("lists") {
+        for (key, sublist) in as_map(lists, "List lists")? {
+            parsed
+                .lists
+                .insert(key_crc(key), parse_list(sublist, found)?);
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_object(name: &str, value: &Value, found: &mut Vec<String>) -> Result<ParameterObject> {
+    let mut obj = ParameterObject::default();
+    for (key, param) in as_map(value, &format!("Object {}", name))? {
+        obj.set_param(key_crc(key), parse_param(key, param, found)?);
+    }
+    Ok(obj)
+}
+
+fn parse_param(key: &str, tagged: &Value, found: &mut Vec<String>) -> Result<Parameter> {
+    let tagged = as_map(tagged, &format!("Parameter {}", key))?;
+    let tag = match tagged.get("type").and_then(Value::as_str) {
+        Some(tag) => tag,
+        None => return invalid(format!("Parameter {} has no type", key)),
+    };
+    let value = match tagged.get("value") {
+        Some(value) if !value.is_null() => value,
+        _ => return invalid(format!("Parameter {} has no value", key)),
+    };
+    let bad_value = || JsonError::InvalidPio(format!("Parameter {} has an invalid {}", key, tag));
+    let mut string = || -> Result<String> {
+        let s = value.as_str().ok_or_else(bad_value)?.to_owned();
+        found.push(s.clone());
+        Ok(s)
+    };
+    Ok(match tag {
+        "bool" => Parameter::Bool(value.as_bool().ok_or_else(bad_value)?),
+        "f32" => Parameter::F32(parse_json_float(value).ok_or_else(bad_value)?),
+        "int" => Parameter::Int(
+            value
+                .as_i64()
+                .and_then(|i| i32::try_from(i).ok())
+                .ok_or_else(bad_value)?,
+        ),
+        "u" => Parameter::U32(
+            value
+                .as_u64()
+                .and_then(|u| u32::try_from(u).ok())
+                .ok_or_else(bad_value)?,
+        ),
+        "vec2" => Parameter::Vec2(Vec2(parse_float_array(value).ok_or_else(bad_value)?)),
+        "vec3" => Parameter::Vec3(Vec3(parse_float_array(value).ok_or_else(bad_value)?)),
+        "vec4" => Parameter::Vec4(Vec4(parse_float_array(value).ok_or_else(bad_value)?)),
+        "color" => Parameter::Color(Color(parse_float_array(value).ok_or_else(bad_value)?)),
+        "quat" => Parameter::Quat(Quat(parse_float_array(value).ok_or_else(bad_value)?)),
+        "str32" => Parameter::String32(string()?),
+        "str64" => Parameter::String64(string()?),
+        "str256" => Parameter::String256(string()?),
+        "str" => Parameter::StringRef(string()?),
+        "curve" => parse_curves(value).ok_or_else(bad_value)?,
+        "buffer_int" => Parameter::BufferInt(BufferInt {
+            buffer: parse_array(value, |v| v.as_i64().and_then(|i| i32::try_from(i).ok()))
+                .ok_or_else(bad_value)?,
+        }),
+        "buffer_f32" => Parameter::BufferF32(BufferF32 {
+            buffer: parse_array(value, parse_json_float).ok_or_else(bad_value)?,
+        }),
+        "buffer_u32" => Parameter::BufferU32(BufferU32 {
+            buffer: parse_array(value, |v| v.as_u64().and_then(|u| u32::try_from(u).ok()))
+                .ok_or_else(bad_value)?,
+        }),
+        "buffer_binary" => Parameter::BufferBinary(BufferBinary {
+            buffer: parse_array(value, |v| v.as_u64().and_then(|u| u8::try_from(u).ok()))
+                .ok_or_else(bad_value)?,
+        }),
+        _ => return invalid(format!("Parameter {} has unknown type {}", key, tag)),
+    })
+}
+
+fn parse_json_float(value: &Value) -> Option<f32> {
+    match value {
+        Value::Number(num) => num.as_f64().map(|f| f as f32),
+        Value::String(text) => parse_float(text).ok(),
+        _ => None,
+    }
+}
+
+/// Reads every element of a JSON array with the given conversion, failing if any element fails
+fn parse_array<T>(value: &Value, convert: impl Fn(&Value) -> Option<T>) -> Option<Vec<T>> {
+    value.as_array()?.iter().map(convert).collect()
+}
+
+fn parse_float_array<const N: usize>(value: &Value) -> Option<[f32; N]> {
+    let floats = parse_array(value, parse_json_float)?;
+    <[f32; N]>::try_from(floats).ok()
+}
+
+fn parse_curve(values: &[Value]) -> Option<Curve> {
+    let a = u32::try_from(values[0].as_u64()?).ok()?;
+    let b = u32::try_from(values[1].as_u64()?).ok()?;
+    let mut floats = [0.0; 30];
+    for (float, value) in floats.iter_mut().zip(&values[2..]) {
+        *float = parse_json_float(value)?;
+    }
+    Some(Curve { a, b, floats })
+}
+
+fn parse_curves(value: &Value) -> Option<Parameter> {
+    let values = value.as_array()?;
+    Some(match values.len() {
+        32 => Parameter::Curve1(Curve1 {
+            curve: parse_curve(&values[0..32])?,
+        }),
+        64 => Parameter::Curve2(Curve2 {
+            curve1: parse_curve(&values[0..32])?,
+            curve2: parse_curve(&values[32..64])?,
+        }),
+        96 => Parameter::Curve3(Curve3 {
+            curve1: parse_curve(&values[0..32])?,
+            curve2: parse_curve(&values[32..64])?,
+            curve3: parse_curve(&values[64..96])?,
+        }),
+        128 => Parameter::Curve4(Curve4 {
+            curve1: parse_curve(&values[0..32])?,
+            curve2: parse_curve(&values[32..64])?,
+            curve3: parse_curve(&values[64..96])?,
+            curve4: parse_curve(&values[96..128])?,
+        }),
+        _ => return None,
+    })
+}
@@ -1,4 +1,3 @@
-#![feature(seek_stream_len)]
 //! # Nintendo parameter archive (AAMP) library in Rust
 //!
 //! A simple to use library for reading, writing, and converting Nintendo parameter archive (AAMP) files
@@ -19,15 +18,138 @@
 //! // Dumps YAML representation to a String
 //! let yaml_dump: String = pio.to_text().unwrap();
 //! ```
-use crc::{crc32, Hasher32};
 use indexmap::IndexMap;
+use names::NameTable;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ops::{Index, IndexMut};
+#[cfg(feature = "glob")]
+use std::path::{Path, PathBuf};
+mod builder;
+mod diff;
+mod dump;
+#[cfg(feature = "json")]
+mod json;
+mod merge;
 pub mod names;
 mod parse;
+mod patch;
 pub mod types;
+mod validate;
 mod write;
 mod yaml;
+pub use builder::{ListBuilder, ObjectBuilder, ParameterIOBuilder};
+pub use diff::{
+    apply, diff, DiffSummary, ListChange, ListDiff, ObjectChange, ObjectDiff, ParamChange,
+    ParameterIODiff,
+};
+#[cfg(feature = "json")]
+pub use json::JsonError;
+pub use merge::MergePolicy;
+pub use parse::{
+    ListReader, ObjectReader, ParameterIOReader, ParseError, ParseOptions, DEFAULT_MAX_DEPTH,
+};
+pub use patch::PatchError;
+pub use validate::{ValidationIssue, ValidationIssueKind};
+pub use write::{WriteError, WriteOptions};
+pub use yaml::emit::{ToTextOptions, U32Format, WithNames};
+
+/// The version of the AAMP binary format, stored at the start of the header. Only version 2 is
+/// supported for reading and writing.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Header flag bit marking the file as little-endian. Files without it are big-endian.
+pub const FLAG_LITTLE_ENDIAN: u32 = 1 << 0;
+
+/// Header flag bit marking strings as UTF-8 rather than Shift JIS. Strings are always read and
+/// written as UTF-8 regardless.
+pub const FLAG_UTF8: u32 = 1 << 1;
+
+/// The byte order of a binary document. Files from Switch titles are little-endian, while some
+/// files from Wii U titles are big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// The byte order given by the `FLAG_LITTLE_ENDIAN` bit of header flags
+    pub fn from_flags(flags: u32) -> Endian {
+        if flags & FLAG_LITTLE_ENDIAN != 0 {
+            Endian::Little
+        } else {
+            Endian::Big
+        }
+    }
+}
+
+/// Computes the hash used as the key for a parameter, object, or list name. This is the CRC32
+/// (IEEE) checksum of the name's UTF-8 bytes, with no trailing null.
+#[inline]
+pub fn hash_name(name: &str) -> u32 {
+    crc::crc32::checksum_ieee(name.as_bytes())
+}
+
+/// Identifies a list, object, or parameter either by name, which is hashed, or by a hash used as
+/// is for when the original name is unknown. Methods taking `impl Into<Key>` accept a `&str`,
+/// a `&String`, or a `u32` hash directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key<'a> {
+    Name(&'a str),
+    Hash(u32),
+}
+
+impl Key<'_> {
+    /// Returns the hash for the key, hashing it first if it is a name
+    pub fn hash(self) -> u32 {
+        match self {
+            Key::Name(name) => hash_name(name),
+            Key::Hash(crc) => crc,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Key<'a> {
+    fn from(name: &'a str) -> Key<'a> {
+        Key::Name(name)
+    }
+}
+
+impl<'a> From<&&'a str> for Key<'a> {
+    fn from(name: &&'a str) -> Key<'a> {
+        Key::Name(name)
+    }
+}
+
+impl<'a> From<&'a String> for Key<'a> {
+    fn from(name: &'a String) -> Key<'a> {
+        Key::Name(name)
+    }
+}
+
+impl From<u32> for Key<'_> {
+    fn from(crc: u32) -> Self {
+        Key::Hash(crc)
+    }
+}
+
+/// Moves the entry at `from` to the key `to`, keeping its position. Returns false without
+/// changing anything if there is no entry at `from` or another entry already uses `to`.
+fn rename_entry<V>(map: &mut IndexMap<u32, V>, from: u32, to: u32) -> bool {
+    if !map.contains_key(&from) || (from != to && map.contains_key(&to)) {
+        return false;
+    }
+    *map = map
+        .drain(..)
+        .map(|(crc, value)| (if crc == from { to } else { crc }, value))
+        .collect();
+    true
+}
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents a single AAMP parameter
 pub enum Parameter {
     Bool(bool),
@@ -54,6 +176,169 @@ pub enum Parameter {
 }
 
 impl Parameter {
+    /// The type of the parameter as stored in the binary format
+    pub fn param_type(&self) -> types::ParameterType {
+        write::get_param_type(self)
+    }
+
+    /// The name of the parameter's type in snake case, e.g. `vec3` or `buffer_binary`. See
+    /// `ParameterType::name` for how these differ from the YAML tags.
+    pub fn type_name(&self) -> &'static str {
+        self.param_type().name()
+    }
+
+    /// The type byte identifying the parameter's type in the binary format
+    pub fn type_id(&self) -> u8 {
+        self.param_type() as u8
+    }
+
+    /// The number of elements in the value: the length of a buffer or string (in bytes), the
+    /// number of components of a vector, color, or quaternion, and the number of floats in a
+    /// curve (30 per curve). Returns `None` for scalars.
+    pub fn element_count(&self) -> Option<usize> {
+        match self {
+            Parameter::Bool(_) | Parameter::F32(_) | Parameter::Int(_) | Parameter::U32(_) => None,
+            Parameter::Vec2(_) => Some(2),
+            Parameter::Vec3(_) => Some(3),
+            Parameter::Vec4(_) | Parameter::Color(_) | Parameter::Quat(_) => Some(4),
+            Parameter::String32(s)
+            | Parameter::String64(s)
+            | Parameter::String256(s)
+            | Parameter::StringRef(s) => Some(s.len()),
+            Parameter::Curve1(_) => Some(30),
+            Parameter::Curve2(_) => Some(60),
+            Parameter::Curve3(_) => Some(90),
+            Parameter::Curve4(_) => Some(120),
+            Parameter::BufferInt(b) => Some(b.buffer.len()),
+            Parameter::BufferF32(b) => Some(b.buffer.len()),
+            Parameter::BufferU32(b) => Some(b.buffer.len()),
+            Parameter::BufferBinary(b) => Some(b.buffer.len()),
+        }
+    }
+
+    /// Returns the value of a `Bool` parameter, or `None` for any other type
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Parameter::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an `Int` parameter, or `None` for any other type
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Parameter::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `U32` parameter, or `None` for any other type
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Parameter::U32(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an `F32` parameter, or `None` for any other type
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Parameter::F32(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of any of the string parameter types, or `None` for any other type
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Parameter::String32(s)
+            | Parameter::String64(s)
+            | Parameter::String256(s)
+            | Parameter::StringRef(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `Vec2` parameter, or `None` for any other type
+    pub fn as_vec2(&self) -> Option<types::Vec2> {
+        match self {
+            Parameter::Vec2(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `Vec3` parameter, or `None` for any other type
+    pub fn as_vec3(&self) -> Option<types::Vec3> {
+        match self {
+            Parameter::Vec3(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `Vec4` parameter, or `None` for any other type
+    pub fn as_vec4(&self) -> Option<types::Vec4> {
+        match self {
+            Parameter::Vec4(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `Color` parameter, or `None` for any other type
+    pub fn as_color(&self) -> Option<types::Color> {
+        match self {
+            Parameter::Color(c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `Quat` parameter, or `None` for any other type
+    pub fn as_quat(&self) -> Option<types::Quat> {
+        match self {
+            Parameter::Quat(q) => Some(*q),
+            _ => None,
+        }
+    }
+
+    /// Converts a buffer parameter to a `u32` buffer where that can be done exactly. A
+    /// `BufferU32` is copied as-is, a `BufferInt` converts only if none of its values are
+    /// negative, and a `BufferBinary` is read as little-endian `u32`s only if its length is a
+    /// multiple of 4. Returns `None` otherwise, including for `BufferF32` and non-buffer
+    /// parameters.
+    pub fn as_buffer_u32(&self) -> Option<types::BufferU32> {
+        let buffer = match self {
+            Parameter::BufferU32(b) => b.buffer.clone(),
+            Parameter::BufferInt(b) => b
+                .buffer
+                .iter()
+                .map(|i| u32::try_from(*i).ok())
+                .collect::<Option<Vec<u32>>>()?,
+            Parameter::BufferBinary(b) => {
+                let chunks = b.buffer.chunks_exact(4);
+                if !chunks.remainder().is_empty() {
+                    return None;
+                }
+                chunks
+                    .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect()
+            }
+            _ => return None,
+        };
+        Some(types::BufferU32 { buffer })
+    }
+
+    /// Serializes any buffer parameter to its raw little-endian bytes, as stored in the binary
+    /// format. This is always exact. Returns `None` for non-buffer parameters.
+    pub fn to_buffer_binary(&self) -> Option<types::BufferBinary> {
+        let buffer = match self {
+            Parameter::BufferBinary(b) => b.buffer.clone(),
+            Parameter::BufferInt(b) => b.buffer.iter().flat_map(|i| i.to_le_bytes()).collect(),
+            Parameter::BufferU32(b) => b.buffer.iter().flat_map(|u| u.to_le_bytes()).collect(),
+            Parameter::BufferF32(b) => b.buffer.iter().flat_map(|f| f.to_le_bytes()).collect(),
+            _ => return None,
+        };
+        Some(types::BufferBinary { buffer })
+    }
+
     #[inline]
     fn is_string(self: &Parameter) -> bool {
         matches!(
@@ -75,26 +360,234 @@ impl Parameter {
                 | Parameter::BufferU32(_)
         )
     }
+
+    /// Compares two parameters, treating floating point values as equal if they differ by no
+    /// more than `epsilon`. All other values must match exactly.
+    pub fn approx_eq(&self, other: &Parameter, epsilon: f32) -> bool {
+        fn floats_eq(a: &[f32], b: &[f32], epsilon: f32) -> bool {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|(x, y)| x.to_bits() == y.to_bits() || (x - y).abs() <= epsilon)
+        }
+        fn curve_eq(a: &types::Curve, b: &types::Curve, epsilon: f32) -> bool {
+            a.a == b.a && a.b == b.b && floats_eq(&a.floats, &b.floats, epsilon)
+        }
+        match (self, other) {
+            (Parameter::F32(a), Parameter::F32(b)) => floats_eq(&[*a], &[*b], epsilon),
+            (Parameter::Vec2(a), Parameter::Vec2(b)) => floats_eq(&a.0, &b.0, epsilon),
+            (Parameter::Vec3(a), Parameter::Vec3(b)) => floats_eq(&a.0, &b.0, epsilon),
+            (Parameter::Vec4(a), Parameter::Vec4(b)) => floats_eq(&a.0, &b.0, epsilon),
+            (Parameter::Color(a), Parameter::Color(b)) => floats_eq(&a.0, &b.0, epsilon),
+            (Parameter::Quat(a), Parameter::Quat(b)) => floats_eq(&a.0, &b.0, epsilon),
+            (Parameter::Curve1(a), Parameter::Curve1(b)) => curve_eq(&a.curve, &b.curve, epsilon),
+            (Parameter::Curve2(a), Parameter::Curve2(b)) => {
+                curve_eq(&a.curve1, &b.curve1, epsilon) && curve_eq(&a.curve2, &b.curve2, epsilon)
+            }
+            (Parameter::Curve3(a), Parameter::Curve3(b)) => {
+                curve_eq(&a.curve1, &b.curve1, epsilon)
+                    && curve_eq(&a.curve2, &b.curve2, epsilon)
+                    && curve_eq(&a.curve3, &b.curve3, epsilon)
+            }
+            (Parameter::Curve4(a), Parameter::Curve4(b)) => {
+                curve_eq(&a.curve1, &b.curve1, epsilon)
+                    && curve_eq(&a.curve2, &b.curve2, epsilon)
+                    && curve_eq(&a.curve3, &b.curve3, epsilon)
+                    && curve_eq(&a.curve4, &b.curve4, epsilon)
+            }
+            (Parameter::BufferF32(a), Parameter::BufferF32(b)) => {
+                floats_eq(&a.buffer, &b.buffer, epsilon)
+            }
+            _ => self == other,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-/// Represents a single AAMP parameter object, containing a map of parameters by hash
+/// A type that can be read from a parameter, for `ParameterObject::get_as`. Each type matches
+/// only its own parameter variants; for example, an `Int` parameter cannot be read as a `u32`.
+pub trait FromParameter: Sized {
+    fn from_parameter(param: &Parameter) -> Option<Self>;
+}
+
+macro_rules! impl_from_parameter {
+    ($($t:ty => $method:ident),* $(,)?) => {
+        $(
+            impl FromParameter for $t {
+                fn from_parameter(param: &Parameter) -> Option<$t> {
+                    param.$method()
+                }
+            }
+        )*
+    };
+}
+
+impl_from_parameter! {
+    bool => as_bool,
+    i32 => as_int,
+    u32 => as_u32,
+    f32 => as_f32,
+    types::Vec2 => as_vec2,
+    types::Vec3 => as_vec3,
+    types::Vec4 => as_vec4,
+    types::Color => as_color,
+    types::Quat => as_quat,
+}
+
+impl FromParameter for String {
+    fn from_parameter(param: &Parameter) -> Option<String> {
+        param.as_str().map(|s| s.to_owned())
+    }
+}
+
+/// The error returned when converting a parameter to a Rust type that does not match its type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Parameter of type {} cannot be converted to {expected}", .found.name())]
+pub struct WrongParameterType {
+    pub expected: &'static str,
+    pub found: types::ParameterType,
+}
+
+macro_rules! impl_try_from_param {
+    ($($t:ty => $name:literal),* $(,)?) => {
+        $(
+            impl TryFrom<&Parameter> for $t {
+                type Error = WrongParameterType;
+
+                fn try_from(param: &Parameter) -> Result<$t, WrongParameterType> {
+                    <$t>::from_parameter(param).ok_or(WrongParameterType {
+                        expected: $name,
+                        found: param.param_type(),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_param! {
+    bool => "bool",
+    i32 => "i32",
+    u32 => "u32",
+    f32 => "f32",
+    String => "String",
+    types::Vec2 => "Vec2",
+    types::Vec3 => "Vec3",
+    types::Vec4 => "Vec4",
+    types::Color => "Color",
+    types::Quat => "Quat",
+}
+
+impl<'a> TryFrom<&'a Parameter> for &'a str {
+    type Error = WrongParameterType;
+
+    fn try_from(param: &'a Parameter) -> Result<&'a str, WrongParameterType> {
+        param.as_str().ok_or(WrongParameterType {
+            expected: "str",
+            found: param.param_type(),
+        })
+    }
+}
+
+macro_rules! impl_from_for_param {
+    ($($t:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$t> for Parameter {
+                fn from(value: $t) -> Parameter {
+                    Parameter::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_for_param! {
+    bool => Bool,
+    f32 => F32,
+    i32 => Int,
+    u32 => U32,
+    String => StringRef,
+    types::Vec2 => Vec2,
+    types::Vec3 => Vec3,
+    types::Vec4 => Vec4,
+    types::Color => Color,
+    types::Quat => Quat,
+    types::Curve1 => Curve1,
+    types::Curve2 => Curve2,
+    types::Curve3 => Curve3,
+    types::Curve4 => Curve4,
+    types::BufferInt => BufferInt,
+    types::BufferF32 => BufferF32,
+    types::BufferU32 => BufferU32,
+    types::BufferBinary => BufferBinary,
+}
+
+impl From<[f32; 2]> for Parameter {
+    fn from(value: [f32; 2]) -> Parameter {
+        Parameter::Vec2(types::Vec2(value))
+    }
+}
+
+impl From<[f32; 3]> for Parameter {
+    fn from(value: [f32; 3]) -> Parameter {
+        Parameter::Vec3(types::Vec3(value))
+    }
+}
+
+impl From<[f32; 4]> for Parameter {
+    /// Converts four floats into a `Vec4` parameter. Use `types::Color` or `types::Quat` for the
+    /// other four-component types.
+    fn from(value: [f32; 4]) -> Parameter {
+        Parameter::Vec4(types::Vec4(value))
+    }
+}
+
+impl From<&str> for Parameter {
+    /// Converts a string slice into a `StringRef` parameter, the variant without a length limit
+    fn from(value: &str) -> Parameter {
+        Parameter::StringRef(value.to_owned())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Represents a single AAMP parameter object, containing a map of parameters by hash. Equality
+/// with `==` ignores the order of the parameters; use `eq_ordered` to compare it too.
 pub struct ParameterObject(IndexMap<u32, Parameter>);
 
 impl ParameterObject {
-    /// Attempt to get a `Parameter` by name, returns None if not found
-    pub fn param(&self, name: &str) -> Option<&Parameter> {
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(name.as_bytes());
-        self.0.get(&digest.sum32())
+    /// Attempt to get a `Parameter` by name or hash, returns None if not found
+    pub fn param<'k>(&self, key: impl Into<Key<'k>>) -> Option<&Parameter> {
+        self.0.get(&key.into().hash())
+    }
+
+    /// Returns whether the object has a parameter with the given name or hash
+    pub fn contains<'k>(&self, key: impl Into<Key<'k>>) -> bool {
+        self.0.contains_key(&key.into().hash())
+    }
+
+    /// Gets a parameter by name or hash as the given type, returns None if not found or if the
+    /// parameter has a different type
+    pub fn get_as<'k, T: FromParameter>(&self, key: impl Into<Key<'k>>) -> Option<T> {
+        self.param(key).and_then(T::from_parameter)
+    }
+
+    /// Sets a parameter value by name or hash
+    pub fn set_param<'k>(&mut self, key: impl Into<Key<'k>>, value: Parameter) {
+        self.0.insert(key.into().hash(), value);
+    }
+
+    /// Removes a parameter by name or hash, preserving the order of the remaining parameters.
+    /// Returns the removed value, or None if not found
+    pub fn remove_param<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<Parameter> {
+        self.0.shift_remove(&key.into().hash())
     }
 
-    /// Sets a parameter value
-    pub fn set_param(&mut self, name: &str, value: Parameter) {
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(name.as_bytes());
-        self.0.insert(digest.sum32(), value);
+    /// Renames a parameter, keeping its position. Returns false without changing anything if
+    /// there is no parameter at `from` or another parameter already uses `to`.
+    pub fn rename_param<'k>(&mut self, from: impl Into<Key<'k>>, to: impl Into<Key<'k>>) -> bool {
+        rename_entry(&mut self.0, from.into().hash(), to.into().hash())
     }
+
     /// Expose reference to underlying IndexMap
     pub fn params(&self) -> &IndexMap<u32, Parameter> {
         &self.0
@@ -104,101 +597,682 @@ impl ParameterObject {
     pub fn params_mut(&mut self) -> &mut IndexMap<u32, Parameter> {
         &mut self.0
     }
+
+    /// Attempt to get the hash and value of the parameter at the given position, returns None if
+    /// out of bounds
+    pub fn param_at_index(&self, index: usize) -> Option<(u32, &Parameter)> {
+        self.0.get_index(index).map(|(crc, param)| (*crc, param))
+    }
+
+    /// Iterates over the parameters with their names looked up in the given table. Parameters
+    /// whose names are not in the table are given their CRC as a decimal string.
+    pub fn iter_named<'a>(
+        &'a self,
+        names: &'a NameTable,
+    ) -> impl Iterator<Item = (Cow<'a, str>, &'a Parameter)> {
+        self.0
+            .iter()
+            .map(move |(crc, param)| (names.name_or_crc(*crc), param))
+    }
+
+    /// Compares two objects, treating floating point values as equal if they differ by no more
+    /// than `epsilon`
+    pub fn approx_eq(&self, other: &ParameterObject, epsilon: f32) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().all(|(crc, param)| match other.0.get(crc) {
+                Some(other_param) => param.approx_eq(other_param, epsilon),
+                None => false,
+            })
+    }
+
+    /// Compares two objects including the order of their parameters. `==` ignores the order,
+    /// but it determines the binary output, so two objects that are `==` but not `eq_ordered`
+    /// serialize to different bytes.
+    pub fn eq_ordered(&self, other: &ParameterObject) -> bool {
+        maps_eq_ordered(&self.0, &other.0, |a, b| a == b)
+    }
+}
+
+impl<'k, K: Into<Key<'k>>> Index<K> for ParameterObject {
+    type Output = Parameter;
+
+    /// Gets a parameter by name or hash. Panics if the object has no such parameter.
+    fn index(&self, key: K) -> &Parameter {
+        let key = key.into();
+        self.param(key).unwrap_or_else(|| missing_param(key))
+    }
+}
+
+impl<'k, K: Into<Key<'k>>> IndexMut<K> for ParameterObject {
+    /// Gets a mutable reference to a parameter by name or hash. Panics if the object has no such
+    /// parameter.
+    fn index_mut(&mut self, key: K) -> &mut Parameter {
+        let key = key.into();
+        self.0
+            .get_mut(&key.hash())
+            .unwrap_or_else(|| missing_param(key))
+    }
+}
+
+fn missing_param(key: Key) -> ! {
+    match key {
+        Key::Name(name) => panic!("No parameter named {:?} in object", name),
+        Key::Hash(crc) => panic!("No parameter with hash {} in object", crc),
+    }
 }
 
 /// Represents a single AAMP parameter list, containing a hash map of parameter objects and
-/// child parameter lists
-#[derive(Debug, PartialEq, Clone)]
+/// child parameter lists. Equality with `==` ignores the order of entries; use `eq_ordered` to
+/// compare it too.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParameterList {
     pub lists: IndexMap<u32, ParameterList>,
     pub objects: IndexMap<u32, ParameterObject>,
 }
 
 impl ParameterList {
-    /// Attempt to get a `ParameterList` by name, returns None if not found
-    pub fn list(&self, name: &str) -> Option<&ParameterList> {
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(name.as_bytes());
-        self.lists.get(&digest.sum32())
+    /// Attempt to get a `ParameterList` by name or hash, returns None if not found
+    pub fn list<'k>(&self, key: impl Into<Key<'k>>) -> Option<&ParameterList> {
+        self.lists.get(&key.into().hash())
+    }
+
+    /// Attempt to get a `ParameterObject` by name or hash, returns None if not found
+    pub fn object<'k>(&self, key: impl Into<Key<'k>>) -> Option<&ParameterObject> {
+        self.objects.get(&key.into().hash())
+    }
+
+    /// Returns whether there is a list with the given name or hash
+    pub fn has_list<'k>(&self, key: impl Into<Key<'k>>) -> bool {
+        self.lists.contains_key(&key.into().hash())
+    }
+
+    /// Returns whether there is an object with the given name or hash
+    pub fn has_object<'k>(&self, key: impl Into<Key<'k>>) -> bool {
+        self.objects.contains_key(&key.into().hash())
+    }
+
+    /// Attempt to get a mutable reference to a `ParameterList` by name or hash, returns None if
+    /// not found
+    pub fn list_mut<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<&mut ParameterList> {
+        self.lists.get_mut(&key.into().hash())
+    }
+
+    /// Attempt to get a mutable reference to a `ParameterObject` by name or hash, returns None
+    /// if not found
+    pub fn object_mut<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<&mut ParameterObject> {
+        self.objects.get_mut(&key.into().hash())
+    }
+
+    /// Removes a `ParameterList` by name or hash, preserving the order of the remaining lists.
+    /// Returns the removed list, or None if not found
+    pub fn remove_list<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<ParameterList> {
+        self.lists.shift_remove(&key.into().hash())
+    }
+
+    /// Removes a `ParameterObject` by name or hash, preserving the order of the remaining
+    /// objects. Returns the removed object, or None if not found
+    pub fn remove_object<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<ParameterObject> {
+        self.objects.shift_remove(&key.into().hash())
+    }
+
+    /// Sets a `ParameterList` by name or hash
+    pub fn set_list<'k>(&mut self, key: impl Into<Key<'k>>, list: ParameterList) {
+        self.lists.insert(key.into().hash(), list);
+    }
+
+    /// Sets a `ParameterObject` by name or hash
+    pub fn set_object<'k>(&mut self, key: impl Into<Key<'k>>, obj: ParameterObject) {
+        self.objects.insert(key.into().hash(), obj);
+    }
+
+    /// Renames a `ParameterList`, keeping its position. Returns false without changing anything
+    /// if there is no list at `from` or another list already uses `to`.
+    pub fn rename_list<'k>(&mut self, from: impl Into<Key<'k>>, to: impl Into<Key<'k>>) -> bool {
+        rename_entry(&mut self.lists, from.into().hash(), to.into().hash())
+    }
+
+    /// Renames a `ParameterObject`, keeping its position. Returns false without changing
+    /// anything if there is no object at `from` or another object already uses `to`.
+    pub fn rename_object<'k>(&mut self, from: impl Into<Key<'k>>, to: impl Into<Key<'k>>) -> bool {
+        rename_entry(&mut self.objects, from.into().hash(), to.into().hash())
+    }
+
+    /// Attempt to get the hash and `ParameterList` at the given position, returns None if out of
+    /// bounds
+    pub fn list_at_index(&self, index: usize) -> Option<(u32, &ParameterList)> {
+        self.lists.get_index(index).map(|(crc, list)| (*crc, list))
+    }
+
+    /// Attempt to get the hash and `ParameterObject` at the given position, returns None if out
+    /// of bounds
+    pub fn object_at_index(&self, index: usize) -> Option<(u32, &ParameterObject)> {
+        self.objects.get_index(index).map(|(crc, obj)| (*crc, obj))
+    }
+
+    /// Iterates over the child lists with their names looked up in the given table. Lists whose
+    /// names are not in the table are given their CRC as a decimal string.
+    pub fn lists_named<'a>(
+        &'a self,
+        names: &'a NameTable,
+    ) -> impl Iterator<Item = (Cow<'a, str>, &'a ParameterList)> {
+        self.lists
+            .iter()
+            .map(move |(crc, list)| (names.name_or_crc(*crc), list))
+    }
+
+    /// Iterates over the objects with their names looked up in the given table. Objects whose
+    /// names are not in the table are given their CRC as a decimal string.
+    pub fn objects_named<'a>(
+        &'a self,
+        names: &'a NameTable,
+    ) -> impl Iterator<Item = (Cow<'a, str>, &'a ParameterObject)> {
+        self.objects
+            .iter()
+            .map(move |(crc, obj)| (names.name_or_crc(*crc), obj))
+    }
+
+    /// Compares two lists recursively, treating floating point values as equal if they differ by
+    /// no more than `epsilon`
+    pub fn approx_eq(&self, other: &ParameterList, epsilon: f32) -> bool {
+        lists_approx_eq(&self.lists, &other.lists, epsilon)
+            && objects_approx_eq(&self.objects, &other.objects, epsilon)
     }
 
-    /// Attempt to get a `ParameterObject` by name, returns None if not found
-    pub fn object(&self, name: &str) -> Option<&ParameterObject> {
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(name.as_bytes());
-        self.objects.get(&digest.sum32())
+    /// Compares two lists recursively including the order of their lists, objects, and
+    /// parameters. `==` ignores the order, but it determines the binary output.
+    pub fn eq_ordered(&self, other: &ParameterList) -> bool {
+        maps_eq_ordered(&self.lists, &other.lists, ParameterList::eq_ordered)
+            && maps_eq_ordered(&self.objects, &other.objects, ParameterObject::eq_ordered)
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-/// Represents a single AAMP parameter IO document
+/// Compares two maps entry by entry in order, using `eq` to compare values
+fn maps_eq_ordered<T>(
+    map: &IndexMap<u32, T>,
+    other: &IndexMap<u32, T>,
+    eq: impl Fn(&T, &T) -> bool,
+) -> bool {
+    map.len() == other.len()
+        && map
+            .iter()
+            .zip(other.iter())
+            .all(|((crc, value), (other_crc, other_value))| {
+                crc == other_crc && eq(value, other_value)
+            })
+}
+
+fn lists_approx_eq(
+    lists: &IndexMap<u32, ParameterList>,
+    other: &IndexMap<u32, ParameterList>,
+    epsilon: f32,
+) -> bool {
+    lists.len() == other.len()
+        && lists.iter().all(|(crc, list)| match other.get(crc) {
+            Some(other_list) => list.approx_eq(other_list, epsilon),
+            None => false,
+        })
+}
+
+fn objects_approx_eq(
+    objects: &IndexMap<u32, ParameterObject>,
+    other: &IndexMap<u32, ParameterObject>,
+    epsilon: f32,
+) -> bool {
+    objects.len() == other.len()
+        && objects.iter().all(|(crc, obj)| match other.get(crc) {
+            Some(other_obj) => obj.approx_eq(other_obj, epsilon),
+            None => false,
+        })
+}
+
+/// Walks the objects of a document depth first without collecting them, yielding each object in
+/// a list before descending into the list's child lists
+struct ObjectsRecursive<'a> {
+    path: Vec<u32>,
+    objects: indexmap::map::Iter<'a, u32, ParameterObject>,
+    lists: Vec<indexmap::map::Iter<'a, u32, ParameterList>>,
+}
+
+impl<'a> ObjectsRecursive<'a> {
+    fn new(
+        lists: &'a IndexMap<u32, ParameterList>,
+        objects: &'a IndexMap<u32, ParameterObject>,
+    ) -> ObjectsRecursive<'a> {
+        ObjectsRecursive {
+            path: vec![],
+            objects: objects.iter(),
+            lists: vec![lists.iter()],
+        }
+    }
+}
+
+impl<'a> Iterator for ObjectsRecursive<'a> {
+    type Item = (Vec<u32>, u32, &'a ParameterObject);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((crc, obj)) = self.objects.next() {
+                return Some((self.path.clone(), *crc, obj));
+            }
+            match self.lists.last_mut()?.next() {
+                Some((crc, list)) => {
+                    self.path.push(*crc);
+                    self.objects = list.objects.iter();
+                    self.lists.push(list.lists.iter());
+                }
+                None => {
+                    self.lists.pop();
+                    self.path.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Walks the parameters of every object yielded by `ObjectsRecursive`
+struct ParamsRecursive<'a> {
+    objects: ObjectsRecursive<'a>,
+    current: Option<(Vec<u32>, indexmap::map::Iter<'a, u32, Parameter>)>,
+}
+
+impl<'a> Iterator for ParamsRecursive<'a> {
+    type Item = (Vec<u32>, u32, &'a Parameter);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((path, params)) = &mut self.current {
+                if let Some((crc, param)) = params.next() {
+                    return Some((path.clone(), *crc, param));
+                }
+            }
+            let (mut path, crc, obj) = self.objects.next()?;
+            path.push(crc);
+            self.current = Some((path, obj.0.iter()));
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Represents a single AAMP parameter IO document. Equality with `==` ignores the order of
+/// lists, objects, and parameters, though the binary output depends on it; use `eq_ordered` to
+/// compare the order too.
 pub struct ParameterIO {
-    /// The parameter IO version, required by the format but of no functional importance
+    /// The document's own version, stored in the header as the parameter IO version. This is
+    /// separate from the version of the binary format itself, which is always `FORMAT_VERSION`.
+    /// It has no functional importance and is written back unchanged.
     pub version: u32,
+    /// The flags in the header. Bit 0 (`FLAG_LITTLE_ENDIAN`) marks the file as little-endian,
+    /// and bit 1 (`FLAG_UTF8`) marks strings as UTF-8. No other bits are known to be used. Files
+    /// from Switch titles set both, which is also the default. The value is written back
+    /// unchanged, and its byte order bit decides the byte order of the output unless
+    /// `WriteOptions::endian` overrides it. It is not part of the YAML representation.
+    #[cfg_attr(feature = "serde", serde(default = "default_flags"))]
+    pub flags: u32,
     /// The parameter IO type, required by the format but of no functional importance
     pub pio_type: String,
     /// The lists in the parameter IO root list (`param_root`)
     pub lists: IndexMap<u32, ParameterList>,
     /// The objects in the parameter IO root list (`param_root`)
     pub objects: IndexMap<u32, ParameterObject>,
+    /// The raw contents of the reserved section following the string section, whose size is
+    /// given by the last field of the header. Its purpose is unknown, and in files from the game
+    /// it is a single null byte. It is kept so that it can be written back unchanged, and
+    /// defaults to a single null byte for new documents.
+    #[cfg_attr(feature = "serde", serde(default = "default_unknown_section"))]
+    pub unknown_section: Vec<u8>,
+    /// Parameters that shared their data with an earlier parameter in the parsed file, as a map
+    /// from the index of each parameter in write order to the index of the parameter whose data
+    /// it points at. Only filled in when parsing with `ParseOptions::preserve_layout` and only
+    /// used when writing with `WriteOptions::preserve_layout`, and ignored when comparing
+    /// documents.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub shared_data: IndexMap<usize, usize>,
 }
 
-impl ParameterIO {
-    /// Attempt to get a `ParameterList` by name, returns None if not found
-    pub fn list(&self, name: &str) -> Option<&ParameterList> {
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(name.as_bytes());
-        self.lists.get(&digest.sum32())
+#[cfg(feature = "serde")]
+fn default_flags() -> u32 {
+    ParameterIO::default().flags
+}
+
+#[cfg(feature = "serde")]
+fn default_unknown_section() -> Vec<u8> {
+    ParameterIO::default().unknown_section
+}
+
+impl Default for ParameterIO {
+    fn default() -> ParameterIO {
+        ParameterIO {
+            version: 0,
+            flags: FLAG_LITTLE_ENDIAN | FLAG_UTF8,
+            pio_type: "xml".to_owned(),
+            lists: IndexMap::new(),
+            objects: IndexMap::new(),
+            unknown_section: vec![0],
+            shared_data: IndexMap::new(),
+        }
     }
+}
 
-    /// Attempt to get a `ParameterObject` by name, returns None if not found
-    pub fn object(&self, name: &str) -> Option<&ParameterObject> {
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(name.as_bytes());
-        self.objects.get(&digest.sum32())
+impl PartialEq for ParameterIO {
+    fn eq(&self, other: &ParameterIO) -> bool {
+        self.version == other.version
+            && self.flags == other.flags
+            && self.pio_type == other.pio_type
+            && self.lists == other.lists
+            && self.objects == other.objects
+            && self.unknown_section == other.unknown_section
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::ParameterIO;
-    use glob::glob;
-    use std::fs::File;
-    use std::path::PathBuf;
+impl ParameterIO {
+    /// Attempt to get a `ParameterList` by name or hash, returns None if not found
+    pub fn list<'k>(&self, key: impl Into<Key<'k>>) -> Option<&ParameterList> {
+        self.lists.get(&key.into().hash())
+    }
 
-    #[test]
-    fn binary_roundtrip() {
-        for file in glob("test/**/*.b*").unwrap() {
-            let good_file: PathBuf = file.unwrap();
-            let mut reader = File::open(&good_file).unwrap();
-            let pio: ParameterIO = ParameterIO::from_binary(&mut reader).unwrap();
-            let mut cur: std::io::Cursor<Vec<u8>> =
-                std::io::Cursor::new(pio.clone().to_binary().unwrap());
-            let pio2: ParameterIO = ParameterIO::from_binary(&mut cur).unwrap();
-            assert_eq!(pio, pio2);
-        }
+    /// Attempt to get a `ParameterObject` by name or hash, returns None if not found
+    pub fn object<'k>(&self, key: impl Into<Key<'k>>) -> Option<&ParameterObject> {
+        self.objects.get(&key.into().hash())
     }
 
-    #[test]
-    fn dump_yaml() {
-        for file in glob("test/*.b*").unwrap() {
-            let good_file: PathBuf = file.unwrap();
-            let mut reader = File::open(&good_file).unwrap();
-            let pio: ParameterIO = ParameterIO::from_binary(&mut reader).unwrap();
-            pio.to_text().unwrap();
-        }
+    /// Returns whether there is a list with the given name or hash
+    pub fn has_list<'k>(&self, key: impl Into<Key<'k>>) -> bool {
+        self.lists.contains_key(&key.into().hash())
     }
 
-    #[test]
-    fn yaml_roundtrip() {
-        for file in glob("test/*.b*").unwrap() {
-            let good_file: PathBuf = file.unwrap();
-            let mut reader = File::open(&good_file).unwrap();
-            let pio: ParameterIO = ParameterIO::from_binary(&mut reader).unwrap();
-            let new_text = pio.clone().to_text().unwrap();
-            let new_pio = ParameterIO::from_text(&new_text).unwrap();
-            if pio != new_pio {
-                panic!(
+    /// Returns whether there is an object with the given name or hash
+    pub fn has_object<'k>(&self, key: impl Into<Key<'k>>) -> bool {
+        self.objects.contains_key(&key.into().hash())
+    }
+
+    /// Attempt to get a mutable reference to a `ParameterList` by name or hash, returns None if
+    /// not found
+    pub fn list_mut<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<&mut ParameterList> {
+        self.lists.get_mut(&key.into().hash())
+    }
+
+    /// Attempt to get a mutable reference to a `ParameterObject` by name or hash, returns None
+    /// if not found
+    pub fn object_mut<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<&mut ParameterObject> {
+        self.objects.get_mut(&key.into().hash())
+    }
+
+    /// Removes a `ParameterList` by name or hash, preserving the order of the remaining lists.
+    /// Returns the removed list, or None if not found
+    pub fn remove_list<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<ParameterList> {
+        self.lists.shift_remove(&key.into().hash())
+    }
+
+    /// Removes a `ParameterObject` by name or hash, preserving the order of the remaining
+    /// objects. Returns the removed object, or None if not found
+    pub fn remove_object<'k>(&mut self, key: impl Into<Key<'k>>) -> Option<ParameterObject> {
+        self.objects.shift_remove(&key.into().hash())
+    }
+
+    /// Sets a `ParameterList` by name or hash
+    pub fn set_list<'k>(&mut self, key: impl Into<Key<'k>>, list: ParameterList) {
+        self.lists.insert(key.into().hash(), list);
+    }
+
+    /// Sets a `ParameterObject` by name or hash
+    pub fn set_object<'k>(&mut self, key: impl Into<Key<'k>>, obj: ParameterObject) {
+        self.objects.insert(key.into().hash(), obj);
+    }
+
+    /// Renames a `ParameterList`, keeping its position. Returns false without changing anything
+    /// if there is no list at `from` or another list already uses `to`.
+    pub fn rename_list<'k>(&mut self, from: impl Into<Key<'k>>, to: impl Into<Key<'k>>) -> bool {
+        rename_entry(&mut self.lists, from.into().hash(), to.into().hash())
+    }
+
+    /// Renames a `ParameterObject`, keeping its position. Returns false without changing
+    /// anything if there is no object at `from` or another object already uses `to`.
+    pub fn rename_object<'k>(&mut self, from: impl Into<Key<'k>>, to: impl Into<Key<'k>>) -> bool {
+        rename_entry(&mut self.objects, from.into().hash(), to.into().hash())
+    }
+
+    /// Attempt to get the hash and `ParameterList` at the given position, returns None if out of
+    /// bounds
+    pub fn list_at_index(&self, index: usize) -> Option<(u32, &ParameterList)> {
+        self.lists.get_index(index).map(|(crc, list)| (*crc, list))
+    }
+
+    /// Attempt to get the hash and `ParameterObject` at the given position, returns None if out
+    /// of bounds
+    pub fn object_at_index(&self, index: usize) -> Option<(u32, &ParameterObject)> {
+        self.objects.get_index(index).map(|(crc, obj)| (*crc, obj))
+    }
+
+    /// Returns the root object with the given name if it is the only thing in the document: the
+    /// root list has exactly one object and no lists. Otherwise returns None.
+    pub fn single_object<'k>(&self, key: impl Into<Key<'k>>) -> Option<&ParameterObject> {
+        if self.lists.is_empty() && self.objects.len() == 1 {
+            self.object(key)
+        } else {
+            None
+        }
+    }
+
+    /// Converts a document holding a single root object, and no lists, into that object. Returns
+    /// the document unchanged as the error if it holds anything else.
+    pub fn try_into_single_object(mut self) -> Result<ParameterObject, Box<ParameterIO>> {
+        if self.lists.is_empty() && self.objects.len() == 1 {
+            Ok(self.objects.pop().map(|(_, obj)| obj).unwrap())
+        } else {
+            Err(Box::new(self))
+        }
+    }
+
+    /// Returns the total number of lists in the document at any depth, not counting the root
+    /// list itself
+    pub fn num_lists(&self) -> usize {
+        write::count_lists(&self.lists)
+    }
+
+    /// Returns the total number of objects in the document at any depth
+    pub fn num_objects(&self) -> usize {
+        write::count_objs(&self.lists, self.objects.len())
+    }
+
+    /// Returns the total number of parameters in the document at any depth
+    pub fn num_parameters(&self) -> usize {
+        write::count_params(&self.lists, &self.objects)
+    }
+
+    /// Returns the total number of lists, objects, and parameters in the document, not counting
+    /// the root list itself
+    pub fn node_count(&self) -> usize {
+        self.num_lists() + self.num_objects() + self.num_parameters()
+    }
+
+    /// Iterates over every parameter in the document, descending through nested lists. Each item
+    /// holds the hashes of the lists and object leading to the parameter (starting below the root
+    /// list), the parameter's own hash, and its value.
+    pub fn iter_params(&self) -> impl Iterator<Item = (Vec<u32>, u32, &Parameter)> {
+        ParamsRecursive {
+            objects: ObjectsRecursive::new(&self.lists, &self.objects),
+            current: None,
+        }
+    }
+
+    /// Iterates over every object in the document, descending through nested lists. Each item
+    /// holds the hashes of the lists leading to the object (starting below the root list), the
+    /// object's own hash, and the object.
+    pub fn objects_recursive(&self) -> impl Iterator<Item = (Vec<u32>, u32, &ParameterObject)> {
+        ObjectsRecursive::new(&self.lists, &self.objects)
+    }
+
+    /// Compares two parameter IO documents recursively, treating floating point values as equal
+    /// if they differ by no more than `epsilon`. Useful for comparing against a copy that went
+    /// through a lossy conversion.
+    pub fn approx_eq(&self, other: &ParameterIO, epsilon: f32) -> bool {
+        self.version == other.version
+            && self.flags == other.flags
+            && self.pio_type == other.pio_type
+            && self.unknown_section == other.unknown_section
+            && lists_approx_eq(&self.lists, &other.lists, epsilon)
+            && objects_approx_eq(&self.objects, &other.objects, epsilon)
+    }
+
+    /// Compares two documents recursively including the order of their lists, objects, and
+    /// parameters. `==` ignores the order, but the binary writer emits entries in map order, so
+    /// only documents that are `eq_ordered` are guaranteed to serialize to the same bytes.
+    pub fn eq_ordered(&self, other: &ParameterIO) -> bool {
+        self == other
+            && maps_eq_ordered(&self.lists, &other.lists, ParameterList::eq_ordered)
+            && maps_eq_ordered(&self.objects, &other.objects, ParameterObject::eq_ordered)
+    }
+}
+
+/// Counts how many of the given documents have each parameter IO type, e.g. `xml`
+pub fn summarize_types(pios: &[ParameterIO]) -> BTreeMap<String, usize> {
+    let mut types = BTreeMap::new();
+    for pio in pios {
+        *types.entry(pio.pio_type.clone()).or_insert(0) += 1;
+    }
+    types
+}
+
+/// Finds all files in a directory matching a glob pattern (e.g. `"**/*.bxml"`) and parses each
+/// as a binary parameter IO document. Returns each path with its parse result, or an error if
+/// the pattern is invalid. Requires the `glob` feature.
+#[cfg(feature = "glob")]
+#[allow(clippy::type_complexity)]
+pub fn load_dir<P: AsRef<Path>>(
+    dir: P,
+    glob_pattern: &str,
+) -> Result<Vec<(PathBuf, Result<ParameterIO, ParseError>)>, glob::PatternError> {
+    let pattern = dir.as_ref().join(glob_pattern);
+    Ok(glob::glob(&pattern.to_string_lossy())?
+        .map(|entry| match entry {
+            Ok(path) => {
+                let result = std::fs::File::open(&path)
+                    .map_err(ParseError::from)
+                    .and_then(|file| ParameterIO::from_binary(&mut std::io::BufReader::new(file)));
+                (path, result)
+            }
+            Err(err) => {
+                let io_err = std::io::Error::new(err.error().kind(), err.to_string());
+                (err.path().to_owned(), Err(io_err.into()))
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        hash_name, types, DiffSummary, Parameter, ParameterIO, ParameterIOBuilder,
+        ParameterIOReader, ParameterList, ParameterObject, ParseError, ParseOptions, WriteError,
+        WriteOptions,
+    };
+    use super::{ToTextOptions, U32Format};
+    use glob::glob;
+    use indexmap::IndexMap;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    /// The stock name table, for converting to text without relying on the global one
+    fn stock_names() -> crate::names::NameTable {
+        crate::names::get_default_name_table()
+    }
+
+    fn sample_pio() -> ParameterIO {
+        let mut obj = ParameterObject(IndexMap::new());
+        obj.set_param("Life", Parameter::Int(100));
+        obj.set_param("Scale", Parameter::F32(1.5));
+        obj.set_param("Position", Parameter::Vec3(types::Vec3([1.0, 2.0, 3.0])));
+        obj.set_param("Name", Parameter::StringRef("Enemy_Lizalfos".to_owned()));
+        obj.set_param(
+            "Weights",
+            Parameter::BufferF32(types::BufferF32 {
+                buffer: vec![0.25, 0.5, 0.75],
+            }),
+        );
+        let mut child = ParameterList {
+            lists: IndexMap::new(),
+            objects: IndexMap::new(),
+        };
+        child.objects.insert(hash_name("Param"), obj.clone());
+        let mut lists = IndexMap::new();
+        lists.insert(hash_name("Child"), child);
+        let mut objects = IndexMap::new();
+        objects.insert(hash_name("General"), obj);
+        ParameterIO {
+            version: 0,
+            flags: 3,
+            pio_type: "xml".to_owned(),
+            lists,
+            objects,
+            unknown_section: vec![0],
+            shared_data: IndexMap::new(),
+        }
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        for file in glob("test/**/*.b*").unwrap() {
+            let good_file: PathBuf = file.unwrap();
+            let mut reader = File::open(&good_file).unwrap();
+            let pio: ParameterIO = ParameterIO::from_binary(&mut reader).unwrap();
+            let mut cur: std::io::Cursor<Vec<u8>> =
+                std::io::Cursor::new(pio.clone().to_binary().unwrap());
+            let pio2: ParameterIO = ParameterIO::from_binary(&mut cur).unwrap();
+            assert_eq!(pio, pio2);
+        }
+    }
+
+    #[cfg(feature = "global-names")]
+    #[test]
+    fn dump_yaml() {
+        for file in glob("test/*.b*").unwrap() {
+            let good_file: PathBuf = file.unwrap();
+            let mut reader = File::open(&good_file).unwrap();
+            let pio: ParameterIO = ParameterIO::from_binary(&mut reader).unwrap();
+            pio.to_text().unwrap();
+        }
+    }
+
+    #[test]
+    fn dump_yaml_with_names() {
+        for file in glob("test/*.b*").unwrap() {
+            let good_file: PathBuf = file.unwrap();
+            let mut reader = File::open(&good_file).unwrap();
+            let pio: ParameterIO = ParameterIO::from_binary(&mut reader).unwrap();
+            pio.with_names(&stock_names()).to_text().unwrap();
+        }
+    }
+
+    #[cfg(feature = "global-names")]
+    #[test]
+    fn yaml_roundtrip() {
+        for file in glob("test/*.b*").unwrap() {
+            let good_file: PathBuf = file.unwrap();
+            let mut reader = File::open(&good_file).unwrap();
+            let pio: ParameterIO = ParameterIO::from_binary(&mut reader).unwrap();
+            let new_text = pio.clone().to_text().unwrap();
+            let new_pio = ParameterIO::from_text(&new_text).unwrap();
+            if pio != new_pio {
+                panic!(
+                    "{:?} failed YAML roundtrip\n{:?}\n{:?}",
+                    &good_file, pio, new_pio
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn yaml_roundtrip_with_names() {
+        for file in glob("test/*.b*").unwrap() {
+            let good_file: PathBuf = file.unwrap();
+            let mut reader = File::open(&good_file).unwrap();
+            let pio: ParameterIO = ParameterIO::from_binary(&mut reader).unwrap();
+            let new_text = pio.with_names(&stock_names()).to_text().unwrap();
+            let new_pio = ParameterIO::from_text(&new_text).unwrap();
+            if pio != new_pio {
+                panic!(
                     "{:?} failed YAML roundtrip\n{:?}\n{:?}",
                     &good_file, pio, new_pio
                 );
@@ -217,4 +1291,2261 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn string_interior_null_roundtrip() {
+        let mut obj = ParameterObject(IndexMap::new());
+        obj.set_param("Name", Parameter::String32("ab\0cd".to_owned()));
+        let mut objects = IndexMap::new();
+        objects.insert(0, obj);
+        let pio = ParameterIO {
+            version: 0,
+            flags: 3,
+            pio_type: "xml".to_owned(),
+            lists: IndexMap::new(),
+            objects,
+            unknown_section: vec![0],
+            shared_data: IndexMap::new(),
+        };
+        let binary = pio.to_binary().unwrap();
+        let options = ParseOptions {
+            preserve_interior_nulls: true,
+            ..Default::default()
+        };
+        let pio2 =
+            ParameterIO::from_binary_with_options(&mut std::io::Cursor::new(&binary), &options)
+                .unwrap();
+        assert_eq!(pio, pio2);
+        assert_eq!(binary, pio2.to_binary().unwrap());
+        let truncated = ParameterIO::from_binary(&mut std::io::Cursor::new(&binary)).unwrap();
+        assert_eq!(
+            truncated.objects[0].param("Name"),
+            Some(&Parameter::String32("ab".to_owned()))
+        );
+    }
+
+    #[test]
+    fn approx_eq_nudged_floats() {
+        fn nudge(obj: &mut ParameterObject) {
+            for param in obj.params_mut().values_mut() {
+                match param {
+                    Parameter::F32(f) => *f += 1e-7,
+                    Parameter::Vec3(v) => v.0.iter_mut().for_each(|f| *f += 1e-7),
+                    Parameter::BufferF32(b) => b.buffer.iter_mut().for_each(|f| *f += 1e-7),
+                    _ => (),
+                }
+            }
+        }
+        let pio = sample_pio();
+        let mut nudged = pio.clone();
+        nudged.objects.values_mut().for_each(nudge);
+        for list in nudged.lists.values_mut() {
+            list.objects.values_mut().for_each(nudge);
+        }
+        assert_ne!(pio, nudged);
+        assert!(pio.approx_eq(&nudged, 1e-6));
+        assert!(!pio.approx_eq(&nudged, 0.0));
+        nudged.flags ^= 2;
+        assert!(!pio.approx_eq(&nudged, 1e-6));
+        nudged.flags ^= 2;
+        nudged.unknown_section = vec![1];
+        assert!(!pio.approx_eq(&nudged, 1e-6));
+    }
+
+    #[test]
+    fn stock_section_names_roundtrip() {
+        let pio = ParameterIO::builder()
+            .object("General", |o| o.param("lists", Parameter::Int(1)))
+            .object("objects", |o| o.param("objects", Parameter::Int(2)))
+            .build()
+            .unwrap();
+        let text = pio.with_names(&stock_names()).to_text().unwrap();
+        let parsed =
+            ParameterIO::from_text_with_names(&text, &mut crate::names::NameTable::new(false))
+                .unwrap();
+        assert!(parsed.eq_ordered(&pio), "{}", text);
+    }
+
+    #[test]
+    fn object_fragment_roundtrip() {
+        let pio = sample_pio();
+        let obj = &pio.objects[&hash_name("General")];
+        let table = crate::names::get_default_name_table();
+        let fragment = obj.to_text_fragment("General", &table);
+        assert!(fragment.starts_with("General: !obj"));
+        let (crc, parsed) = ParameterObject::from_text_fragment(&fragment).unwrap();
+        assert_eq!(crc, hash_name("General"));
+        assert_eq!(&parsed, obj);
+        // Fragment keys are quoted by the same rule as keys in documents
+        for name in &["42", "Key: Value", "lists"] {
+            let fragment = obj.to_text_fragment(name, &table);
+            let (crc, parsed) = ParameterObject::from_text_fragment(&fragment).unwrap();
+            assert_eq!(crc, hash_name(name), "{}", fragment);
+            assert_eq!(&parsed, obj);
+        }
+    }
+
+    #[test]
+    fn malformed_binary_errors() {
+        fn read_u32(bytes: &[u8], offset: usize) -> usize {
+            u32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ]) as usize
+        }
+        let binary = sample_pio().to_binary().unwrap();
+        let params_start = 0x30
+            + read_u32(&binary, 0x14)
+            + read_u32(&binary, 0x18) * 12
+            + read_u32(&binary, 0x1C) * 8;
+        let mut bad_type = binary.clone();
+        bad_type[params_start + 7] = 0xFF;
+        assert!(matches!(
+            ParameterIO::from_binary(&mut std::io::Cursor::new(bad_type)),
+            Err(ParseError::InvalidParameterType(0xFF, _))
+        ));
+        let mut bad_offset = binary;
+        bad_offset[params_start + 4..params_start + 7].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+        assert!(matches!(
+            ParameterIO::from_binary(&mut std::io::Cursor::new(bad_offset)),
+            Err(ParseError::OffsetOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn bad_yaml_errors() {
+        let bad_header =
+            "!io\nversoin: 0\ntype: xml\nparam_root: !list\n  objects: {}\n  lists: {}\n";
+        assert!(ParameterIO::from_text(bad_header).is_err());
+        let bad_curve = "!io\nversion: 0\ntype: xml\nparam_root: !list\n  objects:\n    Obj: !obj\n      Curve: !curve [0, 0, 1.0]\n  lists: {}\n";
+        assert!(ParameterIO::from_text(bad_curve).is_err());
+        let bad_vec = "!io\nversion: 0\ntype: xml\nparam_root: !list\n  objects:\n    Obj: !obj\n      Pos: !vec3 [1.0, 2.0]\n  lists: {}\n";
+        assert!(ParameterIO::from_text(bad_vec).is_err());
+    }
+
+    #[cfg(feature = "glob")]
+    #[test]
+    fn load_test_dir() {
+        for (path, result) in super::load_dir("test", "*.b*").unwrap() {
+            if let Err(err) = result {
+                panic!("{:?} failed to parse: {}", path, err);
+            }
+        }
+    }
+
+    #[test]
+    fn hash_name_matches_crc32() {
+        assert_eq!(hash_name("param_root"), 2_767_637_356);
+        assert_eq!(hash_name(""), 0);
+        assert_eq!(super::Key::from("param_root").hash(), 2_767_637_356);
+        assert_eq!(
+            super::Key::from(&"param_root".to_owned()).hash(),
+            2_767_637_356
+        );
+        assert_eq!(super::Key::from(2_767_637_356).hash(), 2_767_637_356);
+    }
+
+    #[test]
+    fn access_by_index() {
+        let pio = sample_pio();
+        let (crc, obj) = pio.object_at_index(0).unwrap();
+        assert_eq!(crc, hash_name("General"));
+        assert_eq!(
+            obj.param_at_index(1),
+            Some((hash_name("Scale"), &Parameter::F32(1.5)))
+        );
+        assert_eq!(obj.param_at_index(5), None);
+        let (crc, list) = pio.list_at_index(0).unwrap();
+        assert_eq!(crc, hash_name("Child"));
+        assert_eq!(list.object_at_index(0).unwrap().0, hash_name("Param"));
+        assert!(pio.list_at_index(1).is_none());
+    }
+
+    #[test]
+    fn mutable_lookups_by_name() {
+        let mut pio = sample_pio();
+        pio.list_mut("Child")
+            .unwrap()
+            .object_mut("Param")
+            .unwrap()
+            .set_param("Life", Parameter::Int(5));
+        pio.object_mut("General")
+            .unwrap()
+            .set_param("Scale", Parameter::F32(2.0));
+        assert_eq!(
+            pio.list("Child")
+                .unwrap()
+                .object("Param")
+                .unwrap()
+                .param("Life"),
+            Some(&Parameter::Int(5))
+        );
+        assert_eq!(
+            pio.object("General").unwrap().param("Scale"),
+            Some(&Parameter::F32(2.0))
+        );
+        assert!(pio.list_mut("Missing").is_none());
+        assert!(pio.object_mut("Missing").is_none());
+        assert!(pio.list_mut("Child").unwrap().list_mut("Param").is_none());
+    }
+
+    #[test]
+    fn remove_preserves_order() {
+        let mut pio = sample_pio();
+        let obj = pio.object_mut("General").unwrap();
+        assert_eq!(obj.remove_param("Scale"), Some(Parameter::F32(1.5)));
+        assert_eq!(obj.remove_param("Scale"), None);
+        let keys: Vec<u32> = obj.params().keys().copied().collect();
+        assert_eq!(
+            keys,
+            ["Life", "Position", "Name", "Weights"]
+                .iter()
+                .map(|name| hash_name(name))
+                .collect::<Vec<u32>>()
+        );
+
+        let mut list = pio.remove_list("Child").unwrap();
+        assert!(pio.lists.is_empty());
+        list.objects
+            .insert(hash_name("Extra"), ParameterObject(IndexMap::new()));
+        list.objects
+            .insert(hash_name("Last"), ParameterObject(IndexMap::new()));
+        assert!(list.remove_object("Extra").is_some());
+        let keys: Vec<u32> = list.objects.keys().copied().collect();
+        assert_eq!(keys, vec![hash_name("Param"), hash_name("Last")]);
+        assert!(pio.remove_object("General").is_some());
+        assert!(pio.objects.is_empty());
+    }
+
+    #[test]
+    fn string_control_chars_roundtrip() {
+        let mut pio = sample_pio();
+        let value = "Tab\there\u{1}\"quoted\"\\".to_owned();
+        pio.object_mut("General")
+            .unwrap()
+            .set_param("Name", Parameter::StringRef(value.clone()));
+        let text = pio.with_names(&stock_names()).to_text().unwrap();
+        let new_pio = ParameterIO::from_text(&text).unwrap();
+        assert_eq!(
+            new_pio.object("General").unwrap().param("Name"),
+            Some(&Parameter::StringRef(value))
+        );
+        assert_eq!(pio, new_pio);
+    }
+
+    #[test]
+    fn parameter_types_ordered() {
+        use std::convert::TryFrom;
+        let all = types::ParameterType::all();
+        assert_eq!(all.len(), 21);
+        assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+        for (i, ptype) in all.iter().enumerate() {
+            assert_eq!(*ptype as usize, i);
+            assert_eq!(types::ParameterType::try_from(i as u8), Ok(*ptype));
+        }
+        assert!(types::ParameterType::try_from(21).is_err());
+    }
+
+    #[test]
+    fn verify_float_roundtrip() {
+        let options = ToTextOptions {
+            verify_float_roundtrip: true,
+            ..Default::default()
+        };
+        for file in glob("test/*.b*").unwrap() {
+            let mut reader = File::open(file.unwrap()).unwrap();
+            let pio = ParameterIO::from_binary(&mut reader).unwrap();
+            pio.with_names(&stock_names())
+                .with_options(options)
+                .to_text()
+                .unwrap();
+        }
+        let mut pio = sample_pio();
+        pio.object_mut("General")
+            .unwrap()
+            .set_param("Scale", Parameter::F32(f32::from_bits(0xFFC0_0000)));
+        assert!(pio.with_names(&stock_names()).to_text().is_ok());
+        assert!(pio
+            .with_names(&stock_names())
+            .with_options(options)
+            .to_text()
+            .is_err());
+    }
+
+    #[test]
+    fn merge_policies() {
+        let base = sample_pio();
+        let mut other = ParameterIO::default();
+        let mut general = ParameterObject::default();
+        general.set_param("Life", Parameter::Int(1));
+        general.set_param("Speed", Parameter::F32(2.0));
+        general.set_param(
+            "Weights",
+            Parameter::BufferF32(types::BufferF32 { buffer: vec![1.0] }),
+        );
+        other.set_object("General", general);
+        other.set_object("Extra", ParameterObject::default());
+
+        let merged = base.merge_with_policy(&other, crate::MergePolicy::default());
+        assert_eq!(merged, base.merge(&other));
+        assert_eq!(merged.object("General").unwrap()["Life"], Parameter::Int(1));
+
+        let merged = base.merge_with_policy(
+            &other,
+            crate::MergePolicy {
+                overwrite: false,
+                concat_buffers: true,
+                create_missing: false,
+            },
+        );
+        let general = merged.object("General").unwrap();
+        assert_eq!(general["Life"], Parameter::Int(100));
+        assert!(!general.contains("Speed"));
+        assert!(!merged.has_object("Extra"));
+        assert_eq!(
+            general["Weights"],
+            Parameter::BufferF32(types::BufferF32 {
+                buffer: vec![0.25, 0.5, 0.75, 1.0]
+            })
+        );
+    }
+
+    #[test]
+    fn merge_many_layers_patches() {
+        let mut base = sample_pio();
+        let mut patches = vec![];
+        for (life, extra) in &[(200, "First"), (300, "Second"), (400, "Third")] {
+            let mut obj = ParameterObject(IndexMap::new());
+            obj.set_param("Life", Parameter::Int(*life));
+            obj.set_param(extra, Parameter::Bool(true));
+            let mut patch = ParameterIO {
+                version: 0,
+                flags: 3,
+                pio_type: "xml".to_owned(),
+                lists: IndexMap::new(),
+                objects: IndexMap::new(),
+                unknown_section: vec![0],
+                shared_data: IndexMap::new(),
+            };
+            patch.objects.insert(hash_name("General"), obj.clone());
+            patch.lists.insert(
+                hash_name("Child"),
+                ParameterList {
+                    lists: IndexMap::new(),
+                    objects: vec![(hash_name("Param"), obj)].into_iter().collect(),
+                },
+            );
+            patches.push(patch);
+        }
+        patches[1]
+            .object_mut("General")
+            .unwrap()
+            .set_param("Scale", Parameter::F32(2.0));
+        base.merge_many(&patches);
+
+        let general = base.object("General").unwrap();
+        assert_eq!(general.param("Life"), Some(&Parameter::Int(400)));
+        assert_eq!(general.param("Scale"), Some(&Parameter::F32(2.0)));
+        assert_eq!(
+            general.param("Name"),
+            Some(&Parameter::StringRef("Enemy_Lizalfos".to_owned()))
+        );
+        for extra in &["First", "Second", "Third"] {
+            assert_eq!(general.param(extra), Some(&Parameter::Bool(true)));
+        }
+        let child = base.list("Child").unwrap().object("Param").unwrap();
+        assert_eq!(child.param("Life"), Some(&Parameter::Int(400)));
+        assert_eq!(child.param("Scale"), Some(&Parameter::F32(1.5)));
+    }
+
+    #[test]
+    fn verify_names_reports_mishash() {
+        let mut table = crate::names::NameTable::new(false);
+        for name in &[
+            "General", "Child", "Param", "Life", "Scale", "Position", "Name",
+        ] {
+            table.add_name(name);
+        }
+        let mut pio = sample_pio();
+        assert_eq!(pio.verify_names_roundtrip(&table), Ok(()));
+        // Names that are not plain scalars are quoted and read back intact
+        for name in &[
+            "Key: Value",
+            "#Hash",
+            "!Bang",
+            "&Amp",
+            "*Star",
+            " Lead",
+            "Trail ",
+            "End:",
+        ] {
+            table.add_name(name);
+            pio.object_mut("General")
+                .unwrap()
+                .set_param(*name, Parameter::Bool(true));
+        }
+        assert_eq!(pio.verify_names_roundtrip(&table), Ok(()));
+        // The section keys are quoted so that they are not read as structure
+        for name in &["objects", "lists"] {
+            table.add_name(name);
+            pio.object_mut("General")
+                .unwrap()
+                .set_param(*name, Parameter::Bool(true));
+        }
+        assert_eq!(pio.verify_names_roundtrip(&table), Ok(()));
+        let text = pio.with_names(&table).to_text().unwrap();
+        assert!(text.contains("\"lists\": true"), "{}", text);
+        let parsed =
+            ParameterIO::from_text_with_names(&text, &mut crate::names::NameTable::new(false))
+                .unwrap();
+        assert!(parsed.eq_ordered(&pio));
+    }
+
+    #[test]
+    fn default_is_empty() {
+        let mut obj = ParameterObject::default();
+        assert!(obj.params().is_empty());
+        obj.set_param("Life", Parameter::Int(1));
+        assert_eq!(obj.param("Life"), Some(&Parameter::Int(1)));
+        let mut list = ParameterList::default();
+        assert!(list.lists.is_empty() && list.objects.is_empty());
+        list.objects.insert(hash_name("General"), obj);
+        assert!(list.object("General").is_some());
+    }
+
+    #[test]
+    fn inline_small_objects_roundtrip() {
+        let mut pio = sample_pio();
+        let mut obj = ParameterObject::default();
+        obj.set_param("Life", Parameter::Int(100));
+        obj.set_param(
+            "Name",
+            Parameter::StringRef("Lizalfos, Electric".to_owned()),
+        );
+        pio.objects.insert(hash_name("Small"), obj);
+        let options = ToTextOptions {
+            inline_small_objects: Some(2),
+            ..Default::default()
+        };
+        let text = pio
+            .with_names(&stock_names())
+            .with_options(options)
+            .to_text()
+            .unwrap();
+        assert!(text.contains("Small: !obj {Life: 100, Name: \"Lizalfos, Electric\"}"));
+        assert_eq!(ParameterIO::from_text(&text).unwrap(), pio);
+    }
+
+    #[test]
+    fn too_large_errors() {
+        let mut obj = ParameterObject::default();
+        obj.set_param(
+            "Huge",
+            Parameter::BufferBinary(types::BufferBinary {
+                buffer: vec![0; 0x400_0000],
+            }),
+        );
+        obj.set_param("After", Parameter::Bool(true));
+        let mut pio = sample_pio();
+        pio.objects.insert(hash_name("Large"), obj);
+        // The strings are written after the buffer, so they are pushed out of reach along with
+        // the value after it
+        assert!(matches!(
+            pio.to_binary(),
+            Err(WriteError::DataOffsetTooLarge(crc)) if crc == hash_name("Name")
+        ));
+        let issues = pio.validate();
+        let paths: Vec<Vec<u32>> = issues.iter().map(|issue| issue.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                vec![hash_name("General"), hash_name("Name")],
+                vec![hash_name("Large"), hash_name("After")],
+                vec![hash_name("Child"), hash_name("Param"), hash_name("Name")],
+            ]
+        );
+        assert!(issues.iter().all(|issue| matches!(
+            issue.kind,
+            crate::ValidationIssueKind::DataOffsetTooLarge(offset) if offset > 0xFF_FFFF * 4
+        )));
+    }
+
+    #[test]
+    fn deeply_nested_yaml() {
+        let mut list = ParameterList::default();
+        list.objects
+            .insert(hash_name("Leaf"), sample_pio().objects[0].clone());
+        for _ in 0..25 {
+            let mut parent = ParameterList::default();
+            parent.lists.insert(hash_name("Child"), list);
+            list = parent;
+        }
+        let pio = ParameterIO {
+            version: 0,
+            flags: 3,
+            pio_type: "xml".to_owned(),
+            lists: list.lists,
+            objects: IndexMap::new(),
+            unknown_section: vec![0],
+            shared_data: IndexMap::new(),
+        };
+        let text = pio.with_names(&stock_names()).to_text().unwrap();
+        assert_eq!(ParameterIO::from_text(&text).unwrap(), pio);
+    }
+
+    #[test]
+    fn pretty_debug_resolves_names() {
+        let mut table = crate::names::NameTable::new(false);
+        for name in &["General", "Child", "Param", "Life"] {
+            table.add_name(name);
+        }
+        let text = sample_pio().pretty_debug(&table);
+        assert!(text.contains("  General (object)\n    Life: 100\n"));
+        assert!(text.contains("  Child (list)\n    Param (object)\n"));
+        assert!(!text.contains(&hash_name("General").to_string()));
+    }
+
+    #[test]
+    fn unknown_section_roundtrip() {
+        let mut bytes = sample_pio().to_binary().unwrap();
+        bytes.pop();
+        bytes.extend_from_slice(&[1, 2, 3, 4]);
+        bytes[0x2C..0x30].copy_from_slice(&4u32.to_le_bytes());
+        let pio = ParameterIO::from_binary(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(pio.unknown_section, vec![1, 2, 3, 4]);
+        assert_eq!(pio.to_binary().unwrap(), bytes);
+    }
+
+    #[test]
+    fn iter_named_params() {
+        let mut table = crate::names::NameTable::new(false);
+        for name in &["Life", "Scale", "Position", "Name"] {
+            table.add_name(name);
+        }
+        let pio = sample_pio();
+        let names: Vec<String> = pio
+            .object("General")
+            .unwrap()
+            .iter_named(&table)
+            .map(|(name, _)| name.into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "Life".to_owned(),
+                "Scale".to_owned(),
+                "Position".to_owned(),
+                "Name".to_owned(),
+                hash_name("Weights").to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn buffer_le_bytes_roundtrip() {
+        let buffer = types::BufferF32 {
+            buffer: vec![0.25, -1.5, 3.0],
+        };
+        let bytes = buffer.to_le_bytes();
+        assert_eq!(&bytes[..4], &0.25f32.to_le_bytes());
+        assert_eq!(types::BufferF32::from_le_bytes(&bytes).unwrap(), buffer);
+        assert!(matches!(
+            types::BufferF32::from_le_bytes(&bytes[..5]),
+            Err(ParseError::InvalidBufferLength(5))
+        ));
+        let binary = types::BufferBinary::from_le_bytes(&bytes[..5]).unwrap();
+        assert_eq!(binary.to_le_bytes(), &bytes[..5]);
+    }
+
+    #[test]
+    fn with_names_uses_table() {
+        let mut pio = sample_pio();
+        pio.objects
+            .insert(hash_name("SomeCustomObject"), ParameterObject::default());
+        let mut table = crate::names::NameTable::new(true);
+        table.add_name("SomeCustomObject");
+        assert!(!pio
+            .with_names(&stock_names())
+            .to_text()
+            .unwrap()
+            .contains("SomeCustomObject"));
+        assert!(pio
+            .with_names(&table)
+            .to_text()
+            .unwrap()
+            .contains("SomeCustomObject: !obj {}"));
+    }
+
+    #[test]
+    fn write_text_with_names_uses_table() {
+        let mut pio = sample_pio();
+        pio.objects
+            .insert(hash_name("AnotherCustomObject"), ParameterObject::default());
+        let mut table = crate::names::NameTable::new(true);
+        table.add_name("AnotherCustomObject");
+        let mut bytes: Vec<u8> = vec![];
+        pio.write_text_with_names(&mut bytes, &table).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.contains("AnotherCustomObject: !obj {}"));
+        assert_eq!(text, pio.with_names(&table).to_text().unwrap());
+        let mut parse_table = crate::names::NameTable::new(true);
+        assert_eq!(
+            ParameterIO::from_text_with_names(&text, &mut parse_table).unwrap(),
+            pio
+        );
+    }
+
+    #[test]
+    fn shared_data_roundtrip() {
+        let mut obj = ParameterObject::default();
+        obj.set_param("A", Parameter::F32(1.5));
+        obj.set_param("B", Parameter::F32(1.5));
+        let mut pio = ParameterIO::default();
+        pio.objects.insert(hash_name("Obj"), obj);
+        let preserve = WriteOptions {
+            preserve_layout: true,
+            ..Default::default()
+        };
+        let parse = |bytes: &[u8]| {
+            ParameterIO::from_binary_with_options(
+                &mut std::io::Cursor::new(bytes),
+                &ParseOptions {
+                    preserve_layout: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let shared = pio.to_binary().unwrap();
+        assert!(ParameterIO::from_bytes(&shared)
+            .unwrap()
+            .shared_data
+            .is_empty());
+        let parsed = parse(&shared);
+        assert_eq!(parsed.shared_data.get(&1), Some(&0));
+        assert_eq!(parsed.to_binary_with_options(&preserve).unwrap(), shared);
+
+        let unshared = pio.to_binary_with_options(&preserve).unwrap();
+        assert_eq!(unshared.len(), shared.len() + 4);
+        let parsed = parse(&unshared);
+        assert!(parsed.shared_data.is_empty());
+        assert_eq!(parsed.to_binary_with_options(&preserve).unwrap(), unshared);
+
+        // Editing a value that was shared must not leave it pointing at the old one
+        let mut edited = parse(&shared);
+        edited.objects[0].set_param("B", Parameter::F32(2.5));
+        let binary = edited.to_binary_with_options(&preserve).unwrap();
+        assert_eq!(ParameterIO::from_bytes(&binary).unwrap(), edited);
+    }
+
+    #[test]
+    fn eq_ignores_shared_data() {
+        let mut pio = sample_pio();
+        pio.objects[0].set_param("Other", Parameter::F32(1.5));
+        let parsed = ParameterIO::from_binary_with_options(
+            &mut std::io::Cursor::new(pio.to_binary().unwrap()),
+            &ParseOptions {
+                preserve_layout: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(!parsed.shared_data.is_empty());
+        assert_eq!(parsed, pio);
+    }
+
+    #[test]
+    fn writer_dedups_values() {
+        let mut obj = ParameterObject::default();
+        obj.set_param("A", Parameter::Int(5));
+        obj.set_param("B", Parameter::Int(5));
+        obj.set_param("C", Parameter::StringRef("abc".to_owned()));
+        obj.set_param("D", Parameter::StringRef("abc".to_owned()));
+        obj.set_param("E", Parameter::Int(6));
+        obj.set_param("F", Parameter::U32(5));
+        let mut pio = ParameterIO::default();
+        pio.set_object("Obj", obj);
+        let binary = pio.to_binary().unwrap();
+        let offsets = ParameterIOReader::new(&binary)
+            .unwrap()
+            .data_offsets()
+            .unwrap();
+        assert_eq!(offsets.len(), 6);
+        assert_eq!(offsets[0], offsets[1]);
+        assert_eq!(offsets[2], offsets[3]);
+        assert_ne!(offsets[0], offsets[4]);
+        // Values are compared by their bytes, since the type is stored with the offset
+        assert_eq!(offsets[0], offsets[5]);
+        assert_eq!(ParameterIO::from_bytes(&binary).unwrap(), pio);
+    }
+
+    #[test]
+    fn index_object_by_name() {
+        let mut pio = sample_pio();
+        let obj = pio.object_mut("General").unwrap();
+        assert_eq!(obj["Life"], Parameter::Int(100));
+        obj["Life"] = Parameter::Int(50);
+        assert_eq!(obj.param("Life"), Some(&Parameter::Int(50)));
+        let crc = hash_name("Life");
+        assert_eq!(obj[crc], Parameter::Int(50));
+        obj[crc] = Parameter::Int(25);
+        assert_eq!(obj[&"Life".to_owned()], Parameter::Int(25));
+    }
+
+    #[test]
+    #[should_panic(expected = "No parameter with hash 1 in object")]
+    fn index_missing_hash_panics() {
+        let pio = sample_pio();
+        let _ = &pio.object("General").unwrap()[1];
+    }
+
+    #[test]
+    #[should_panic(expected = "No parameter named \"Missing\"")]
+    fn index_missing_param_panics() {
+        let pio = sample_pio();
+        let _ = &pio.object("General").unwrap()["Missing"];
+    }
+
+    #[test]
+    #[should_panic(expected = "No parameter named \"Missing\"")]
+    fn index_mut_missing_param_panics() {
+        let mut pio = sample_pio();
+        pio.object_mut("General").unwrap()["Missing"] = Parameter::Int(1);
+    }
+
+    #[test]
+    fn node_count_totals() {
+        let pio = sample_pio();
+        let (lists, objects, params) = (pio.num_lists(), pio.num_objects(), pio.num_parameters());
+        assert_eq!((lists, objects, params), (1, 2, 10));
+        assert_eq!(pio.node_count(), lists + objects + params);
+    }
+
+    #[test]
+    fn infer_string_type() {
+        let mut obj = ParameterObject::default();
+        obj.set_param(
+            "Name",
+            Parameter::StringRef("Enemy_Lizalfos_Dark!".to_owned()),
+        );
+        let mut pio = ParameterIO::default();
+        pio.objects.insert(hash_name("Obj"), obj);
+        let options = WriteOptions {
+            infer_string_type: true,
+            ..Default::default()
+        };
+        let parsed =
+            ParameterIO::from_bytes(&pio.to_binary_with_options(&options).unwrap()).unwrap();
+        assert_eq!(
+            parsed.object("Obj").unwrap().param("Name"),
+            Some(&Parameter::String32("Enemy_Lizalfos_Dark!".to_owned()))
+        );
+        let parsed = ParameterIO::from_bytes(&pio.to_binary().unwrap()).unwrap();
+        assert!(matches!(
+            parsed.object("Obj").unwrap().param("Name"),
+            Some(Parameter::StringRef(_))
+        ));
+
+        pio.objects[0].set_param("Name", Parameter::StringRef("a".repeat(256)));
+        assert!(matches!(
+            pio.to_binary_with_options(&options),
+            Err(WriteError::StringTooLong(_, 256))
+        ));
+    }
+
+    #[test]
+    fn sized_string_limits() {
+        let mut pio = ParameterIO::default();
+        pio.objects
+            .insert(hash_name("Obj"), ParameterObject::default());
+        let at_limit = [
+            Parameter::String32("a".repeat(31)),
+            Parameter::String64("a".repeat(63)),
+            Parameter::String256("a".repeat(255)),
+        ];
+        for param in at_limit.iter() {
+            pio.objects[0].set_param("Name", param.clone());
+            let parsed = ParameterIO::from_bytes(&pio.to_binary().unwrap()).unwrap();
+            assert_eq!(parsed.object("Obj").unwrap().param("Name"), Some(param));
+        }
+        let over_limit = [
+            Parameter::String32("a".repeat(32)),
+            Parameter::String64("a".repeat(64)),
+            Parameter::String256("a".repeat(400)),
+        ];
+        for param in over_limit.iter() {
+            pio.objects[0].set_param("Name", param.clone());
+            assert!(matches!(
+                pio.to_binary(),
+                Err(WriteError::StringTooLong(crc, _)) if crc == hash_name("Name")
+            ));
+        }
+    }
+
+    #[test]
+    fn truncate_strings_option() {
+        let options = WriteOptions {
+            truncate_strings: true,
+            ..Default::default()
+        };
+        let mut pio = sample_pio();
+        let cases = [
+            (
+                Parameter::String32("é".repeat(20)),
+                Parameter::String32("é".repeat(15)),
+            ),
+            (
+                Parameter::String64("a".repeat(64)),
+                Parameter::String64("a".repeat(63)),
+            ),
+            (
+                Parameter::String256("a".repeat(31)),
+                Parameter::String256("a".repeat(31)),
+            ),
+        ];
+        for (param, truncated) in cases.iter() {
+            pio.objects[0].set_param("Name", param.clone());
+            let binary = pio.to_binary_with_options(&options).unwrap();
+            let parsed = ParameterIO::from_bytes(&binary).unwrap();
+            assert_eq!(
+                parsed.object("General").unwrap().param("Name"),
+                Some(truncated)
+            );
+        }
+        pio.objects[0].set_param("Name", cases[0].0.clone());
+        assert!(matches!(
+            pio.to_binary(),
+            Err(WriteError::StringTooLong(_, 40))
+        ));
+    }
+
+    #[test]
+    fn diff_summary_counts() {
+        let base = sample_pio();
+        let mut other = base.clone();
+        assert_eq!(base.diff_summary(&other), DiffSummary::default());
+
+        let general = other.object_mut("General").unwrap();
+        general.set_param("Life", Parameter::Int(200));
+        general.set_param("Scale", Parameter::F32(2.0));
+        general.set_param("Speed", Parameter::F32(1.0));
+        general.remove_param("Name");
+        other
+            .list_mut("Child")
+            .unwrap()
+            .objects
+            .insert(hash_name("Extra"), ParameterObject::default());
+        assert_eq!(
+            base.diff_summary(&other),
+            DiffSummary {
+                added: 2,
+                removed: 1,
+                modified: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn diff_is_minimal_patch() {
+        let base = sample_pio();
+        let mut other = base.clone();
+        assert_eq!(base.diff(&other), ParameterIO::default());
+
+        other
+            .object_mut("General")
+            .unwrap()
+            .set_param("Life", Parameter::Int(200));
+        let mut extra = ParameterObject::default();
+        extra.set_param("Speed", Parameter::F32(1.0));
+        other
+            .list_mut("Child")
+            .unwrap()
+            .objects
+            .insert(hash_name("Extra"), extra.clone());
+        let patch = base.diff(&other);
+        assert_eq!(patch.objects.len(), 1);
+        assert_eq!(patch.object("General").unwrap().params().len(), 1);
+        let child = patch.list("Child").unwrap();
+        assert!(child.object("Param").is_none());
+        assert_eq!(child.object("Extra"), Some(&extra));
+        assert_eq!(base.merge(&patch), other);
+    }
+
+    #[test]
+    fn structural_diff_and_apply() {
+        let base = sample_pio();
+        assert!(crate::diff(&base, &base).is_empty());
+
+        let mut other = base.clone();
+        other.version = 3;
+        other.flags = crate::FLAG_UTF8;
+        let general = other.object_mut("General").unwrap();
+        general.set_param("Life", Parameter::Int(200));
+        general.remove_param("Scale");
+        general.set_param("Speed", Parameter::F32(2.0));
+        other.remove_list("Child");
+        other.set_list("Added", ParameterList::default());
+        let diff = crate::diff(&base, &other);
+        assert_eq!(diff.version, Some(3));
+        assert_eq!(diff.flags, Some(crate::FLAG_UTF8));
+        assert_eq!(diff.summary(), base.diff_summary(&other));
+        let changes = match &diff.root.objects[&hash_name("General")] {
+            crate::ObjectChange::Changed(obj) => &obj.params,
+            change => panic!("Expected a changed object, got {:?}", change),
+        };
+        assert_eq!(
+            changes[&hash_name("Life")],
+            crate::ParamChange::Changed(Parameter::Int(200))
+        );
+        assert_eq!(changes[&hash_name("Scale")], crate::ParamChange::Removed);
+        assert_eq!(
+            changes[&hash_name("Speed")],
+            crate::ParamChange::Added(Parameter::F32(2.0))
+        );
+        assert_eq!(
+            diff.root.lists[&hash_name("Child")],
+            crate::ListChange::Removed
+        );
+        assert_eq!(crate::apply(&base, &diff), other);
+
+        // Changes to nested lists apply on top of whatever else the base has in them
+        let mut nested = base.clone();
+        nested
+            .list_mut("Child")
+            .unwrap()
+            .object_mut("Param")
+            .unwrap()
+            .set_param("Life", Parameter::Int(1));
+        let diff = crate::diff(&base, &nested);
+        let mut modded = base.clone();
+        modded
+            .list_mut("Child")
+            .unwrap()
+            .set_object("Extra", ParameterObject::default());
+        let applied = crate::apply(&modded, &diff);
+        let child = applied.list("Child").unwrap();
+        assert!(child.has_object("Extra"));
+        assert_eq!(child.object("Param").unwrap()["Life"], Parameter::Int(1));
+    }
+
+    #[test]
+    fn pio_version_roundtrip() {
+        let mut pio = sample_pio();
+        pio.version = 7;
+        let bytes = pio.to_binary().unwrap();
+        assert_eq!(&bytes[4..8], &crate::FORMAT_VERSION.to_le_bytes());
+        assert_eq!(&bytes[0x10..0x14], &7u32.to_le_bytes());
+        assert_eq!(ParameterIO::from_bytes(&bytes).unwrap().version, 7);
+    }
+
+    #[test]
+    fn objects_recursive_paths() {
+        let pio = sample_pio();
+        let objects: Vec<_> = pio.objects_recursive().collect();
+        assert_eq!(objects.len(), pio.num_objects());
+        assert!(objects
+            .iter()
+            .any(|(path, crc, obj)| path == &vec![hash_name("Child")]
+                && *crc == hash_name("Param")
+                && *obj == pio.list("Child").unwrap().object("Param").unwrap()));
+    }
+
+    fn nested_pio() -> ParameterIO {
+        fn object(name: &str) -> ParameterObject {
+            let mut obj = ParameterObject::default();
+            obj.set_param(name, Parameter::Int(1));
+            obj.set_param("Shared", Parameter::Int(2));
+            obj
+        }
+        let mut inner = ParameterList::default();
+        inner.set_object("C", object("C"));
+        let mut first = ParameterList::default();
+        first.set_object("B", object("B"));
+        first.set_list("L2", inner);
+        let mut second = ParameterList::default();
+        second.set_object("D", object("D"));
+        let mut pio = ParameterIO::default();
+        pio.set_object("A", object("A"));
+        pio.set_list("L1", first);
+        pio.set_list("L3", second);
+        pio
+    }
+
+    #[test]
+    fn objects_recursive_order() {
+        let pio = nested_pio();
+        let objects: Vec<(Vec<u32>, u32)> = pio
+            .objects_recursive()
+            .map(|(path, crc, _)| (path, crc))
+            .collect();
+        assert_eq!(
+            objects,
+            vec![
+                (vec![], hash_name("A")),
+                (vec![hash_name("L1")], hash_name("B")),
+                (vec![hash_name("L1"), hash_name("L2")], hash_name("C")),
+                (vec![hash_name("L3")], hash_name("D")),
+            ]
+        );
+        assert_eq!(ParameterIO::default().objects_recursive().count(), 0);
+    }
+
+    #[test]
+    fn iter_params_paths() {
+        let pio = nested_pio();
+        let params: Vec<(Vec<u32>, u32, &Parameter)> = pio.iter_params().collect();
+        assert_eq!(params.len(), pio.num_parameters());
+        assert_eq!(
+            params[..2],
+            [
+                (vec![hash_name("A")], hash_name("A"), &Parameter::Int(1)),
+                (
+                    vec![hash_name("A")],
+                    hash_name("Shared"),
+                    &Parameter::Int(2)
+                ),
+            ]
+        );
+        assert_eq!(
+            params[4],
+            (
+                vec![hash_name("L1"), hash_name("L2"), hash_name("C")],
+                hash_name("C"),
+                &Parameter::Int(1)
+            )
+        );
+        assert_eq!(
+            params[7],
+            (
+                vec![hash_name("L3"), hash_name("D")],
+                hash_name("Shared"),
+                &Parameter::Int(2)
+            )
+        );
+        assert_eq!(
+            pio.iter_params().next().map(|(_, crc, _)| crc),
+            Some(hash_name("A"))
+        );
+    }
+
+    #[test]
+    fn raw_keys_roundtrip() {
+        let pio = sample_pio();
+        let options = ToTextOptions {
+            raw_keys: true,
+            ..Default::default()
+        };
+        let text = pio
+            .with_names(&stock_names())
+            .with_options(options)
+            .to_text()
+            .unwrap();
+        assert!(!text.contains("General"));
+        assert!(text.contains(&format!("{}: ", hash_name("General"))));
+        assert_eq!(ParameterIO::from_text(&text).unwrap(), pio);
+    }
+
+    #[test]
+    fn empty_entries_ignore_stray_offsets() {
+        let mut pio = ParameterIO::default();
+        pio.objects
+            .insert(hash_name("Empty"), ParameterObject::default());
+        pio.lists
+            .insert(hash_name("EmptyList"), ParameterList::default());
+        let mut binary = pio.to_binary().unwrap();
+        let lists_start = 0x30 + u32::from_le_bytes([binary[0x14], 0, 0, 0]) as usize;
+        // The second list entry is the empty list, followed by the empty object
+        let list = lists_start + 12;
+        binary[list + 4..list + 6].copy_from_slice(&[0xFF, 0xFF]);
+        binary[list + 8..list + 10].copy_from_slice(&[0xFF, 0xFF]);
+        let obj = lists_start + 24;
+        binary[obj + 4..obj + 6].copy_from_slice(&[0xFF, 0xFF]);
+        let parsed = ParameterIO::from_bytes(&binary).unwrap();
+        assert_eq!(parsed, pio);
+    }
+
+    #[test]
+    fn empty_containers_yaml_roundtrip() {
+        let mut pio = sample_pio();
+        pio.objects
+            .insert(hash_name("Empty"), ParameterObject::default());
+        let mut nested = ParameterList::default();
+        nested
+            .lists
+            .insert(hash_name("EmptyList"), ParameterList::default());
+        nested
+            .objects
+            .insert(hash_name("Empty"), ParameterObject::default());
+        pio.lists.insert(hash_name("Nested"), nested);
+        pio.lists
+            .insert(hash_name("EmptyList"), ParameterList::default());
+        pio.list_mut("Child")
+            .unwrap()
+            .lists
+            .insert(hash_name("EmptyList"), ParameterList::default());
+        let text = pio.with_names(&stock_names()).to_text().unwrap();
+        assert_eq!(ParameterIO::from_text(&text).unwrap(), pio);
+        let options = ToTextOptions {
+            inline_small_objects: Some(2),
+            ..Default::default()
+        };
+        let text = pio
+            .with_names(&stock_names())
+            .with_options(options)
+            .to_text()
+            .unwrap();
+        assert_eq!(ParameterIO::from_text(&text).unwrap(), pio);
+
+        let empty = ParameterIO::default();
+        assert_eq!(
+            ParameterIO::from_text(&empty.with_names(&stock_names()).to_text().unwrap()).unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn hex_dump_sections() {
+        let pio = sample_pio();
+        let binary = pio.to_binary().unwrap();
+        let dump = pio.hex_dump().unwrap();
+        assert!(dump.starts_with("header @ 0x0 (0x30 bytes)\n  00000000  41 41 4d 50"));
+        assert!(dump.lines().nth(1).unwrap().ends_with("|AAMP............|"));
+        assert!(dump.contains("pio type @ 0x30 (0x4 bytes)"));
+        assert!(dump.contains("lists @ 0x34 (0x18 bytes)"));
+        assert!(dump.contains("objects @ 0x4c (0x10 bytes)"));
+        assert!(dump.contains("params @ 0x5c (0x50 bytes)"));
+        assert!(dump.contains(&format!("reserved @ {:#x} (0x1 bytes)", binary.len() - 1)));
+
+        // Big-endian documents give the same sizes
+        let mut big = pio;
+        big.flags = crate::FLAG_UTF8;
+        let big_dump = big.hex_dump().unwrap();
+        assert_eq!(big.to_binary().unwrap().len(), binary.len());
+        let headings = |dump: &str| -> Vec<String> {
+            dump.lines()
+                .filter(|line| !line.starts_with(' '))
+                .map(str::to_owned)
+                .collect()
+        };
+        assert_eq!(headings(&big_dump), headings(&dump));
+    }
+
+    #[test]
+    fn non_finite_floats_yaml_roundtrip() {
+        let floats = vec![f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 1.5];
+        let mut pio = sample_pio();
+        let obj = &mut pio.objects[0];
+        obj.set_param(
+            "Buffer",
+            Parameter::BufferF32(types::BufferF32 {
+                buffer: floats.clone(),
+            }),
+        );
+        obj.set_param("NaN", Parameter::F32(f32::NAN));
+        obj.set_param("Inf", Parameter::F32(f32::NEG_INFINITY));
+        obj.set_param(
+            "Pos",
+            Parameter::Vec3(types::Vec3([f32::INFINITY, 0.0, f32::NAN])),
+        );
+        obj.set_param("Str", Parameter::StringRef(".inf".to_owned()));
+        let options = ToTextOptions {
+            verify_float_roundtrip: true,
+            ..Default::default()
+        };
+        let text = pio
+            .with_names(&stock_names())
+            .with_options(options)
+            .to_text()
+            .unwrap();
+        assert!(text.contains("!buffer_f32 [.nan, .inf, -.inf, 1.5]"));
+        let parsed = ParameterIO::from_text(&text).unwrap();
+        let obj = parsed.object("General").unwrap();
+        let bits = |params: &[f32]| params.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+        match obj.param("Buffer") {
+            Some(Parameter::BufferF32(buffer)) => assert_eq!(bits(&buffer.buffer), bits(&floats)),
+            other => panic!("Expected a float buffer, got {:?}", other),
+        }
+        assert!(
+            matches!(obj.param("NaN"), Some(Parameter::F32(f)) if f.to_bits() == f32::NAN.to_bits())
+        );
+        assert_eq!(obj.param("Inf"), Some(&Parameter::F32(f32::NEG_INFINITY)));
+        match obj.param("Pos") {
+            Some(Parameter::Vec3(v)) => {
+                assert_eq!(bits(&v.0), bits(&[f32::INFINITY, 0.0, f32::NAN]))
+            }
+            other => panic!("Expected a vec3, got {:?}", other),
+        }
+        assert_eq!(
+            obj.param("Str"),
+            Some(&Parameter::StringRef(".inf".to_owned()))
+        );
+    }
+
+    #[test]
+    fn from_iter_checked_lengths() {
+        assert_eq!(
+            types::Vec3::from_iter_checked(vec![1.0, 2.0, 3.0]).unwrap(),
+            types::Vec3([1.0, 2.0, 3.0])
+        );
+        assert!(matches!(
+            types::Vec3::from_iter_checked(vec![1.0, 2.0]),
+            Err(types::WrongValueCount {
+                expected: 3,
+                found: 2,
+            })
+        ));
+        assert_eq!(
+            types::Color::from_iter_checked((0..4).map(|x| x as f32)).unwrap(),
+            types::Color([0.0, 1.0, 2.0, 3.0])
+        );
+        assert!(matches!(
+            types::Color::from_iter_checked((0..5).map(|x| x as f32)),
+            Err(types::WrongValueCount {
+                expected: 4,
+                found: 5,
+            })
+        ));
+        let curve = types::Curve::from_floats_checked(1, 2, vec![0.5; 30]).unwrap();
+        assert_eq!((curve.a, curve.b, curve.floats), (1, 2, [0.5; 30]));
+        assert!(matches!(
+            types::Curve::from_floats_checked(1, 2, vec![0.5; 29]),
+            Err(types::WrongValueCount {
+                expected: 30,
+                found: 29,
+            })
+        ));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn plain_json_vec3() {
+        let mut table = crate::names::NameTable::new(false);
+        table.add_name("General");
+        table.add_name("Position");
+        let json = sample_pio().to_plain_json(&table);
+        assert_eq!(
+            json["param_root"]["objects"]["General"]["Position"],
+            serde_json::json!([1.0, 2.0, 3.0])
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn tagged_json_roundtrip() {
+        let curve = types::Curve {
+            a: 1,
+            b: 2,
+            floats: [0.25; 30],
+        };
+        let pio = ParameterIOBuilder::new("xml")
+            .object("General", |o| {
+                o.param("Name", Parameter::String32("Link".to_owned()))
+                    .param("Speed", Parameter::F32(f32::INFINITY))
+                    .param("Tint", Parameter::Color(types::Color([0.1, 0.2, 0.3, 1.0])))
+                    .param("Flags", Parameter::U32(0xFFFF_FFFF))
+                    .param(
+                        "Curve",
+                        Parameter::Curve2(types::Curve2 {
+                            curve1: curve.clone(),
+                            curve2: curve,
+                        }),
+                    )
+                    .param(
+                        "Data",
+                        Parameter::BufferBinary(types::BufferBinary {
+                            buffer: vec![0, 255],
+                        }),
+                    )
+            })
+            .list("Children", |l| l.object("0", |o| o.param("Ref", "x")))
+            .build()
+            .unwrap();
+        let mut table = crate::names::NameTable::new(false);
+        for name in &[
+            "General", "Name", "Speed", "Tint", "Flags", "Curve", "Data", "0",
+        ] {
+            table.add_name(name);
+        }
+        let json = pio.to_json_with_names(&table);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["param_root"]["objects"]["General"]["Name"],
+            serde_json::json!({"type": "str32", "value": "Link"})
+        );
+        assert_eq!(
+            value["param_root"]["objects"]["General"]["Tint"]["value"],
+            serde_json::json!([0.1, 0.2, 0.3, 1.0])
+        );
+        assert_eq!(
+            value["param_root"]["objects"]["General"]["Speed"]["value"],
+            ".inf"
+        );
+        let mut found = crate::names::NameTable::new(false);
+        let parsed = ParameterIO::from_json_with_names(&json, &mut found).unwrap();
+        assert!(parsed.eq_ordered(&pio));
+        assert_eq!(found.get_name(hash_name("Link")).as_deref(), Some("Link"));
+        let mut pio = pio;
+        pio.flags = crate::FLAG_UTF8;
+        pio.unknown_section = vec![1, 2, 3];
+        let parsed = ParameterIO::from_json(&pio.to_json_with_names(&table)).unwrap();
+        assert_eq!(parsed.flags, crate::FLAG_UTF8);
+        assert_eq!(parsed.unknown_section, vec![1, 2, 3]);
+        assert!(parsed.eq_ordered(&pio));
+        assert!(matches!(
+            ParameterIO::from_json(
+                r#"{"version": 0, "type": "xml", "param_root": {"objects":
+                    {"A": {"B": {"type": "vec2", "value": [1.0]}}}}}"#
+            ),
+            Err(crate::JsonError::InvalidPio(_))
+        ));
+    }
+
+    #[test]
+    fn single_object_documents() {
+        let mut pio = ParameterIO::default();
+        let obj = sample_pio().objects[0].clone();
+        pio.objects.insert(hash_name("General"), obj.clone());
+        assert_eq!(pio.single_object("General"), Some(&obj));
+        assert_eq!(pio.single_object("Other"), None);
+        assert_eq!(pio.clone().try_into_single_object().unwrap(), obj);
+
+        let pio = sample_pio();
+        assert_eq!(pio.single_object("General"), None);
+        assert_eq!(*pio.clone().try_into_single_object().unwrap_err(), pio);
+    }
+
+    #[test]
+    fn buffer_alignment_roundtrip() {
+        let mut pio = sample_pio();
+        pio.objects[0].set_param(
+            "Ints",
+            Parameter::BufferInt(types::BufferInt {
+                buffer: vec![1, 2, 3],
+            }),
+        );
+        let options = WriteOptions {
+            buffer_alignment: 8,
+            ..Default::default()
+        };
+        let binary = pio.to_binary_with_options(&options).unwrap();
+        let parsed = ParameterIO::from_bytes(&binary).unwrap();
+        assert_eq!(parsed, pio);
+        let buffers: [Vec<u8>; 2] = [
+            [0.25f32, 0.5, 0.75]
+                .iter()
+                .flat_map(|f| f.to_le_bytes())
+                .collect(),
+            [1i32, 2, 3].iter().flat_map(|i| i.to_le_bytes()).collect(),
+        ];
+        for contents in buffers.iter() {
+            let position = binary
+                .windows(contents.len())
+                .position(|window| window == &contents[..])
+                .unwrap();
+            assert_eq!(position % 8, 0);
+        }
+        assert!(matches!(
+            pio.to_binary_with_options(&WriteOptions {
+                buffer_alignment: 6,
+                ..Default::default()
+            }),
+            Err(WriteError::InvalidAlignment(6))
+        ));
+    }
+
+    #[test]
+    fn strict_parse_checks_sizes() {
+        let binary = sample_pio().to_binary().unwrap();
+        assert_eq!(
+            ParameterIO::from_binary_strict(&mut std::io::Cursor::new(&binary)).unwrap(),
+            sample_pio()
+        );
+        let truncated = &binary[..binary.len() - 8];
+        assert!(matches!(
+            ParameterIO::from_binary_strict(&mut std::io::Cursor::new(truncated)),
+            Err(ParseError::FileSizeMismatch(_, _))
+        ));
+        let mut bad_sections = binary.clone();
+        bad_sections[0x24] += 4;
+        assert!(matches!(
+            ParameterIO::from_binary_strict(&mut std::io::Cursor::new(bad_sections)),
+            Err(ParseError::InconsistentSections(_, _))
+        ));
+    }
+
+    #[test]
+    fn summarize_pio_types() {
+        let pios: Vec<ParameterIO> = ["xml", "xml", "bas", "xml", "bphysics"]
+            .iter()
+            .map(|pio_type| {
+                let mut pio = sample_pio();
+                pio.pio_type = (*pio_type).to_owned();
+                ParameterIO::from_bytes(&pio.to_binary().unwrap()).unwrap()
+            })
+            .collect();
+        let types = crate::summarize_types(&pios);
+        assert_eq!(
+            types.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("bas".to_owned(), 1),
+                ("bphysics".to_owned(), 1),
+                ("xml".to_owned(), 3)
+            ]
+        );
+        assert!(crate::summarize_types(&[]).is_empty());
+    }
+
+    #[test]
+    fn eq_ordered_checks_order() {
+        let pio = sample_pio();
+        let mut reordered = pio.clone();
+        let params = reordered.objects[0].params_mut();
+        params.swap_indices(0, 1);
+        assert_eq!(reordered, pio);
+        assert!(!reordered.eq_ordered(&pio));
+        assert!(!reordered.objects[0].eq_ordered(&pio.objects[0]));
+        assert_ne!(reordered.to_binary().unwrap(), pio.to_binary().unwrap());
+        assert!(pio.clone().eq_ordered(&pio));
+    }
+
+    #[test]
+    fn null_param_errors() {
+        for value in &["", " ~", " null"] {
+            let text = format!(
+                "!io\nversion: 0\ntype: xml\nparam_root: !list\n  objects:\n    Obj: !obj\n      Empty:{}\n      Life: 100\n  lists: {{}}\n",
+                value
+            );
+            match ParameterIO::from_text(&text) {
+                Err(crate::yaml::parse::YamlParseError::InvalidPio(msg)) => {
+                    assert!(msg.contains("Empty"), "{}", msg)
+                }
+                other => panic!("Expected an error, got {:?}", other),
+            }
+        }
+        let mut pio = sample_pio();
+        pio.objects[0].set_param("Name", Parameter::StringRef("null".to_owned()));
+        pio.objects[0].set_param("Tilde", Parameter::StringRef("~".to_owned()));
+        let text = pio.with_names(&stock_names()).to_text().unwrap();
+        assert_eq!(ParameterIO::from_text(&text).unwrap(), pio);
+    }
+
+    #[test]
+    fn type_names_and_ids() {
+        let param = Parameter::String32("abc".to_owned());
+        assert_eq!(param.type_name(), "string32");
+        assert_eq!(param.type_id(), 7);
+        assert_eq!(
+            Parameter::StringRef("abc".to_owned()).type_name(),
+            "string_ref"
+        );
+        assert_eq!(Parameter::U32(1).type_name(), "u32");
+        assert_eq!(Parameter::U32(1).type_id(), 17);
+        // Unlike the YAML tags, every type has a distinct name
+        let mut names: Vec<&str> = types::ParameterType::all()
+            .iter()
+            .map(|ty| ty.name())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), types::ParameterType::all().len());
+    }
+
+    #[test]
+    fn element_counts() {
+        let curve = types::Curve {
+            a: 0,
+            b: 0,
+            floats: [0.0; 30],
+        };
+        let counts = [
+            (
+                Parameter::BufferF32(types::BufferF32 {
+                    buffer: vec![1.0, 2.0, 3.0, 4.0, 5.0],
+                }),
+                Some(5),
+            ),
+            (Parameter::Vec3(types::Vec3([1.0, 2.0, 3.0])), Some(3)),
+            (
+                Parameter::Curve2(types::Curve2 {
+                    curve1: curve.clone(),
+                    curve2: curve,
+                }),
+                Some(60),
+            ),
+            (Parameter::F32(1.0), None),
+        ];
+        for (param, count) in counts.iter() {
+            assert_eq!(param.element_count(), *count);
+        }
+    }
+
+    #[test]
+    fn buffer_conversions() {
+        let ints = Parameter::BufferInt(types::BufferInt {
+            buffer: vec![1, 0x01020304],
+        });
+        let u32s = ints.as_buffer_u32().unwrap();
+        assert_eq!(u32s.buffer, vec![1, 0x01020304]);
+        let binary = ints.to_buffer_binary().unwrap();
+        assert_eq!(binary.buffer, vec![1, 0, 0, 0, 4, 3, 2, 1]);
+        assert_eq!(
+            Parameter::BufferBinary(binary).as_buffer_u32().unwrap(),
+            u32s
+        );
+        let negative = Parameter::BufferInt(types::BufferInt { buffer: vec![-1] });
+        assert_eq!(negative.as_buffer_u32(), None);
+        let odd = Parameter::BufferBinary(types::BufferBinary {
+            buffer: vec![1, 2, 3],
+        });
+        assert_eq!(odd.as_buffer_u32(), None);
+        assert_eq!(Parameter::U32(1).to_buffer_binary(), None);
+    }
+
+    #[test]
+    fn flags_roundtrip() {
+        let mut pio = sample_pio();
+        pio.flags = 1;
+        let binary = pio.to_binary().unwrap();
+        assert_eq!(&binary[8..12], &1u32.to_le_bytes());
+        let parsed = ParameterIO::from_binary(&mut std::io::Cursor::new(&binary)).unwrap();
+        assert_eq!(parsed.flags, 1);
+        assert_eq!(parsed, pio);
+        assert_eq!(parsed.to_binary().unwrap(), binary);
+        assert_ne!(parsed, sample_pio());
+
+        pio.flags = 2;
+        let binary = pio.to_binary().unwrap();
+        assert_eq!(&binary[8..12], &2u32.to_be_bytes());
+        assert_eq!(ParameterIO::from_bytes(&binary).unwrap().flags, 2);
+    }
+
+    #[test]
+    fn explicit_name_table_roundtrip() {
+        let mut pio = sample_pio();
+        pio.objects[0].set_param("Target", Parameter::StringRef("UnlistedName".to_owned()));
+        pio.objects
+            .insert(hash_name("UnlistedName"), pio.objects[0].clone());
+        let binary = pio.to_binary().unwrap();
+        let mut table = crate::names::NameTable::new(true);
+        let parsed =
+            ParameterIO::from_binary_with_names(&mut std::io::Cursor::new(&binary), &mut table)
+                .unwrap();
+        let text = parsed.with_names(&table).to_text().unwrap();
+        assert!(text.contains("UnlistedName: !obj"));
+        let mut new_table = crate::names::NameTable::new(false);
+        assert_eq!(
+            ParameterIO::from_text_with_names(&text, &mut new_table).unwrap(),
+            pio
+        );
+        assert_eq!(
+            new_table.get_name(hash_name("UnlistedName")).as_deref(),
+            Some("UnlistedName")
+        );
+    }
+
+    #[test]
+    fn merge_yaml_patch() {
+        let mut pio = sample_pio();
+        pio.merge_text(
+            "!io\nversion: 0\ntype: xml\nparam_root: !list\n  objects:\n    General: !obj\n      Life: 250\n  lists: {}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            pio.object("General").unwrap().param("Life"),
+            Some(&Parameter::Int(250))
+        );
+        assert_eq!(
+            pio.object("General").unwrap().params().len(),
+            sample_pio().object("General").unwrap().params().len()
+        );
+        assert!(pio.merge_text("!io\nversion: 0\n").is_err());
+    }
+
+    #[test]
+    fn u32_formats() {
+        let mut pio = sample_pio();
+        pio.objects[0].set_param("Flags", Parameter::U32(31));
+        pio.objects[0].set_param(
+            "Ids",
+            Parameter::BufferU32(types::BufferU32 {
+                buffer: vec![1, 31],
+            }),
+        );
+        let table = crate::names::NameTable::new(true);
+        let formats = [
+            (U32Format::Default, "!u 0x1F", "!buffer_u32 [1, 31]"),
+            (U32Format::Hex, "!u 0x1F", "!buffer_u32 [0x1, 0x1F]"),
+            (U32Format::Decimal, "!u 31", "!buffer_u32 [1, 31]"),
+        ];
+        for (format, u32_text, buffer_text) in formats.iter() {
+            let options = ToTextOptions {
+                u32_format: *format,
+                ..Default::default()
+            };
+            let text = pio
+                .with_names(&table)
+                .with_options(options)
+                .to_text()
+                .unwrap();
+            assert!(text.contains(u32_text), "{}", text);
+            assert!(text.contains(buffer_text), "{}", text);
+            let mut names = crate::names::NameTable::new(false);
+            assert_eq!(
+                ParameterIO::from_text_with_names(&text, &mut names).unwrap(),
+                pio
+            );
+        }
+    }
+
+    #[test]
+    fn build_document() {
+        let pio = ParameterIOBuilder::new("xml")
+            .version(1)
+            .object("LinkTarget", |o| o.param("Foo", 1.0f32).param("Bar", true))
+            .list("AI", |l| {
+                l.object("Def", |o| o.param("Name", "Idle"))
+                    .list("Children", |l| l)
+            })
+            .build()
+            .unwrap();
+        assert_eq!(pio.version, 1);
+        let obj = pio.object("LinkTarget").unwrap();
+        assert_eq!(
+            obj.params().keys().copied().collect::<Vec<u32>>(),
+            vec![hash_name("Foo"), hash_name("Bar")]
+        );
+        let ai = pio.list("AI").unwrap();
+        assert_eq!(
+            ai.object("Def").unwrap().param("Name"),
+            Some(&Parameter::StringRef("Idle".to_owned()))
+        );
+        assert!(ai.list("Children").is_some());
+
+        let binary = pio.to_binary().unwrap();
+        let parsed = ParameterIO::from_binary(&mut std::io::Cursor::new(&binary)).unwrap();
+        assert!(parsed.eq_ordered(&pio));
+    }
+
+    #[cfg(feature = "global-names")]
+    #[test]
+    fn parsing_leaves_global_table_alone() {
+        let mut obj = ParameterObject::default();
+        obj.set_param("Target", "UnregisteredObjectName".into());
+        let mut pio = ParameterIO::default();
+        pio.set_object("General", obj);
+        pio.set_object("UnregisteredObjectName", ParameterObject::default());
+        let binary = pio.to_binary().unwrap();
+
+        let before = crate::names::dump_global_table().len();
+        let parsed = ParameterIO::from_binary(&mut std::io::Cursor::new(&binary)).unwrap();
+        assert_eq!(crate::names::dump_global_table().len(), before);
+        assert!(crate::names::guess_name(hash_name("UnregisteredObjectName"), 0, 0).is_none());
+        // The document's own string values still name its objects
+        assert!(parsed
+            .to_text()
+            .unwrap()
+            .contains("\n    UnregisteredObjectName: !obj"));
+    }
+
+    #[test]
+    fn builder_registers_and_validates() {
+        let mut table = crate::names::NameTable::new(false);
+        let pio = ParameterIO::builder()
+            .object("BuilderGeneral", |o| o.param("BuilderLife", 100))
+            .list("BuilderAI", |l| l.object("BuilderDef", |o| o))
+            .build_with_names(&mut table)
+            .unwrap();
+        assert_eq!(pio.pio_type, "xml");
+        assert_eq!(
+            pio.object("BuilderGeneral").unwrap().param("BuilderLife"),
+            Some(&Parameter::Int(100))
+        );
+        for name in &["BuilderGeneral", "BuilderLife", "BuilderAI", "BuilderDef"] {
+            assert_eq!(table.get_name(hash_name(name)).as_deref(), Some(*name));
+        }
+
+        let long = Parameter::String32("x".repeat(32));
+        assert!(matches!(
+            ParameterIO::builder()
+                .object("General", |o| o.param("Name", long))
+                .build(),
+            Err(WriteError::StringTooLong(crc, 32)) if crc == hash_name("Name")
+        ));
+    }
+
+    #[test]
+    fn validate_reports_issues() {
+        use crate::{ValidationIssue, ValidationIssueKind};
+        let mut pio = sample_pio();
+        assert!(pio.validate().is_empty());
+        let mut many = ParameterObject::default();
+        for i in 0..=u16::MAX as u32 {
+            many.set_param(i, Parameter::Bool(true));
+        }
+        pio.set_object("Many", many);
+        let child = pio.list_mut("Child").unwrap();
+        let obj = child.object_mut("Param").unwrap();
+        obj.set_param("Short", Parameter::String32("x".repeat(31)));
+        obj.set_param("Long", Parameter::String64("x".repeat(64)));
+        let issues = pio.validate();
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue {
+                    path: vec![hash_name("Many")],
+                    kind: ValidationIssueKind::TooManyParams(65536),
+                },
+                ValidationIssue {
+                    path: vec![hash_name("Child"), hash_name("Param")],
+                    kind: ValidationIssueKind::EntryOffsetTooLarge(524336),
+                },
+                ValidationIssue {
+                    path: vec![hash_name("Child"), hash_name("Param"), hash_name("Long")],
+                    kind: ValidationIssueKind::StringTooLong {
+                        capacity: 64,
+                        len: 64
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            issues[2].to_string(),
+            format!(
+                "param_root/{}/{}/{}: String of 64 bytes is too long for a capacity of 64 bytes",
+                hash_name("Child"),
+                hash_name("Param"),
+                hash_name("Long")
+            )
+        );
+        assert!(matches!(
+            pio.to_binary(),
+            Err(WriteError::TooManyEntries(crc, 65536)) if crc == hash_name("Many")
+        ));
+    }
+
+    #[test]
+    fn entry_offset_too_large() {
+        use crate::{ValidationIssue, ValidationIssueKind};
+        let mut pio = sample_pio();
+        let mut big = ParameterObject::default();
+        for i in 0..40000u32 {
+            big.set_param(i, Parameter::Bool(true));
+        }
+        pio.set_object("Big", big);
+        let mut after = ParameterObject::default();
+        after.set_param("Name", Parameter::Int(1));
+        pio.set_object("After", after);
+        let issues = pio.validate();
+        assert!(matches!(
+            issues.first(),
+            Some(ValidationIssue {
+                path,
+                kind: ValidationIssueKind::EntryOffsetTooLarge(_),
+            }) if path == &vec![hash_name("After")]
+        ));
+        assert!(matches!(pio.to_binary(), Err(WriteError::FileTooLarge)));
+        let mut buffer = vec![];
+        assert!(pio.write_binary(&mut buffer).is_err());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn mutate_by_key() {
+        let mut list = ParameterList::default();
+        list.set_object("First", ParameterObject::default());
+        list.set_object(hash_name("Second"), ParameterObject::default());
+        list.set_list("Child", ParameterList::default());
+        assert!(list.has_object(hash_name("First")));
+        assert!(list.object("Second").is_some());
+
+        list.object_mut(hash_name("First"))
+            .unwrap()
+            .set_param("Value", Parameter::Int(1));
+        let obj = list.object_mut("First").unwrap();
+        assert!(obj.rename_param("Value", "Renamed"));
+        assert_eq!(obj.param(hash_name("Renamed")), Some(&Parameter::Int(1)));
+        assert!(!obj.rename_param("Value", "Other"));
+
+        assert!(list.rename_object("First", "Third"));
+        assert_eq!(
+            list.objects.keys().copied().collect::<Vec<u32>>(),
+            vec![hash_name("Third"), hash_name("Second")]
+        );
+        assert!(!list.rename_object("Third", "Second"));
+        assert!(list.rename_list(hash_name("Child"), "Renamed"));
+        assert!(list.remove_list(hash_name("Renamed")).is_some());
+        assert!(list.remove_object("Second").is_some());
+        assert_eq!(list.objects.len(), 1);
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let obj = sample_pio().object("General").unwrap().clone();
+        assert_eq!(obj["Life"].as_int(), Some(100));
+        assert_eq!(obj["Life"].as_f32(), None);
+        assert_eq!(obj["Name"].as_str(), Some("Enemy_Lizalfos"));
+        assert_eq!(
+            obj["Position"].as_vec3(),
+            Some(types::Vec3([1.0, 2.0, 3.0]))
+        );
+        assert_eq!(obj.get_as::<f32>("Scale"), Some(1.5));
+        assert_eq!(
+            obj.get_as::<String>(hash_name("Name")).as_deref(),
+            Some("Enemy_Lizalfos")
+        );
+        assert_eq!(obj.get_as::<u32>("Life"), None);
+        assert_eq!(obj.get_as::<bool>("Missing"), None);
+    }
+
+    #[test]
+    fn primitive_conversions() {
+        use std::convert::TryFrom;
+        let mut obj = ParameterObject::default();
+        obj.set_param("Life", 100.into());
+        obj.set_param("Position", [1.0, 2.0, 3.0].into());
+        obj.set_param("Name", "Link".into());
+        assert_eq!(i32::try_from(&obj["Life"]), Ok(100));
+        assert_eq!(
+            types::Vec3::try_from(&obj["Position"]),
+            Ok(types::Vec3([1.0, 2.0, 3.0]))
+        );
+        assert_eq!(<&str>::try_from(&obj["Name"]), Ok("Link"));
+        assert_eq!(
+            f32::try_from(&obj["Life"]),
+            Err(crate::WrongParameterType {
+                expected: "f32",
+                found: types::ParameterType::Int,
+            })
+        );
+    }
+
+    #[test]
+    fn from_conversions() {
+        assert_eq!(Parameter::from(true), Parameter::Bool(true));
+        assert_eq!(Parameter::from(1.5f32), Parameter::F32(1.5));
+        assert_eq!(Parameter::from(-3), Parameter::Int(-3));
+        assert_eq!(Parameter::from(7u32), Parameter::U32(7));
+        assert_eq!(
+            Parameter::from("Link".to_owned()),
+            Parameter::StringRef("Link".to_owned())
+        );
+        assert_eq!(
+            Parameter::from("Link"),
+            Parameter::StringRef("Link".to_owned())
+        );
+        assert_eq!(
+            Parameter::from([1.0, 2.0]),
+            Parameter::Vec2(types::Vec2([1.0, 2.0]))
+        );
+        assert_eq!(
+            Parameter::from([1.0, 2.0, 3.0]),
+            Parameter::Vec3(types::Vec3([1.0, 2.0, 3.0]))
+        );
+        assert_eq!(
+            Parameter::from([1.0, 2.0, 3.0, 4.0]),
+            Parameter::Vec4(types::Vec4([1.0, 2.0, 3.0, 4.0]))
+        );
+        assert_eq!(
+            Parameter::from(types::Color([0.0, 0.5, 1.0, 1.0])),
+            Parameter::Color(types::Color([0.0, 0.5, 1.0, 1.0]))
+        );
+        assert_eq!(
+            Parameter::from(types::Quat([0.0, 0.0, 0.0, 1.0])),
+            Parameter::Quat(types::Quat([0.0, 0.0, 0.0, 1.0]))
+        );
+        let buffer = types::BufferU32 {
+            buffer: vec![1, 2, 3],
+        };
+        assert_eq!(
+            Parameter::from(buffer.clone()),
+            Parameter::BufferU32(buffer)
+        );
+    }
+
+    #[test]
+    fn contains_by_name() {
+        let pio = sample_pio();
+        assert!(pio.has_object("General"));
+        assert!(!pio.has_object("Child"));
+        assert!(pio.has_list("Child"));
+        assert!(!pio.has_list("General"));
+        let child = pio.list("Child").unwrap();
+        assert!(child.has_object("Param"));
+        assert!(!child.has_list("Param"));
+        assert!(pio.object("General").unwrap().contains("Life"));
+        assert!(!pio.object("General").unwrap().contains("Death"));
+    }
+
+    #[test]
+    fn big_endian_roundtrip() {
+        let pio = sample_pio();
+        let binary = pio
+            .to_binary_with_options(&WriteOptions {
+                endian: Some(super::Endian::Big),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            &binary[4..12],
+            &[0, 0, 0, 2, 0, 0, 0, super::FLAG_UTF8 as u8]
+        );
+        let parsed = ParameterIO::from_bytes(&binary).unwrap();
+        assert_eq!(parsed.flags, super::FLAG_UTF8);
+        assert!(parsed.lists == pio.lists && parsed.objects == pio.objects);
+        assert_eq!(parsed.to_binary().unwrap(), binary);
+        let reader = ParameterIOReader::new(&binary).unwrap();
+        let general = reader.root().unwrap().object("General").unwrap().unwrap();
+        assert_eq!(general.param("Life").unwrap(), Some(Parameter::Int(100)));
+        // Flipping the flag without swapping the bytes gives a nonsense format version
+        let mut flipped = pio.to_binary().unwrap();
+        flipped[8] &= !(super::FLAG_LITTLE_ENDIAN as u8);
+        assert!(matches!(
+            ParameterIO::from_bytes(&flipped),
+            Err(ParseError::UnsupportedVersion(_))
+        ));
+        let forced = ParseOptions {
+            endian: Some(super::Endian::Little),
+            ..Default::default()
+        };
+        assert!(
+            ParameterIO::from_binary_with_options(&mut std::io::Cursor::new(&binary), &forced)
+                .is_err()
+        );
+    }
+
+    /// Builds a file whose root list has a chain of `depth` lists nested below it, each list
+    /// entry directly after its parent's
+    fn nested_lists_binary(depth: u32) -> Vec<u8> {
+        let lists_size = (depth + 1) * 12;
+        let mut binary = b"AAMP".to_vec();
+        for field in &[2, 3, 0x34 + lists_size, 0, 4, depth + 1, 0, 0, 0, 0, 0] {
+            binary.extend_from_slice(&u32::to_le_bytes(*field));
+        }
+        binary.extend_from_slice(b"xml\0");
+        for i in 0..=depth {
+            let crc = if i == 0 { 2_767_637_356 } else { i };
+            binary.extend_from_slice(&crc.to_le_bytes());
+            let num_lists: u16 = if i < depth { 1 } else { 0 };
+            for field in &[3, num_lists, 0, 0] {
+                binary.extend_from_slice(&u16::to_le_bytes(*field));
+            }
+        }
+        binary
+    }
+
+    #[test]
+    fn deeply_nested_binary_errors() {
+        let binary = nested_lists_binary(5000);
+        assert!(matches!(
+            ParameterIO::from_binary(&mut std::io::Cursor::new(&binary)),
+            Err(ParseError::TooDeep(super::DEFAULT_MAX_DEPTH))
+        ));
+        let binary = nested_lists_binary(super::DEFAULT_MAX_DEPTH as u32);
+        let pio = ParameterIO::from_binary(&mut std::io::Cursor::new(&binary)).unwrap();
+        assert_eq!(pio.num_lists(), super::DEFAULT_MAX_DEPTH);
+        let options = ParseOptions {
+            max_depth: 10,
+            ..Default::default()
+        };
+        assert!(matches!(
+            ParameterIO::from_binary_with_options(&mut std::io::Cursor::new(&binary), &options),
+            Err(ParseError::TooDeep(10))
+        ));
+    }
+
+    /// Builds a binary whose lists come in pairs, with both lists of each pair pointing at the
+    /// next pair as their children, so that reading every reference would take 2^pairs steps
+    fn shared_children_binary(pairs: u32) -> Vec<u8> {
+        let num_lists = 1 + pairs * 2;
+        let mut binary = b"AAMP".to_vec();
+        for field in &[2, 3, 0x34 + num_lists * 12, 0, 4, num_lists, 0, 0, 0, 0, 0] {
+            binary.extend_from_slice(&u32::to_le_bytes(*field));
+        }
+        binary.extend_from_slice(b"xml\0");
+        let mut push_list = |crc: u32, lists_rel_offset: u16, num_lists: u16| {
+            binary.extend_from_slice(&crc.to_le_bytes());
+            for field in &[lists_rel_offset, num_lists, 0, 0] {
+                binary.extend_from_slice(&u16::to_le_bytes(*field));
+            }
+        };
+        push_list(2_767_637_356, 3, 2);
+        for i in 1..=pairs {
+            let num_children = if i < pairs { 2 } else { 0 };
+            push_list(i * 2, 6, num_children);
+            push_list(i * 2 + 1, 3, num_children);
+        }
+        binary
+    }
+
+    #[test]
+    fn repeated_list_offsets_error() {
+        let binary = shared_children_binary(40);
+        // Lists are read depth first, so the second list of the next-to-last pair is the first to
+        // reach a pair again
+        let repeated = 0x34 + 12 + 24 * 39;
+        assert!(matches!(
+            ParameterIO::from_bytes(&binary),
+            Err(ParseError::RepeatedOffset(offset)) if offset == repeated
+        ));
+        let reader = ParameterIOReader::new(&binary).unwrap();
+        assert!(matches!(
+            reader.root().unwrap().to_list(),
+            Err(ParseError::RepeatedOffset(offset)) if offset == repeated
+        ));
+        assert!(matches!(
+            reader.data_offsets(),
+            Err(ParseError::RepeatedOffset(offset)) if offset == repeated
+        ));
+        let pio = ParameterIO::from_bytes(&shared_children_binary(1)).unwrap();
+        assert_eq!(pio.num_lists(), 2);
+    }
+
+    #[test]
+    fn numeric_keys_roundtrip() {
+        let mut table = crate::names::NameTable::new(false);
+        for name in &["42", "042", "Say\"Hi", "'Quoted", "Key: Value", "#Hash"] {
+            table.add_name(name);
+        }
+        let mut pio = sample_pio();
+        let obj = &mut pio.objects[0];
+        obj.set_param("42", Parameter::Int(1));
+        obj.set_param(42, Parameter::Int(2));
+        obj.set_param("042", Parameter::Int(3));
+        obj.set_param("Say\"Hi", Parameter::Int(4));
+        obj.set_param("'Quoted", Parameter::Int(5));
+        obj.set_param("Key: Value", Parameter::Int(6));
+        obj.set_param("#Hash", Parameter::Int(7));
+        let text = pio.with_names(&table).to_text().unwrap();
+        assert!(text.contains("\"42\": 1"), "{}", text);
+        assert!(text.contains(" 42: 2"), "{}", text);
+        assert!(text.contains("\"042\": 3"), "{}", text);
+        let parsed =
+            ParameterIO::from_text_with_names(&text, &mut crate::names::NameTable::new(false))
+                .unwrap();
+        assert_eq!(parsed, pio);
+        let obj = &parsed.objects[0];
+        assert_eq!(obj.param("42"), Some(&Parameter::Int(1)));
+        assert_eq!(obj.param(42), Some(&Parameter::Int(2)));
+    }
+
+    #[test]
+    fn streamed_text_matches_to_text() {
+        let pio = sample_pio();
+        let mut table = crate::names::NameTable::new(false);
+        for name in &[
+            "Child", "Param", "General", "Life", "Scale", "Position", "Name",
+        ] {
+            table.add_name(name);
+        }
+        let mut streamed = vec![];
+        pio.write_text_with_names(&mut streamed, &table).unwrap();
+        let text = pio.with_names(&table).to_text().unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), text);
+        assert!(text.contains("General: !obj"), "{}", text);
+    }
+
+    #[test]
+    fn patch_binary_in_place() {
+        use crate::PatchError;
+        let pio = sample_pio();
+        let mut file = std::io::Cursor::new(pio.to_binary().unwrap());
+        let mut obj = ParameterObject::default();
+        obj.set_param("Life", Parameter::Int(200));
+        obj.set_param("Name", Parameter::StringRef("Enemy".to_owned()));
+        let mut patch = ParameterIO::default();
+        patch.set_object("General", obj.clone());
+        // Both objects share the same values, so both must be patched
+        let mut child = ParameterList::default();
+        child.set_object("Param", obj);
+        patch.set_list("Child", child);
+        assert_eq!(ParameterIO::patch_binary(&mut file, &patch).unwrap(), 4);
+        let expected = pio.merge(&patch);
+        let patched = file.into_inner();
+        assert_eq!(patched.len(), pio.to_binary().unwrap().len());
+        assert_eq!(ParameterIO::from_bytes(&patched).unwrap(), expected);
+
+        let try_patch = |obj: ParameterObject| {
+            let mut patch = ParameterIO::default();
+            patch.set_object("General", obj);
+            let mut file = std::io::Cursor::new(patched.clone());
+            let result = ParameterIO::patch_binary(&mut file, &patch);
+            assert_eq!(file.into_inner(), patched);
+            result
+        };
+        let mut obj = ParameterObject::default();
+        obj.set_param("Life", Parameter::Int(300));
+        assert!(matches!(try_patch(obj), Err(PatchError::SharedData(_))));
+        let mut obj = ParameterObject::default();
+        obj.set_param("Name", Parameter::StringRef("Enemy_Lizalfos".to_owned()));
+        assert!(matches!(try_patch(obj), Err(PatchError::ValueTooLarge(_))));
+        let mut obj = ParameterObject::default();
+        obj.set_param("Life", Parameter::F32(200.0));
+        assert!(matches!(
+            try_patch(obj),
+            Err(PatchError::TypeMismatch(_, types::ParameterType::Int, _))
+        ));
+        let mut obj = ParameterObject::default();
+        obj.set_param("Death", Parameter::Int(1));
+        assert!(matches!(try_patch(obj), Err(PatchError::NotFound(_))));
+    }
+
+    #[test]
+    fn write_binary_returns_size() {
+        let pio = sample_pio();
+        let mut bytes = vec![];
+        let written = pio.write_binary(&mut bytes).unwrap();
+        assert_eq!(written, bytes.len() as u64);
+        assert_eq!(bytes, pio.to_binary().unwrap());
+        let file_size = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+        assert_eq!(
+            written,
+            (file_size as usize + pio.unknown_section.len()) as u64
+        );
+    }
+
+    #[test]
+    fn streamed_binary_matches_to_binary() {
+        // Accepts at most a few bytes per call and cannot seek, so the writer has to emit
+        // everything in order
+        struct Trickle(Vec<u8>);
+        impl std::io::Write for Trickle {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let len = buf.len().min(3);
+                self.0.extend_from_slice(&buf[..len]);
+                Ok(len)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut docs = vec![sample_pio(), nested_pio(), ParameterIO::default()];
+        for file in glob("test/*.b*").unwrap().filter_map(|f| f.ok()) {
+            docs.push(ParameterIO::from_binary(&mut File::open(&file).unwrap()).unwrap());
+        }
+        for pio in docs {
+            let mut out = Trickle(vec![]);
+            let written = pio.write_binary(&mut out).unwrap();
+            assert_eq!(written, out.0.len() as u64);
+            assert_eq!(out.0, pio.to_binary().unwrap());
+        }
+    }
+
+    #[test]
+    fn float_formatting_is_uniform() {
+        let floats = [
+            (0.1, "0.1"),
+            (1e-4, "0.0001"),
+            (1e-5, "1e-05"),
+            (1e-7, "1e-07"),
+            (1.0, "1.0"),
+            (-0.0, "-0.0"),
+            (16_777_216.0, "16777216.0"),
+            (1e15, "1000000000000000.0"),
+            (1e16, "1e+16"),
+            (f32::MAX, "3.4028235e+38"),
+            (f32::MIN_POSITIVE, "1.1754944e-38"),
+        ];
+        for (float, text) in floats.iter() {
+            let params = [
+                (Parameter::F32(*float), text.to_string()),
+                (
+                    Parameter::Vec3(types::Vec3([*float; 3])),
+                    format!("!vec3 [{0}, {0}, {0}]", text),
+                ),
+                (
+                    Parameter::BufferF32(types::BufferF32 {
+                        buffer: vec![*float],
+                    }),
+                    format!("!buffer_f32 [{}]", text),
+                ),
+            ];
+            for (param, expected) in params.iter() {
+                assert_eq!(&param.to_string(), expected);
+            }
+            let curve = Parameter::Curve1(types::Curve1 {
+                curve: types::Curve {
+                    a: 0,
+                    b: 0,
+                    floats: [*float; 30],
+                },
+            });
+            assert!(curve.to_string().ends_with(&format!(", {}]", text)));
+            assert_eq!(
+                crate::yaml::parse::parse_float(text).unwrap().to_bits(),
+                float.to_bits()
+            );
+        }
+    }
+
+    #[test]
+    fn too_many_entries_errors() {
+        let mut pio = sample_pio();
+        for crc in 0..=u16::MAX as u32 {
+            pio.objects[0].set_param(crc, Parameter::Bool(true));
+        }
+        assert!(matches!(
+            pio.to_binary(),
+            Err(WriteError::TooManyEntries(crc, count))
+                if crc == hash_name("General") && count == pio.objects[0].params().len()
+        ));
+    }
+
+    #[test]
+    fn lazy_reader_lookups() {
+        let pio = sample_pio();
+        let binary = pio.to_binary().unwrap();
+        let reader = ParameterIOReader::new(&binary).unwrap();
+        assert_eq!(reader.pio_type(), "xml");
+        let root = reader.root().unwrap();
+        assert_eq!(root.num_lists(), 1);
+        let general = root.object("General").unwrap().unwrap();
+        assert_eq!(general.len(), pio.objects[0].params().len());
+        assert_eq!(general.param("Life").unwrap(), Some(Parameter::Int(100)));
+        assert_eq!(general.param("Missing").unwrap(), None);
+        assert_eq!(general.to_object().unwrap(), pio.objects[0]);
+        let child = root.list("Child").unwrap().unwrap();
+        assert_eq!(child.to_list().unwrap(), pio.lists[0]);
+        assert!(root.list("General").unwrap().is_none());
+        assert_eq!(root.list_at(0).unwrap().unwrap().crc(), hash_name("Child"));
+        assert!(root.list_at(1).unwrap().is_none());
+        assert_eq!(
+            root.object_at(0).unwrap().unwrap().crc(),
+            hash_name("General")
+        );
+        assert!(root.object_at(root.num_objects()).unwrap().is_none());
+        assert_eq!(reader.to_pio().unwrap(), pio);
+    }
+
+    #[test]
+    fn other_format_versions_rejected() {
+        let mut binary = sample_pio().to_binary().unwrap();
+        binary[4..8].copy_from_slice(&1u32.to_le_bytes());
+        assert!(matches!(
+            ParameterIO::from_binary(&mut std::io::Cursor::new(&binary)),
+            Err(ParseError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            ParameterIOReader::new(&binary),
+            Err(ParseError::UnsupportedVersion(1))
+        ));
+    }
+
+    #[cfg(all(feature = "serde", feature = "json"))]
+    #[test]
+    fn serde_roundtrip() {
+        let mut pio = sample_pio();
+        pio.flags = 1;
+        let json = serde_json::to_string(&pio).unwrap();
+        assert_eq!(serde_json::from_str::<ParameterIO>(&json).unwrap(), pio);
+        // Documents serialized before the flags and shared data were recorded still load
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let fields = value.as_object_mut().unwrap();
+        fields.remove("flags");
+        fields.remove("shared_data");
+        let old = serde_json::from_value::<ParameterIO>(value).unwrap();
+        assert_eq!(old.flags, super::FLAG_LITTLE_ENDIAN | super::FLAG_UTF8);
+    }
 }
@@ -0,0 +1,137 @@
+use super::yaml::parse::YamlParseError;
+use super::{Parameter, ParameterIO, ParameterList, ParameterObject};
+use indexmap::IndexMap;
+
+/// Controls how `ParameterIO::merge_with_policy` resolves a document merged on top of another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergePolicy {
+    /// Replace parameters present in both documents with the value from the one merged on top.
+    /// When false, existing values are kept. Defaults to true.
+    pub overwrite: bool,
+    /// Append buffer parameters present in both documents with the same type instead of
+    /// replacing or keeping them, for stacking edits that each add entries to a buffer. Defaults
+    /// to false.
+    pub concat_buffers: bool,
+    /// Add lists, objects, and parameters present only in the document merged on top. When
+    /// false, only those already present are merged into. Defaults to true.
+    pub create_missing: bool,
+}
+
+impl Default for MergePolicy {
+    fn default() -> MergePolicy {
+        MergePolicy {
+            overwrite: true,
+            concat_buffers: false,
+            create_missing: true,
+        }
+    }
+}
+
+impl ParameterIO {
+    /// Returns a new document with `other` merged on top of this one. Lists and objects are
+    /// merged recursively by hash, parameters in `other` replace or extend those in this
+    /// document, and lists, objects, and parameters present on only one side are kept.
+    pub fn merge(&self, other: &ParameterIO) -> ParameterIO {
+        self.merge_with_policy(other, MergePolicy::default())
+    }
+
+    /// Returns a new document with `other` merged on top of this one like `merge`, resolving
+    /// parameters present in both and entries missing from this document by the given policy
+    pub fn merge_with_policy(&self, other: &ParameterIO, policy: MergePolicy) -> ParameterIO {
+        let mut merged = self.clone();
+        merge_lists(&mut merged.lists, &other.lists, &policy);
+        merge_objects(&mut merged.objects, &other.objects, &policy);
+        merged
+    }
+
+    /// Parses a YAML patch, which may be a partial document, and merges it on top of this
+    /// document in place as `merge` does
+    pub fn merge_text(&mut self, patch_yaml: &str) -> Result<(), YamlParseError> {
+        let patch = ParameterIO::from_text(patch_yaml)?;
+        self.merge_many(std::iter::once(&patch));
+        Ok(())
+    }
+
+    /// Applies each patch in order on top of this document. Lists and objects are merged
+    /// recursively by hash, parameters in a patch replace or extend those already present, and
+    /// anything only present in this document is kept. Later patches take priority over earlier
+    /// ones.
+    pub fn merge_many<'a, I: IntoIterator<Item = &'a ParameterIO>>(&mut self, patches: I) {
+        let policy = MergePolicy::default();
+        for patch in patches {
+            merge_lists(&mut self.lists, &patch.lists, &policy);
+            merge_objects(&mut self.objects, &patch.objects, &policy);
+        }
+    }
+}
+
+pub(crate) fn merge_lists(
+    base: &mut IndexMap<u32, ParameterList>,
+    patch: &IndexMap<u32, ParameterList>,
+    policy: &MergePolicy,
+) {
+    for (crc, list) in patch {
+        match base.get_mut(crc) {
+            Some(base_list) => {
+                merge_lists(&mut base_list.lists, &list.lists, policy);
+                merge_objects(&mut base_list.objects, &list.objects, policy);
+            }
+            None if policy.create_missing => {
+                base.insert(*crc, list.clone());
+            }
+            None => (),
+        }
+    }
+}
+
+pub(crate) fn merge_objects(
+    base: &mut IndexMap<u32, ParameterObject>,
+    patch: &IndexMap<u32, ParameterObject>,
+    policy: &MergePolicy,
+) {
+    for (crc, obj) in patch {
+        match base.get_mut(crc) {
+            Some(base_obj) => {
+                for (param_crc, param) in obj.params() {
+                    match base_obj.params_mut().get_mut(param_crc) {
+                        Some(base_param) => merge_param(base_param, param, policy),
+                        None if policy.create_missing => {
+                            base_obj.params_mut().insert(*param_crc, param.clone());
+                        }
+                        None => (),
+                    }
+                }
+            }
+            None if policy.create_missing => {
+                base.insert(*crc, obj.clone());
+            }
+            None => (),
+        }
+    }
+}
+
+fn merge_param(base: &mut Parameter, patch: &Parameter, policy: &MergePolicy) {
+    let concatenated = policy.concat_buffers
+        && match (&mut *base, patch) {
+            (Parameter::BufferInt(a), Parameter::BufferInt(b)) => {
+                a.buffer.extend_from_slice(&b.buffer);
+                true
+            }
+            (Parameter::BufferF32(a), Parameter::BufferF32(b)) => {
+                a.buffer.extend_from_slice(&b.buffer);
+                true
+            }
+            (Parameter::BufferU32(a), Parameter::BufferU32(b)) => {
+                a.buffer.extend_from_slice(&b.buffer);
+                true
+            }
+            (Parameter::BufferBinary(a), Parameter::BufferBinary(b)) => {
+                a.buffer.extend_from_slice(&b.buffer);
+                true
+            }
+            _ => false,
+        };
+    if !concatenated && policy.overwrite {
+        *base = patch.clone();
+    }
+}
@@ -1,66 +1,272 @@
+use crate::{hash_name, Parameter, ParameterIO};
+#[cfg(feature = "global-names")]
 use cached::proc_macro::cached;
-use crc::{crc32, Hasher32};
+#[cfg(feature = "global-names")]
 use lazy_static::lazy_static;
 use metrohash::MetroHashMap;
+use std::borrow::Cow;
+#[cfg(feature = "global-names")]
+use std::cell::RefCell;
+use std::io::{self, BufRead};
+#[cfg(feature = "global-names")]
+use std::marker::PhantomData;
+#[cfg(feature = "global-names")]
 use std::sync::Mutex;
+use thiserror::Error;
 
 const NAMES: &str = include_str!("../data/botw_hashed_names.txt");
 const NUMBERED_NAMES: &str = include_str!("../data/botw_numbered_names.txt");
 
+#[cfg(feature = "global-names")]
 lazy_static! {
     static ref NUMBERED_NAME_LIST: Vec<String> =
         NUMBERED_NAMES.split('\n').map(|s| s.to_owned()).collect();
 }
 
-#[cached]
+/// Returns a name table containing the stock names. With the `global-names` feature the stock
+/// names are shared, so this is cheap.
+#[cfg_attr(feature = "global-names", cached)]
 pub fn get_default_name_table() -> NameTable {
     NameTable::new(true)
 }
 
+#[cfg(feature = "global-names")]
 lazy_static::lazy_static! {
-    pub(crate) static ref TABLE: Mutex<NameTable> = Mutex::new(get_default_name_table());
+    /// The stock names, shared read-only by every table created with them
+    static ref STOCK_TABLE: NameTable = NameTable {
+        table: stock_names(),
+        stock: None,
+    };
+}
+
+#[cfg(feature = "global-names")]
+thread_local! {
+    static SCOPED_TABLE: RefCell<Option<NameTable>> = const { RefCell::new(None) };
+}
+
+/// Runs a function with the name table active on this thread: the table of the innermost
+/// `scope` if there is one, otherwise the stock names. Nothing ever modifies the stock names, so
+/// this takes no lock.
+#[cfg(feature = "global-names")]
+pub(crate) fn with_table<T>(f: impl FnOnce(&NameTable) -> T) -> T {
+    SCOPED_TABLE.with(|scoped| match scoped.borrow().as_ref() {
+        Some(table) => f(table),
+        None => f(&STOCK_TABLE),
+    })
+}
+
+/// Returns a copy of the active name table with the string values of the given document added,
+/// for converting that document to text. Copying is cheap because the stock names are shared.
+#[cfg(feature = "global-names")]
+pub(crate) fn table_for(pio: &ParameterIO) -> NameTable {
+    let mut table = SCOPED_TABLE
+        .with(|scoped| scoped.borrow().clone())
+        .unwrap_or_else(|| NameTable::new(true));
+    table.add_string_values(pio);
+    table
+}
+
+/// Makes the given table the active name table on the current thread, in place of the stock
+/// names, until the returned guard is dropped. Everything that uses the global table, such as
+/// `ParameterIO::to_text` and `guess_name`, uses this table instead. Scopes can be nested;
+/// dropping a guard restores the table that was active before it.
+#[cfg(feature = "global-names")]
+pub fn scope(table: NameTable) -> ScopeGuard {
+    let previous = SCOPED_TABLE.with(|scoped| scoped.borrow_mut().replace(table));
+    ScopeGuard {
+        previous,
+        _not_send: PhantomData,
+    }
+}
+
+/// Restores the previously active name table when dropped. Created by `scope`.
+#[cfg(feature = "global-names")]
+pub struct ScopeGuard {
+    previous: Option<NameTable>,
+    _not_send: PhantomData<*const ()>,
+}
+
+#[cfg(feature = "global-names")]
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        SCOPED_TABLE.with(|scoped| *scoped.borrow_mut() = previous);
+    }
+}
+
+/// Returns every hash and name in the active name table, sorted by hash
+#[cfg(feature = "global-names")]
+pub fn dump_global_table() -> Vec<(u32, String)> {
+    let mut names: Vec<(u32, String)> = with_table(|table| {
+        table
+            .names()
+            .map(|(crc, name)| (crc, name.to_owned()))
+            .collect()
+    });
+    names.sort_unstable();
+    names
+}
+
+/// Returned by `NameTable::try_add_name` when a name's hash is already mapped to a different name
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{new:?} has the same hash ({crc}) as {existing:?}")]
+pub struct CollisionError {
+    pub crc: u32,
+    /// The name already in the table
+    pub existing: String,
+    /// The name that was being added
+    pub new: String,
+}
+
+fn stock_names() -> MetroHashMap<u32, String> {
+    NAMES
+        .split('\n')
+        .map(|name| (hash_name(name), name.to_owned()))
+        .collect()
 }
 
 #[derive(Clone)]
 pub struct NameTable {
     table: MetroHashMap<u32, String>,
+    /// The shared stock names, looked up after the names added to this table
+    #[cfg(feature = "global-names")]
+    stock: Option<&'static NameTable>,
 }
 
 impl NameTable {
+    #[cfg(feature = "global-names")]
     pub fn new(include_stock_names: bool) -> NameTable {
-        let mut m: MetroHashMap<u32, String> = MetroHashMap::default();
-        if include_stock_names {
-            let mut dig = crc32::Digest::new(crc::crc32::IEEE);
-            for name in NAMES.split('\n') {
-                dig.write(name.as_bytes());
-                m.insert(dig.sum32(), name.to_owned());
-                dig.reset();
-            }
+        NameTable {
+            table: MetroHashMap::default(),
+            stock: if include_stock_names {
+                Some(&STOCK_TABLE)
+            } else {
+                None
+            },
         }
-        NameTable { table: m }
+    }
+
+    #[cfg(not(feature = "global-names"))]
+    pub fn new(include_stock_names: bool) -> NameTable {
+        NameTable {
+            table: if include_stock_names {
+                stock_names()
+            } else {
+                MetroHashMap::default()
+            },
+        }
+    }
+
+    #[cfg(feature = "global-names")]
+    fn stock(&self) -> Option<&'static NameTable> {
+        self.stock
+    }
+
+    #[cfg(not(feature = "global-names"))]
+    fn stock(&self) -> Option<&'static NameTable> {
+        None
+    }
+
+    /// The stock names not overridden by names added to this table
+    fn stock_names(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.stock()
+            .into_iter()
+            .flat_map(|stock| stock.table.iter())
+            .filter(move |(crc, _)| !self.table.contains_key(crc))
+            .map(|(crc, name)| (*crc, name.as_str()))
     }
 
     pub fn add_name(self: &mut NameTable, name: &str) {
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(name.as_bytes());
-        self.table.insert(digest.sum32(), name.to_owned());
-        digest.reset();
+        self.table.insert(hash_name(name), name.to_owned());
+    }
+
+    /// Adds a name like `add_name`, but fails instead of replacing it if the hash is already
+    /// mapped to a different name. Adding a name already in the table succeeds.
+    pub fn try_add_name(&mut self, name: &str) -> Result<(), CollisionError> {
+        let crc = hash_name(name);
+        match self.get_name_ref(crc) {
+            Some(existing) if existing != name => Err(CollisionError {
+                crc,
+                existing: existing.to_owned(),
+                new: name.to_owned(),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                self.table.insert(crc, name.to_owned());
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads names from a reader, one per line, and adds each to the table. Blank lines are
+    /// skipped. Returns the number of names added.
+    pub fn add_names_from_reader<R: BufRead>(&mut self, reader: R) -> io::Result<usize> {
+        let mut count = 0;
+        for line in reader.lines() {
+            let line = line?;
+            let name = line.trim();
+            if !name.is_empty() {
+                self.add_name(name);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Adds the value of every string parameter in a document to the table as a possible name.
+    /// String values are often the names of other lists or objects.
+    pub fn add_string_values(&mut self, pio: &ParameterIO) {
+        for (_, _, param) in pio.iter_params() {
+            match param {
+                Parameter::String32(s)
+                | Parameter::String64(s)
+                | Parameter::String256(s)
+                | Parameter::StringRef(s) => self.add_name(s),
+                _ => (),
+            }
+        }
+    }
+
+    /// Iterates over every hash and name in the table, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.table
+            .iter()
+            .map(|(crc, name)| (*crc, name.as_str()))
+            .chain(self.stock_names())
+    }
+
+    /// The number of names in the table
+    pub fn len(&self) -> usize {
+        self.table.len() + self.stock_names().count()
+    }
+
+    /// Returns whether the table has no names
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty() && self.stock_names().next().is_none()
     }
 
     pub fn get_name(&self, crc: u32) -> Option<String> {
+        self.get_name_ref(crc).map(|name| name.to_owned())
+    }
+
+    /// Looks up the name for a CRC, borrowing it from the table
+    pub fn get_name_ref(&self, crc: u32) -> Option<&str> {
         match self.table.get(&crc) {
-            Some(s) => Some(s.to_owned()),
-            None => None,
+            Some(name) => Some(name),
+            None => self.stock().and_then(|stock| stock.get_name_ref(crc)),
         }
     }
-}
 
-lazy_static::lazy_static! {
-    static ref DIGEST: Mutex<crc32::Digest> = Mutex::new(crc32::Digest::new(crc32::IEEE));
+    /// Returns the name for a CRC if it is in the table, otherwise the CRC as a decimal string
+    pub(crate) fn name_or_crc(&self, crc: u32) -> Cow<'_, str> {
+        match self.get_name_ref(crc) {
+            Some(name) => Cow::Borrowed(name),
+            None => Cow::Owned(crc.to_string()),
+        }
+    }
 }
 
 fn test_names(parent: &str, idx: usize, crc: u32) -> Option<String> {
-    let mut digest = DIGEST.lock().unwrap();
     for i in &[idx, idx + 1] {
         for name in &[
             [parent, i.to_string().as_str()].join(""),
@@ -70,72 +276,242 @@ fn test_names(parent: &str, idx: usize, crc: u32) -> Option<String> {
             [parent, format!("{:03}", i).as_str()].join(""),
             [parent, "_", format!("{:03}", i).as_str()].join(""),
         ] {
-            digest.write(name.as_bytes());
-            if digest.sum32() == crc {
+            if hash_name(name) == crc {
                 return Some(name.to_owned());
             }
-            digest.reset();
         }
     }
     None
 }
 
-#[cached]
+/// Attempts to guess the name for a CRC from the name of its parent, looked up in the active
+/// table, and its index in the parent. Parsing does not add a document's string values to the
+/// global table, so a name that only appears as a string value in a parsed file is not found
+/// here; use a `NameTable` filled by `from_binary_with_names` and `guess_name_with_table`.
+#[cfg(feature = "global-names")]
 pub fn guess_name(crc: u32, parent_crc: u32, idx: usize) -> Option<String> {
-    let table = TABLE.lock().unwrap();
-    let parent = table.get_name(parent_crc);
-    drop(table);
-    match parent {
-        Some(parent_name) => {
-            let mut matched = test_names(&parent_name, idx, crc);
-            if matched.is_none() {
-                if &parent_name == "Children" {
-                    matched = test_names("Child", idx, crc);
-                }
-                if matched.is_none() {
-                    for suffix in &["s", "es", "List"] {
-                        if parent_name.ends_with(suffix) {
-                            matched = test_names(
-                                &parent_name[0..parent_name.len() - suffix.len()],
-                                idx,
-                                crc,
-                            );
-                            if matched.is_some() {
-                                break;
-                            }
-                        }
+    let parent = with_table(|table| table.get_name(parent_crc));
+    guess_name_from_parent(crc, parent, idx)
+}
+
+/// Attempts to guess the name for a CRC like `guess_name`, but looks up the parent name in the
+/// given table instead of the global one.
+pub fn guess_name_with_table(
+    table: &NameTable,
+    crc: u32,
+    parent_crc: u32,
+    idx: usize,
+) -> Option<String> {
+    guess_name_from_parent(crc, table.get_name(parent_crc), idx)
+}
+
+#[cfg_attr(feature = "global-names", cached)]
+fn guess_name_from_parent(crc: u32, parent: Option<String>, idx: usize) -> Option<String> {
+    parent
+        .and_then(|parent_name| guess_from_parent_name(&parent_name, idx, crc))
+        .or_else(|| try_numbered_name(idx, crc))
+}
+
+fn guess_from_parent_name(parent_name: &str, idx: usize, crc: u32) -> Option<String> {
+    let mut matched = test_names(parent_name, idx, crc);
+    if matched.is_none() {
+        if parent_name == "Children" {
+            matched = test_names("Child", idx, crc);
+        }
+        if matched.is_none() {
+            for suffix in &["s", "es", "List"] {
+                if parent_name.ends_with(suffix) {
+                    matched =
+                        test_names(&parent_name[0..parent_name.len() - suffix.len()], idx, crc);
+                    if matched.is_some() {
+                        break;
                     }
                 }
             }
-            match matched {
-                Some(s) => Some(s),
-                None => try_numbered_name(idx, crc),
+        }
+    }
+    matched
+}
+
+/// Describes how the name for a CRC is resolved during YAML emission
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameSource {
+    /// The name is in the name table, either as a stock name or one added since
+    Stock,
+    /// The name was guessed from the parent's name and the index, e.g. `Child_3` in `Children`
+    Guessed,
+    /// The name was found from the list of numbered name patterns. These depend on the index and
+    /// are the least reliable.
+    Numbered,
+    /// No name could be found, so the CRC is used as-is
+    Unknown,
+}
+
+/// Determines how the name for a CRC with the given parent CRC and index would be resolved,
+/// using the active name table
+#[cfg(feature = "global-names")]
+pub fn classify(crc: u32, parent_crc: u32, idx: usize) -> NameSource {
+    with_table(|table| classify_with_table(table, crc, parent_crc, idx))
+}
+
+/// Determines how the name for a CRC would be resolved like `classify`, but using the given
+/// table instead of the global one
+pub fn classify_with_table(table: &NameTable, crc: u32, parent_crc: u32, idx: usize) -> NameSource {
+    if table.get_name_ref(crc).is_some() {
+        return NameSource::Stock;
+    }
+    if table
+        .get_name_ref(parent_crc)
+        .and_then(|parent_name| guess_from_parent_name(parent_name, idx, crc))
+        .is_some()
+    {
+        NameSource::Guessed
+    } else if try_numbered_name(idx, crc).is_some() {
+        NameSource::Numbered
+    } else {
+        NameSource::Unknown
+    }
+}
+
+/// Returns whether the name for a CRC would only be resolved from the numbered name patterns
+#[cfg(feature = "global-names")]
+pub fn is_numbered_name(crc: u32, parent_crc: u32, idx: usize) -> bool {
+    classify(crc, parent_crc, idx) == NameSource::Numbered
+}
+
+/// The highest index the numbered name patterns are kept formatted for. Larger indices are rare,
+/// and are scanned on each lookup instead so the index stays small.
+#[cfg(feature = "global-names")]
+const MAX_INDEXED: usize = 255;
+
+/// The numbered name patterns formatted with every index so far, up to `MAX_INDEXED`, by hash.
+/// Each entry is kept as the pattern's position in the list and the index it was formatted with
+/// rather than as the name itself, which is only formatted again for a hit.
+#[cfg(feature = "global-names")]
+struct NumberedNameIndex {
+    names: MetroHashMap<u32, (u16, u32)>,
+    /// Any further entries for hashes shared by more than one formatted name
+    collisions: MetroHashMap<u32, Vec<(u16, u32)>>,
+    /// The number of indices the patterns have been formatted with
+    len: usize,
+}
+
+#[cfg(feature = "global-names")]
+impl NumberedNameIndex {
+    fn new() -> NumberedNameIndex {
+        NumberedNameIndex {
+            names: MetroHashMap::default(),
+            collisions: MetroHashMap::default(),
+            len: 0,
+        }
+    }
+
+    fn insert(&mut self, crc: u32, entry: (u16, u32)) {
+        if let Some(existing) = self.names.insert(crc, entry) {
+            self.names.insert(crc, existing);
+            self.collisions.entry(crc).or_default().push(entry);
+        }
+    }
+
+    /// Formats every pattern with each index up to and including `max`, if not done already.
+    /// Names without a number pattern are the same for every index and are only added once.
+    fn extend_to(&mut self, max: usize) {
+        let max = max.min(MAX_INDEXED);
+        for i in self.len..=max {
+            for (pos, name) in NUMBERED_NAME_LIST.iter().enumerate() {
+                if name.contains('{') {
+                    self.insert(hash_name(&rt_format(name, i)), (pos as u16, i as u32));
+                } else if i == 0 {
+                    self.insert(hash_name(name), (pos as u16, 0));
+                }
             }
         }
-        None => try_numbered_name(idx, crc),
+        self.len = self.len.max(max + 1);
+    }
+
+    /// Finds the name for a hash among the patterns formatted with indices up to `max`. Where
+    /// several match, the pattern latest in the list wins, and then the highest index.
+    fn find(&mut self, crc: u32, max: usize) -> Option<String> {
+        self.extend_to(max);
+        let indexed = self.names.get(&crc).and_then(|first| {
+            std::iter::once(first)
+                .chain(self.collisions.get(&crc).into_iter().flatten())
+                .filter(|(_, i)| *i as usize <= max)
+                .max()
+                .copied()
+        });
+        let (pos, i) = indexed.max(Self::scan(crc, max))?;
+        let name = &NUMBERED_NAME_LIST[pos as usize];
+        Some(if name.contains('{') {
+            rt_format(name, i as usize)
+        } else {
+            name.to_owned()
+        })
+    }
+
+    /// Formats the number patterns with the indices past `MAX_INDEXED` up to `max` without
+    /// keeping them, returning the best match the same way as `find`
+    fn scan(crc: u32, max: usize) -> Option<(u16, u32)> {
+        if max <= MAX_INDEXED {
+            return None;
+        }
+        use std::fmt::Write;
+        let mut buf = String::new();
+        NUMBERED_NAME_LIST
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(pos, name)| {
+                let (prefix, rest) = name.split_at(name.find('{')?);
+                let (spec, suffix) = rest.split_at(rest.find('}')? + 1);
+                let width = spec.trim_start_matches("{:").trim_end_matches('}');
+                let width = width.parse().unwrap_or(0);
+                (MAX_INDEXED + 1..=max)
+                    .rev()
+                    .find(|i| {
+                        buf.clear();
+                        buf.push_str(prefix);
+                        write!(buf, "{:01$}", i, width).unwrap();
+                        buf.push_str(suffix);
+                        hash_name(&buf) == crc
+                    })
+                    .map(|i| (pos as u16, i as u32))
+            })
     }
 }
 
-#[cached]
+#[cfg(feature = "global-names")]
+lazy_static! {
+    static ref NUMBERED_NAME_INDEX: Mutex<NumberedNameIndex> = Mutex::new(NumberedNameIndex::new());
+}
+
+#[cfg(feature = "global-names")]
+#[cached(size = 4096)]
 fn try_numbered_name(idx: usize, crc: u32) -> Option<String> {
-    let mut opt = Option::None;
-    let mut dig = crc32::Digest::new(crc32::IEEE);
-    for name in NUMBERED_NAME_LIST.iter() {
-        for i in 0..idx + 2 {
-            let maybe: String = if name.contains('{') {
-                rt_format(name, i)
-            } else {
-                name.to_owned()
-            };
-            dig.write(maybe.as_bytes());
-            if dig.sum32() == crc as u32 {
-                opt = Some(maybe);
-            }
-            dig.reset();
+    NUMBERED_NAME_INDEX.lock().unwrap().find(crc, idx + 1)
+}
+
+/// Finds a numbered name the same way as the indexed version, formatting each pattern on every
+/// call instead of keeping an index in global state
+#[cfg(not(feature = "global-names"))]
+fn try_numbered_name(idx: usize, crc: u32) -> Option<String> {
+    let mut best = None;
+    for name in NUMBERED_NAMES.split('\n') {
+        let found = if name.contains('{') {
+            (0..idx + 2)
+                .rev()
+                .map(|i| rt_format(name, i))
+                .find(|name| hash_name(name) == crc)
+        } else if hash_name(name) == crc {
+            Some(name.to_owned())
+        } else {
+            None
+        };
+        if found.is_some() {
+            best = found;
         }
-        dig.reset();
     }
-    opt
+    best
 }
 
 #[inline]
@@ -152,3 +528,226 @@ fn rt_format(name: &str, i: usize) -> String {
         unreachable!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "global-names")]
+    use super::{classify, dump_global_table, scope};
+    use super::{classify_with_table, get_default_name_table, NameSource};
+    use super::{CollisionError, NameTable};
+    use crate::hash_name;
+
+    #[cfg(feature = "global-names")]
+    #[test]
+    fn classify_names() {
+        assert_eq!(classify(hash_name("LinkTarget"), 0, 0), NameSource::Stock);
+        assert_eq!(
+            classify(hash_name("Child_1"), hash_name("Children"), 1),
+            NameSource::Guessed
+        );
+        assert_eq!(classify(hash_name("Check_3"), 0, 3), NameSource::Numbered);
+        assert_eq!(classify(0xDEADBEEF, 0, 0), NameSource::Unknown);
+    }
+
+    #[cfg(feature = "global-names")]
+    #[test]
+    fn scoped_table() {
+        let crc = hash_name("ScopedOnlyName");
+        let mut table = NameTable::new(false);
+        table.add_name("ScopedOnlyName");
+        {
+            let _guard = scope(table);
+            assert_eq!(classify(crc, 0, 0), NameSource::Stock);
+            {
+                let _inner = scope(NameTable::new(false));
+                assert_eq!(classify(crc, 0, 0), NameSource::Unknown);
+            }
+            assert_eq!(classify(crc, 0, 0), NameSource::Stock);
+        }
+        assert_eq!(classify(crc, 0, 0), NameSource::Unknown);
+    }
+
+    /// Guesses numbered names by scanning every pattern at every index, the way `guess_name` did
+    /// before it had an index, to check the index against
+    #[cfg(feature = "global-names")]
+    #[test]
+    fn numbered_names_match_scan() {
+        use super::{guess_name_with_table, rt_format, NAMES, NUMBERED_NAME_LIST};
+        use std::collections::HashSet;
+        const MAX_IDX: usize = 10;
+        let formatted: Vec<Vec<(u32, String)>> = NUMBERED_NAME_LIST
+            .iter()
+            .map(|name| {
+                (0..MAX_IDX + 2)
+                    .map(|i| {
+                        let name = if name.contains('{') {
+                            rt_format(name, i)
+                        } else {
+                            name.to_owned()
+                        };
+                        (hash_name(&name), name)
+                    })
+                    .collect()
+            })
+            .collect();
+        let all: HashSet<u32> = formatted.iter().flatten().map(|(crc, _)| *crc).collect();
+        let scan = |idx: usize, crc: u32| -> Option<String> {
+            if !all.contains(&crc) {
+                return None;
+            }
+            let mut best = None;
+            for names in &formatted {
+                if let Some((_, name)) = names[..idx + 2].iter().rev().find(|(c, _)| *c == crc) {
+                    best = Some(name.clone());
+                }
+            }
+            best
+        };
+        let table = NameTable::new(false);
+        let crcs = NAMES
+            .split('\n')
+            .map(hash_name)
+            .chain(all.iter().copied())
+            .collect::<HashSet<u32>>();
+        for idx in 0..=MAX_IDX {
+            for &crc in &crcs {
+                assert_eq!(
+                    guess_name_with_table(&table, crc, 0, idx),
+                    scan(idx, crc),
+                    "{} at index {}",
+                    crc,
+                    idx
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "global-names")]
+    #[test]
+    fn numbered_names_past_index() {
+        use super::{guess_name_with_table, rt_format, MAX_INDEXED};
+        use super::{NUMBERED_NAME_INDEX, NUMBERED_NAME_LIST};
+        let table = NameTable::new(false);
+        for name in NUMBERED_NAME_LIST.iter().filter(|name| name.contains('{')) {
+            let crc = hash_name(&rt_format(name, MAX_INDEXED + 2));
+            let found = guess_name_with_table(&table, crc, 0, MAX_INDEXED + 2);
+            assert_eq!(found.as_deref().map(hash_name), Some(crc), "{}", name);
+        }
+        let idx = MAX_INDEXED * 4;
+        assert_eq!(
+            guess_name_with_table(&table, hash_name(&format!("Check_{}", idx)), 0, idx),
+            Some(format!("Check_{}", idx))
+        );
+        assert_eq!(
+            guess_name_with_table(&table, hash_name("Check_3"), 0, idx),
+            Some("Check_3".to_owned())
+        );
+        assert!(NUMBERED_NAME_INDEX.lock().unwrap().len <= MAX_INDEXED + 1);
+    }
+
+    #[test]
+    fn classify_names_with_table() {
+        let table = get_default_name_table();
+        assert_eq!(
+            classify_with_table(&table, hash_name("LinkTarget"), 0, 0),
+            NameSource::Stock
+        );
+        assert_eq!(
+            classify_with_table(&table, hash_name("Child_1"), hash_name("Children"), 1),
+            NameSource::Guessed
+        );
+        assert_eq!(
+            classify_with_table(&table, hash_name("Check_3"), 0, 3),
+            NameSource::Numbered
+        );
+        assert_eq!(
+            classify_with_table(&table, 0xDEADBEEF, 0, 0),
+            NameSource::Unknown
+        );
+    }
+
+    #[test]
+    fn list_names() {
+        let mut table = NameTable::new(false);
+        assert!(table.is_empty());
+        table.add_name("Foo");
+        table.add_name("Bar");
+        table.add_name("Foo");
+        assert_eq!(table.len(), 2);
+        let mut names: Vec<(u32, &str)> = table.names().collect();
+        names.sort_unstable();
+        let mut expected = vec![(hash_name("Foo"), "Foo"), (hash_name("Bar"), "Bar")];
+        expected.sort_unstable();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn load_names_from_reader() {
+        let mut table = NameTable::new(false);
+        let text = "FirstName\n\n  SecondName  \r\nFirstName\n";
+        assert_eq!(table.add_names_from_reader(text.as_bytes()).unwrap(), 3);
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.get_name_ref(hash_name("FirstName")),
+            Some("FirstName")
+        );
+        assert_eq!(
+            table.get_name_ref(hash_name("SecondName")),
+            Some("SecondName")
+        );
+    }
+
+    #[cfg(feature = "global-names")]
+    #[test]
+    fn dump_scoped_table() {
+        let mut table = NameTable::new(false);
+        table.add_name("DumpedName");
+        let _guard = scope(table);
+        assert_eq!(
+            dump_global_table(),
+            vec![(hash_name("DumpedName"), "DumpedName".to_owned())]
+        );
+    }
+
+    #[test]
+    fn stock_names_are_overridable() {
+        let stock = NameTable::new(true);
+        let mut table = stock.clone();
+        assert_eq!(table.len(), stock.len());
+        assert_eq!(
+            table.get_name_ref(hash_name("LinkTarget")),
+            Some("LinkTarget")
+        );
+        table.add_name("LinkTarget");
+        table.add_name("NotAStockName");
+        assert_eq!(table.len(), stock.len() + 1);
+        assert_eq!(stock.get_name_ref(hash_name("NotAStockName")), None);
+        assert_eq!(
+            table
+                .names()
+                .filter(|(_, name)| *name == "LinkTarget")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn detect_collisions() {
+        // "plumless" and "buckeroo" are a known CRC32 collision
+        assert_eq!(hash_name("plumless"), hash_name("buckeroo"));
+        let mut table = NameTable::new(false);
+        assert_eq!(table.try_add_name("plumless"), Ok(()));
+        assert_eq!(table.try_add_name("plumless"), Ok(()));
+        assert_eq!(
+            table.try_add_name("buckeroo"),
+            Err(CollisionError {
+                crc: hash_name("plumless"),
+                existing: "plumless".to_owned(),
+                new: "buckeroo".to_owned(),
+            })
+        );
+        assert_eq!(table.get_name_ref(hash_name("plumless")), Some("plumless"));
+        table.add_name("buckeroo");
+        assert_eq!(table.get_name_ref(hash_name("plumless")), Some("buckeroo"));
+    }
+}
@@ -1,9 +1,11 @@
-use super::types;
-use super::{Parameter, ParameterIO, ParameterList, ParameterObject};
-use binread::{BinRead, NullString};
+use super::names::NameTable;
+use super::types::{self, ParameterType};
+use super::{Endian, Key, Parameter, ParameterIO, ParameterList, ParameterObject, FORMAT_VERSION};
+use binread::{BinRead, NullString, ReadOptions};
 use indexmap::IndexMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Cursor, Read, Seek, SeekFrom};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,67 +16,29 @@ pub enum ParseError {
     YamlParseError(#[from] crate::yaml::parse::YamlParseError),
     #[error(transparent)]
     IOError(#[from] std::io::Error),
-}
-
-#[derive(Debug, BinRead)]
-enum ParameterType {
-    Bool = 0,
-    F32,
-    Int,
-    Vec2,
-    Vec3,
-    Vec4,
-    Color,
-    String32,
-    String64,
-    Curve1,
-    Curve2,
-    Curve3,
-    Curve4,
-    BufferInt,
-    BufferF32,
-    String256,
-    Quat,
-    U32,
-    BufferU32,
-    BufferBinary,
-    StringRef,
-}
-
-impl TryFrom<u8> for ParameterType {
-    type Error = String;
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(ParameterType::Bool),
-            1 => Ok(ParameterType::F32),
-            2 => Ok(ParameterType::Int),
-            3 => Ok(ParameterType::Vec2),
-            4 => Ok(ParameterType::Vec3),
-            5 => Ok(ParameterType::Vec4),
-            6 => Ok(ParameterType::Color),
-            7 => Ok(ParameterType::String32),
-            8 => Ok(ParameterType::String64),
-            9 => Ok(ParameterType::Curve1),
-            10 => Ok(ParameterType::Curve2),
-            11 => Ok(ParameterType::Curve3),
-            12 => Ok(ParameterType::Curve4),
-            13 => Ok(ParameterType::BufferInt),
-            14 => Ok(ParameterType::BufferF32),
-            15 => Ok(ParameterType::String256),
-            16 => Ok(ParameterType::Quat),
-            17 => Ok(ParameterType::U32),
-            18 => Ok(ParameterType::BufferU32),
-            19 => Ok(ParameterType::BufferBinary),
-            20 => Ok(ParameterType::StringRef),
-            _ => Err(format!("Invalid parameter type: {}", value)),
-        }
-    }
+    #[error("Invalid type {0} for parameter {1}")]
+    InvalidParameterType(u8, u32),
+    #[error("Offset {0:#x} is outside the file")]
+    OffsetOutOfRange(u64),
+    #[error("Buffer of {0} bytes is not a multiple of 4 bytes")]
+    InvalidBufferLength(usize),
+    #[error("Header gives a file size of {0} bytes, but the file is {1} bytes")]
+    FileSizeMismatch(u64, u64),
+    #[error("Header sections end at {0:#x}, which does not match the file size of {1:#x}")]
+    InconsistentSections(u64, u64),
+    #[error("Lists are nested more than {0} levels deep")]
+    TooDeep(usize),
+    #[error("List at offset {0:#x} is reached more than once")]
+    RepeatedOffset(u64),
+    #[error("AAMP format version {0} is not supported, only version 2 is")]
+    UnsupportedVersion(u32),
 }
 
 #[derive(BinRead, Debug)]
-#[br(little, assert(version == 2 && flags & 1 == 1))]
+#[br(assert(format_version == FORMAT_VERSION))]
+#[allow(dead_code)]
 struct ParseHeader {
-    version: u32,
+    format_version: u32,
     flags: u32,
     file_size: u32,
     pio_version: u32,
@@ -88,7 +52,6 @@ struct ParseHeader {
 }
 
 #[derive(BinRead, Debug)]
-#[br(little)]
 struct ParseParameterList {
     crc: u32,
     lists_rel_offset: u16,
@@ -98,7 +61,6 @@ struct ParseParameterList {
 }
 
 #[derive(BinRead, Debug)]
-#[br(little)]
 struct ParseParameterObject {
     crc: u32,
     params_rel_offset: u16,
@@ -106,91 +68,677 @@ struct ParseParameterObject {
 }
 
 #[derive(BinRead, Debug)]
-#[br(little)]
 struct ParseParameter {
     crc: u32,
-    #[br(map = |x: [u8; 3]| u32::from_le_bytes([x[0], x[1], x[2], 0]))]
-    data_offset: u32,
-    #[br(map = |x: u8| ParameterType::try_from(x).expect(&format!("Invalid type for param {}", crc)))]
-    param_type: ParameterType,
+    /// The data offset in the low 24 bits and the type in the high 8, read as one word so that
+    /// the split is the same in either byte order
+    offset_and_type: u32,
 }
 
-#[derive(BinRead, Debug)]
-#[br(little, magic = b"AAMP")]
-struct ParseParameterIO {
-    header: ParseHeader,
-    pio_type: NullString,
+impl ParseParameter {
+    fn data_offset(&self) -> u32 {
+        self.offset_and_type & 0xFF_FFFF
+    }
+
+    fn param_type(&self) -> u8 {
+        (self.offset_and_type >> 24) as u8
+    }
 }
 
-#[derive(BinRead, Debug)]
-struct ParseBufferInt {
-    size: u32,
-    #[br(count=size)]
-    content: Vec<i32>,
+/// The default for `ParseOptions::max_depth`. Files from the game nest lists only a few levels
+/// deep.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Options controlling how a binary parameter IO document is read
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions {
+    /// Read `String32`, `String64`, and `String256` values across their full capacity instead of
+    /// stopping at the first null byte. Reading also stops at the start of the next string in
+    /// the string section, and trailing null padding is trimmed, so interior nulls are preserved.
+    pub preserve_interior_nulls: bool,
+    /// Record which parameters share their data with an earlier parameter in
+    /// `ParameterIO::shared_data`, so that writing with `WriteOptions::preserve_layout` can
+    /// reproduce the sharing. This takes a second pass over the parameter entries, so it is off
+    /// by default and `shared_data` is left empty.
+    pub preserve_layout: bool,
+    /// Check the header before reading anything else: the file size must match the length of
+    /// the reader, and the section sizes must add up to the file size. Fails with
+    /// `ParseError::FileSizeMismatch` or `ParseError::InconsistentSections` otherwise.
+    pub strict: bool,
+    /// The deepest lists may be nested below the root list before parsing fails with
+    /// `ParseError::TooDeep`, which bounds the recursion on deeply nested files. Files that reach
+    /// the same list more than once fail with `ParseError::RepeatedOffset` regardless. Defaults
+    /// to `DEFAULT_MAX_DEPTH`.
+    pub max_depth: usize,
+    /// The byte order to read the document in. Defaults to `None`, which takes it from the
+    /// `FLAG_LITTLE_ENDIAN` bit of the header flags.
+    pub endian: Option<Endian>,
 }
 
-#[derive(BinRead, Debug)]
-struct ParseBufferF32 {
-    size: u32,
-    #[br(count=size)]
-    content: Vec<f32>,
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            preserve_interior_nulls: false,
+            preserve_layout: false,
+            strict: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            endian: None,
+        }
+    }
 }
 
-#[derive(BinRead, Debug)]
-struct ParseBufferU32 {
-    size: u32,
-    #[br(count=size)]
-    content: Vec<u32>,
+struct ParseContext {
+    options: ParseOptions,
+    read_options: ReadOptions,
+    string_bounds: Vec<u64>,
+    len: u64,
+}
+
+impl ParseContext {
+    /// Reads a value in the document's byte order
+    fn read<T: BinRead<Args = ()>, R: Read + Seek>(&self, reader: &mut R) -> Result<T, ParseError> {
+        Ok(T::read_options(reader, &self.read_options, ())?)
+    }
 }
 
 #[derive(BinRead, Debug)]
-struct ParseBufferBinary {
-    size: u32,
-    #[br(count=size)]
-    content: Vec<u8>,
+#[br(magic = b"AAMP")]
+struct ParseParameterIO {
+    header: ParseHeader,
+    pio_type: NullString,
 }
 
 impl ParameterIO {
     /// Parses an AAMP Parameter IO document from its binary format. Takes any reader with the
     /// Read and Seek traits and returns a result containing a `ParameterIO` or a `ParseError`.
     pub fn from_binary<R: Read + Seek>(reader: &mut R) -> Result<ParameterIO, ParseError> {
-        let ppio: ParseParameterIO = ParseParameterIO::read(reader)?;
-        reader.seek(SeekFrom::Start((ppio.header.pio_offset + 0x30) as u64))?;
-        let parse_pio: ParseParameterList = ParseParameterList::read(reader)?;
-        let param_root: ParameterList =
-            ParameterList::from_parse_list(parse_pio, ppio.header.pio_offset + 0x30, reader)?;
+        ParameterIO::from_binary_with_options(reader, &ParseOptions::default())
+    }
+
+    /// Parses an AAMP Parameter IO document from its binary format, already in memory as a byte
+    /// slice. Otherwise the same as `from_binary`.
+    pub fn from_bytes(data: &[u8]) -> Result<ParameterIO, ParseError> {
+        ParameterIO::from_binary(&mut Cursor::new(data))
+    }
+
+    /// Parses an AAMP Parameter IO document from its binary format, first checking that the
+    /// sizes in the header are consistent with each other and with the length of the reader.
+    /// Otherwise the same as `from_binary`. See `ParseOptions::strict`.
+    pub fn from_binary_strict<R: Read + Seek>(reader: &mut R) -> Result<ParameterIO, ParseError> {
+        ParameterIO::from_binary_with_options(
+            reader,
+            &ParseOptions {
+                strict: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Parses an AAMP Parameter IO document from its binary format like `from_binary`, and adds
+    /// its string values to the given name table as possible names
+    pub fn from_binary_with_names<R: Read + Seek>(
+        reader: &mut R,
+        names: &mut NameTable,
+    ) -> Result<ParameterIO, ParseError> {
+        let pio = ParameterIO::from_binary(reader)?;
+        names.add_string_values(&pio);
+        Ok(pio)
+    }
+
+    /// Parses an AAMP Parameter IO document from its binary format using the given
+    /// `ParseOptions`. Otherwise the same as `from_binary`.
+    pub fn from_binary_with_options<R: Read + Seek>(
+        reader: &mut R,
+        options: &ParseOptions,
+    ) -> Result<ParameterIO, ParseError> {
+        let start = reader.stream_position()?;
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+        let read_options = read_options(detect_endian(reader, options)?);
+        let ppio = ParseParameterIO::read_options(reader, &read_options, ())?;
+        if options.strict {
+            check_sizes(&ppio.header, len - start)?;
+        }
+        let ctx = ParseContext {
+            options: *options,
+            read_options,
+            string_bounds: if options.preserve_interior_nulls {
+                string_bounds(&ppio.header, reader, &read_options)?
+            } else {
+                vec![]
+            },
+            len,
+        };
+        let root_offset = ppio.header.pio_offset as u64 + 0x30;
+        seek_checked(reader, root_offset, &ctx)?;
+        let parse_pio: ParseParameterList = ctx.read(reader)?;
+        let param_root: ParameterList = ParameterList::from_parse_list(
+            parse_pio,
+            root_offset,
+            0,
+            reader,
+            &ctx,
+            &mut HashSet::new(),
+        )?;
+        let unknown_section = read_unknown_section(&ppio.header, reader, &ctx)?;
+        let mut data_offsets = vec![];
+        if options.preserve_layout {
+            collect_data_offsets(
+                root_offset,
+                0,
+                reader,
+                &ctx,
+                &mut HashSet::new(),
+                &mut data_offsets,
+            )?;
+        }
         let pio = ParameterIO {
             version: ppio.header.pio_version,
+            flags: ppio.header.flags,
             pio_type: ppio.pio_type.to_string(),
             lists: param_root.lists,
             objects: param_root.objects,
+            unknown_section,
+            shared_data: shared_data(&data_offsets),
         };
         Ok(pio)
     }
 }
 
+/// Reads a binary parameter IO document lazily, straight from a byte slice. Only the header is
+/// read up front; list, object, and parameter entries are read when they are looked up, and
+/// parameter values only when requested, so pulling one object out of a large file does not
+/// materialize every string and buffer in it.
+pub struct ParameterIOReader<'a> {
+    data: &'a [u8],
+    version: u32,
+    flags: u32,
+    endian: Endian,
+    pio_type: String,
+    root_offset: u64,
+    ctx: ParseContext,
+}
+
+impl<'a> ParameterIOReader<'a> {
+    /// Reads the header of a binary parameter IO document
+    pub fn new(data: &'a [u8]) -> Result<ParameterIOReader<'a>, ParseError> {
+        ParameterIOReader::with_options(data, &ParseOptions::default())
+    }
+
+    /// Reads the header of a binary parameter IO document, reading everything after it with the
+    /// given `ParseOptions`
+    pub fn with_options(
+        data: &'a [u8],
+        options: &ParseOptions,
+    ) -> Result<ParameterIOReader<'a>, ParseError> {
+        let mut reader = Cursor::new(data);
+        let endian = detect_endian(&mut reader, options)?;
+        let read_options = read_options(endian);
+        let ppio = ParseParameterIO::read_options(&mut reader, &read_options, ())?;
+        if options.strict {
+            check_sizes(&ppio.header, data.len() as u64)?;
+        }
+        let ctx = ParseContext {
+            options: *options,
+            read_options,
+            string_bounds: if options.preserve_interior_nulls {
+                string_bounds(&ppio.header, &mut reader, &read_options)?
+            } else {
+                vec![]
+            },
+            len: data.len() as u64,
+        };
+        Ok(ParameterIOReader {
+            data,
+            version: ppio.header.pio_version,
+            flags: ppio.header.flags,
+            endian,
+            pio_type: ppio.pio_type.to_string(),
+            root_offset: ppio.header.pio_offset as u64 + 0x30,
+            ctx,
+        })
+    }
+
+    /// The document's own version, as in `ParameterIO::version`
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The flags in the header, as in `ParameterIO::flags`
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// The byte order the document is read in
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    /// The parameter IO type, as in `ParameterIO::pio_type`
+    pub fn pio_type(&self) -> &str {
+        &self.pio_type
+    }
+
+    /// Returns the root list (`param_root`)
+    pub fn root(&self) -> Result<ListReader<'_>, ParseError> {
+        ListReader::read(self, self.root_offset, 0)
+    }
+
+    /// Reads the whole document, the same as `ParameterIO::from_binary_with_options`
+    pub fn to_pio(&self) -> Result<ParameterIO, ParseError> {
+        ParameterIO::from_binary_with_options(&mut Cursor::new(self.data), &self.ctx.options)
+    }
+
+    /// The absolute data offset of every parameter, in write order
+    pub(crate) fn data_offsets(&self) -> Result<Vec<u64>, ParseError> {
+        let mut data_offsets = vec![];
+        collect_data_offsets(
+            self.root_offset,
+            0,
+            &mut Cursor::new(self.data),
+            &self.ctx,
+            &mut HashSet::new(),
+            &mut data_offsets,
+        )?;
+        Ok(data_offsets)
+    }
+
+    fn cursor(&self, offset: u64) -> Result<Cursor<&'a [u8]>, ParseError> {
+        let mut reader = Cursor::new(self.data);
+        seek_checked(&mut reader, offset, &self.ctx)?;
+        Ok(reader)
+    }
+}
+
+/// A list in a `ParameterIOReader`, whose contents are read on demand
+pub struct ListReader<'r> {
+    pio: &'r ParameterIOReader<'r>,
+    entry: ParseParameterList,
+    offset: u64,
+    depth: usize,
+}
+
+impl<'r> ListReader<'r> {
+    fn read(
+        pio: &'r ParameterIOReader<'r>,
+        offset: u64,
+        depth: usize,
+    ) -> Result<ListReader<'r>, ParseError> {
+        if depth > pio.ctx.options.max_depth {
+            return Err(ParseError::TooDeep(pio.ctx.options.max_depth));
+        }
+        let entry: ParseParameterList = pio.ctx.read(&mut pio.cursor(offset)?)?;
+        Ok(ListReader {
+            pio,
+            entry,
+            offset,
+            depth,
+        })
+    }
+
+    /// The hash of the list's name
+    pub fn crc(&self) -> u32 {
+        self.entry.crc
+    }
+
+    /// The number of lists directly in this list
+    pub fn num_lists(&self) -> usize {
+        self.entry.num_lists as usize
+    }
+
+    /// The number of objects directly in this list
+    pub fn num_objects(&self) -> usize {
+        self.entry.num_objs as usize
+    }
+
+    /// Returns the child list at the given index, or None if the index is out of range
+    pub fn list_at(&self, index: usize) -> Result<Option<ListReader<'r>>, ParseError> {
+        if index >= self.num_lists() {
+            return Ok(None);
+        }
+        let offset = self.offset + (self.entry.lists_rel_offset as u64 * 4) + (12 * index as u64);
+        ListReader::read(self.pio, offset, self.depth + 1).map(Some)
+    }
+
+    /// Returns the object at the given index, or None if the index is out of range
+    pub fn object_at(&self, index: usize) -> Result<Option<ObjectReader<'r>>, ParseError> {
+        if index >= self.num_objects() {
+            return Ok(None);
+        }
+        let offset = self.offset + (self.entry.objs_rel_offset as u64 * 4) + (8 * index as u64);
+        ObjectReader::read(self.pio, offset).map(Some)
+    }
+
+    /// Finds a child list by name or hash, reading only the list entries
+    pub fn list<'k>(&self, key: impl Into<Key<'k>>) -> Result<Option<ListReader<'r>>, ParseError> {
+        let crc = key.into().hash();
+        for i in 0..self.num_lists() {
+            let list = self.list_at(i)?.expect("index is within num_lists");
+            if list.crc() == crc {
+                return Ok(Some(list));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds an object by name or hash, reading only the object entries
+    pub fn object<'k>(
+        &self,
+        key: impl Into<Key<'k>>,
+    ) -> Result<Option<ObjectReader<'r>>, ParseError> {
+        let crc = key.into().hash();
+        for i in 0..self.num_objects() {
+            let obj = self.object_at(i)?.expect("index is within num_objects");
+            if obj.crc() == crc {
+                return Ok(Some(obj));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the list and everything in it
+    pub fn to_list(&self) -> Result<ParameterList, ParseError> {
+        let mut reader = self.pio.cursor(self.offset)?;
+        let entry: ParseParameterList = self.pio.ctx.read(&mut reader)?;
+        ParameterList::from_parse_list(
+            entry,
+            self.offset,
+            self.depth,
+            &mut reader,
+            &self.pio.ctx,
+            &mut HashSet::new(),
+        )
+    }
+}
+
+/// An object in a `ParameterIOReader`, whose parameters are read on demand
+pub struct ObjectReader<'r> {
+    pio: &'r ParameterIOReader<'r>,
+    entry: ParseParameterObject,
+    offset: u64,
+}
+
+impl<'r> ObjectReader<'r> {
+    fn read(pio: &'r ParameterIOReader<'r>, offset: u64) -> Result<ObjectReader<'r>, ParseError> {
+        let entry: ParseParameterObject = pio.ctx.read(&mut pio.cursor(offset)?)?;
+        Ok(ObjectReader { pio, entry, offset })
+    }
+
+    /// The hash of the object's name
+    pub fn crc(&self) -> u32 {
+        self.entry.crc
+    }
+
+    /// The number of parameters in the object
+    pub fn len(&self) -> usize {
+        self.entry.num_params as usize
+    }
+
+    /// Returns whether the object has no parameters
+    pub fn is_empty(&self) -> bool {
+        self.entry.num_params == 0
+    }
+
+    /// Finds a parameter by hash and reads its value along with the absolute offset of its data
+    pub(crate) fn locate_param(&self, crc: u32) -> Result<Option<(u64, Parameter)>, ParseError> {
+        for i in 0..self.len() as u64 {
+            let offset = self.offset + (self.entry.params_rel_offset as u64 * 4) + (8 * i);
+            let mut reader = self.pio.cursor(offset)?;
+            let param: ParseParameter = self.pio.ctx.read(&mut reader)?;
+            if param.crc == crc {
+                let data_offset = offset + (param.data_offset() as u64 * 4);
+                return Parameter::from_parse_param(param, offset, &mut reader, &self.pio.ctx)
+                    .map(|value| Some((data_offset, value)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds a parameter by name or hash and reads its value, reading no other values
+    pub fn param<'k>(&self, key: impl Into<Key<'k>>) -> Result<Option<Parameter>, ParseError> {
+        Ok(self
+            .locate_param(key.into().hash())?
+            .map(|(_, param)| param))
+    }
+
+    /// Reads the object and all of its parameters
+    pub fn to_object(&self) -> Result<ParameterObject, ParseError> {
+        let mut reader = self.pio.cursor(self.offset)?;
+        let entry: ParseParameterObject = self.pio.ctx.read(&mut reader)?;
+        ParameterObject::from_parse_obj(entry, self.offset, &mut reader, &self.pio.ctx)
+    }
+}
+
+/// Works out the byte order of the document, from `ParseOptions::endian` or else from the
+/// `FLAG_LITTLE_ENDIAN` bit of the flags, and checks the format version following the magic in
+/// that byte order, so that files in other versions of the format fail with
+/// `ParseError::UnsupportedVersion`. Leaves the reader where it was.
+fn detect_endian<R: Read + Seek>(
+    reader: &mut R,
+    options: &ParseOptions,
+) -> Result<Endian, ParseError> {
+    let start = reader.stream_position()?;
+    let mut bytes = [0; 12];
+    let read = reader.read(&mut bytes)?;
+    reader.seek(SeekFrom::Start(start))?;
+    if read < 12 || &bytes[..4] != b"AAMP" {
+        // Leave the error to the header parser
+        return Ok(options.endian.unwrap_or(Endian::Little));
+    }
+    let word = |i: usize| [bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]];
+    // Little-endian files have the flag in their first byte of the flags, while the first
+    // byte of the flags in big-endian files holds no known flags
+    let endian = options
+        .endian
+        .unwrap_or_else(|| Endian::from_flags(u32::from_le_bytes(word(8))));
+    let version = match endian {
+        Endian::Little => u32::from_le_bytes(word(4)),
+        Endian::Big => u32::from_be_bytes(word(4)),
+    };
+    if version != FORMAT_VERSION {
+        return Err(ParseError::UnsupportedVersion(version));
+    }
+    Ok(endian)
+}
+
+fn read_options(endian: Endian) -> ReadOptions {
+    ReadOptions {
+        endian: match endian {
+            Endian::Little => binread::Endian::Little,
+            Endian::Big => binread::Endian::Big,
+        },
+        ..Default::default()
+    }
+}
+
+/// Checks that the section sizes in the header add up to the file size, and that the file size
+/// matches the actual length. The reserved section may come after the end given by the file size,
+/// as it does in files written by this library.
+fn check_sizes(header: &ParseHeader, len: u64) -> Result<(), ParseError> {
+    let sections_end = 0x30
+        + header.pio_offset as u64
+        + header.num_lists as u64 * 12
+        + header.num_objects as u64 * 8
+        + header.num_params as u64 * 8
+        + header.data_section_size as u64
+        + header.string_section_size as u64;
+    let reserved_end = sections_end + header.idk_section_size as u64;
+    let file_size = header.file_size as u64;
+    if file_size != sections_end && file_size != reserved_end {
+        return Err(ParseError::InconsistentSections(sections_end, file_size));
+    }
+    if len != file_size && len != reserved_end {
+        return Err(ParseError::FileSizeMismatch(file_size, len));
+    }
+    Ok(())
+}
+
+/// Seeks to an absolute offset, failing instead of seeking past the end of the file
+fn seek_checked<R: Seek>(
+    reader: &mut R,
+    offset: u64,
+    ctx: &ParseContext,
+) -> Result<(), ParseError> {
+    if offset >= ctx.len {
+        return Err(ParseError::OffsetOutOfRange(offset));
+    }
+    reader.seek(SeekFrom::Start(offset))?;
+    Ok(())
+}
+
+/// Reads the reserved section following the string section. Files are sometimes shorter than the
+/// size given in the header, so only the bytes actually present are read.
+fn read_unknown_section<R: Read + Seek>(
+    header: &ParseHeader,
+    reader: &mut R,
+    ctx: &ParseContext,
+) -> Result<Vec<u8>, ParseError> {
+    let start = 0x30
+        + header.pio_offset as u64
+        + header.num_lists as u64 * 12
+        + header.num_objects as u64 * 8
+        + header.num_params as u64 * 8
+        + header.data_section_size as u64
+        + header.string_section_size as u64;
+    let end = (start + header.idk_section_size as u64).min(ctx.len);
+    if start >= end {
+        return Ok(vec![]);
+    }
+    reader.seek(SeekFrom::Start(start))?;
+    let mut section = vec![0; (end - start) as usize];
+    reader.read_exact(&mut section)?;
+    Ok(section)
+}
+
+/// Checks that a list is not nested too deeply and has not been reached before. In a valid file
+/// every list entry is reached exactly once, but a malformed one can point several entries at
+/// the same children, which would otherwise be read again for each of them.
+fn check_list(
+    offset: u64,
+    depth: usize,
+    ctx: &ParseContext,
+    visited: &mut HashSet<u64>,
+) -> Result<(), ParseError> {
+    if depth > ctx.options.max_depth {
+        return Err(ParseError::TooDeep(ctx.options.max_depth));
+    }
+    if !visited.insert(offset) {
+        return Err(ParseError::RepeatedOffset(offset));
+    }
+    Ok(())
+}
+
+/// Collects the absolute data offset of every parameter under the list at `offset`, in the order
+/// the writer lays parameters out: a list's objects first, then each of its child lists.
+fn collect_data_offsets<R: Read + Seek>(
+    offset: u64,
+    depth: usize,
+    reader: &mut R,
+    ctx: &ParseContext,
+    visited: &mut HashSet<u64>,
+    data_offsets: &mut Vec<u64>,
+) -> Result<(), ParseError> {
+    check_list(offset, depth, ctx, visited)?;
+    seek_checked(reader, offset, ctx)?;
+    let plist: ParseParameterList = ctx.read(reader)?;
+    for i in 0..plist.num_objs as u64 {
+        let obj_off = offset + (plist.objs_rel_offset as u64 * 4) + (8 * i);
+        seek_checked(reader, obj_off, ctx)?;
+        let obj: ParseParameterObject = ctx.read(reader)?;
+        for j in 0..obj.num_params as u64 {
+            let param_off = obj_off + (obj.params_rel_offset as u64 * 4) + (8 * j);
+            seek_checked(reader, param_off, ctx)?;
+            let param: ParseParameter = ctx.read(reader)?;
+            data_offsets.push(param_off + (param.data_offset() as u64 * 4));
+        }
+    }
+    for i in 0..plist.num_lists as u64 {
+        let list_off = offset + (plist.lists_rel_offset as u64 * 4) + (12 * i);
+        collect_data_offsets(list_off, depth + 1, reader, ctx, visited, data_offsets)?;
+    }
+    Ok(())
+}
+
+/// Maps each parameter whose data offset was already used by an earlier parameter to the index
+/// of that first parameter
+fn shared_data(data_offsets: &[u64]) -> IndexMap<usize, usize> {
+    let mut first: HashMap<u64, usize> = HashMap::new();
+    let mut shared = IndexMap::new();
+    for (i, offset) in data_offsets.iter().enumerate() {
+        match first.get(offset) {
+            Some(j) => {
+                shared.insert(i, *j);
+            }
+            None => {
+                first.insert(*offset, i);
+            }
+        }
+    }
+    shared
+}
+
+/// Collects the absolute offsets at which strings begin in the string section, plus the end of
+/// the section, so fixed strings can be read up to the next boundary.
+fn string_bounds<R: Read + Seek>(
+    header: &ParseHeader,
+    reader: &mut R,
+    options: &ReadOptions,
+) -> Result<Vec<u64>, ParseError> {
+    let params_start = 0x30
+        + header.pio_offset as u64
+        + header.num_lists as u64 * 12
+        + header.num_objects as u64 * 8;
+    let strings_start =
+        params_start + header.num_params as u64 * 8 + header.data_section_size as u64;
+    let strings_end = strings_start + header.string_section_size as u64;
+    let mut bounds = vec![strings_end];
+    reader.seek(SeekFrom::Start(params_start))?;
+    for i in 0..header.num_params as u64 {
+        let param: ParseParameter = ParseParameter::read_options(reader, options, ())?;
+        let data_offset = params_start + (i * 8) + (param.data_offset() as u64 * 4);
+        if (strings_start..strings_end).contains(&data_offset) {
+            bounds.push(data_offset);
+        }
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+    Ok(bounds)
+}
+
 impl ParameterList {
+    /// Reads a list at the given depth below the root list, along with everything in it.
+    /// `visited` holds the offsets of the lists read so far.
     fn from_parse_list<R: Read + Seek>(
         plist: ParseParameterList,
-        offset: u32,
+        offset: u64,
+        depth: usize,
         reader: &mut R,
+        ctx: &ParseContext,
+        visited: &mut HashSet<u64>,
     ) -> Result<ParameterList, ParseError> {
+        check_list(offset, depth, ctx, visited)?;
         let mut list_map: IndexMap<u32, ParameterList> = IndexMap::new();
         let mut obj_map: IndexMap<u32, ParameterObject> = IndexMap::new();
         if plist.num_lists > 0 {
             for i in 0..plist.num_lists {
-                let off = offset + (plist.lists_rel_offset as u32 * 4) + (12 * i as u32);
-                reader.seek(SeekFrom::Start(off as u64))?;
-                let list: ParseParameterList = ParseParameterList::read(reader)?;
-                list_map.insert(list.crc, ParameterList::from_parse_list(list, off, reader)?);
+                let off = offset + (plist.lists_rel_offset as u64 * 4) + (12 * i as u64);
+                seek_checked(reader, off, ctx)?;
+                let list: ParseParameterList = ctx.read(reader)?;
+                list_map.insert(
+                    list.crc,
+                    ParameterList::from_parse_list(list, off, depth + 1, reader, ctx, visited)?,
+                );
             }
         }
         if plist.num_objs > 0 {
             for i in 0..plist.num_objs {
-                let off = offset + (plist.objs_rel_offset as u32 * 4) + (8 * i as u32);
-                reader.seek(SeekFrom::Start(off as u64))?;
-                let obj: ParseParameterObject = ParseParameterObject::read(reader)?;
-                obj_map.insert(obj.crc, ParameterObject::from_parse_obj(obj, off, reader)?);
+                let off = offset + (plist.objs_rel_offset as u64 * 4) + (8 * i as u64);
+                seek_checked(reader, off, ctx)?;
+                let obj: ParseParameterObject = ctx.read(reader)?;
+                obj_map.insert(
+                    obj.crc,
+                    ParameterObject::from_parse_obj(obj, off, reader, ctx)?,
+                );
             }
         }
         Ok(ParameterList {
@@ -203,18 +751,19 @@ impl ParameterList {
 impl ParameterObject {
     fn from_parse_obj<R: Read + Seek>(
         pobj: ParseParameterObject,
-        offset: u32,
+        offset: u64,
         reader: &mut R,
+        ctx: &ParseContext,
     ) -> Result<ParameterObject, ParseError> {
         let mut param_map: IndexMap<u32, Parameter> = IndexMap::new();
         if pobj.num_params > 0 {
             for i in 0..pobj.num_params {
-                let off = offset + (pobj.params_rel_offset as u32 * 4) + (8 * i as u32);
-                reader.seek(SeekFrom::Start(off as u64))?;
-                let param: ParseParameter = ParseParameter::read(reader)?;
+                let off = offset + (pobj.params_rel_offset as u64 * 4) + (8 * i as u64);
+                seek_checked(reader, off, ctx)?;
+                let param: ParseParameter = ctx.read(reader)?;
                 param_map.insert(
                     param.crc,
-                    Parameter::from_parse_param(param, off as u32, reader)?,
+                    Parameter::from_parse_param(param, off, reader, ctx)?,
                 );
             }
         }
@@ -222,76 +771,88 @@ impl ParameterObject {
     }
 }
 
-fn add_parsed_string_to_table(string: &str) {
-    let mut table = crate::names::TABLE.lock().unwrap();
-    table.add_name(string);
+fn read_fixed_string<R: Read + Seek>(
+    reader: &mut R,
+    capacity: u64,
+    ctx: &ParseContext,
+) -> Result<String, ParseError> {
+    if !ctx.options.preserve_interior_nulls {
+        return Ok(NullString::read(reader)?.to_string());
+    }
+    let start = reader.stream_position()?;
+    let end = match ctx.string_bounds.iter().find(|b| **b > start) {
+        Some(bound) => (*bound).min(start + capacity),
+        None => start + capacity,
+    };
+    let mut bytes: Vec<u8> = Vec::with_capacity((end - start) as usize);
+    reader.by_ref().take(end - start).read_to_end(&mut bytes)?;
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn read_buffer<T: BinRead<Args = ()>, R: Read + Seek>(
+    reader: &mut R,
+    data_offset: u64,
+    ctx: &ParseContext,
+) -> Result<Vec<T>, ParseError> {
+    let size_offset = data_offset
+        .checked_sub(4)
+        .ok_or(ParseError::OffsetOutOfRange(data_offset))?;
+    seek_checked(reader, size_offset, ctx)?;
+    let size = ctx.read::<u32, _>(reader)? as u64;
+    let end = data_offset + size * std::mem::size_of::<T>() as u64;
+    if end > ctx.len {
+        return Err(ParseError::OffsetOutOfRange(end));
+    }
+    (0..size).map(|_| ctx.read(reader)).collect()
 }
 
 impl Parameter {
     fn from_parse_param<R: Read + Seek>(
         param: ParseParameter,
-        offset: u32,
+        offset: u64,
         reader: &mut R,
+        ctx: &ParseContext,
     ) -> Result<Parameter, ParseError> {
-        let data_offset = offset as u64 + (param.data_offset as u64 * 4);
-        reader.seek(SeekFrom::Start(data_offset))?;
-        match param.param_type {
-            ParameterType::Bool => Ok(Parameter::Bool(u8::read(reader)? == 1)),
-            ParameterType::F32 => Ok(Parameter::F32(f32::read(reader)?)),
-            ParameterType::Int => Ok(Parameter::Int(i32::read(reader)?)),
-            ParameterType::Vec2 => Ok(Parameter::Vec2(types::Vec2::read(reader)?)),
-            ParameterType::Vec3 => Ok(Parameter::Vec3(types::Vec3::read(reader)?)),
-            ParameterType::Vec4 => Ok(Parameter::Vec4(types::Vec4::read(reader)?)),
-            ParameterType::Color => Ok(Parameter::Color(types::Color::read(reader)?)),
-            ParameterType::String32 => {
-                let name = NullString::read(reader)?.to_string();
-                add_parsed_string_to_table(&name);
-                Ok(Parameter::String32(name))
-            }
-            ParameterType::String64 => {
-                let name = NullString::read(reader)?.to_string();
-                add_parsed_string_to_table(&name);
-                Ok(Parameter::String64(name))
-            }
-            ParameterType::Curve1 => Ok(Parameter::Curve1(types::Curve1::read(reader)?)),
-            ParameterType::Curve2 => Ok(Parameter::Curve2(types::Curve2::read(reader)?)),
-            ParameterType::Curve3 => Ok(Parameter::Curve3(types::Curve3::read(reader)?)),
-            ParameterType::Curve4 => Ok(Parameter::Curve4(types::Curve4::read(reader)?)),
-            ParameterType::BufferInt => {
-                reader.seek(SeekFrom::Current(-4))?;
-                Ok(Parameter::BufferInt(types::BufferInt {
-                    buffer: ParseBufferInt::read(reader)?.content,
-                }))
-            }
-            ParameterType::BufferF32 => {
-                reader.seek(SeekFrom::Current(-4))?;
-                Ok(Parameter::BufferF32(types::BufferF32 {
-                    buffer: ParseBufferF32::read(reader)?.content,
-                }))
-            }
+        let param_type = ParameterType::try_from(param.param_type())
+            .map_err(|_| ParseError::InvalidParameterType(param.param_type(), param.crc))?;
+        let data_offset = offset + (param.data_offset() as u64 * 4);
+        seek_checked(reader, data_offset, ctx)?;
+        match param_type {
+            ParameterType::Bool => Ok(Parameter::Bool(ctx.read::<u8, _>(reader)? == 1)),
+            ParameterType::F32 => Ok(Parameter::F32(ctx.read(reader)?)),
+            ParameterType::Int => Ok(Parameter::Int(ctx.read(reader)?)),
+            ParameterType::Vec2 => Ok(Parameter::Vec2(ctx.read(reader)?)),
+            ParameterType::Vec3 => Ok(Parameter::Vec3(ctx.read(reader)?)),
+            ParameterType::Vec4 => Ok(Parameter::Vec4(ctx.read(reader)?)),
+            ParameterType::Color => Ok(Parameter::Color(ctx.read(reader)?)),
+            ParameterType::String32 => Ok(Parameter::String32(read_fixed_string(reader, 32, ctx)?)),
+            ParameterType::String64 => Ok(Parameter::String64(read_fixed_string(reader, 64, ctx)?)),
+            ParameterType::Curve1 => Ok(Parameter::Curve1(ctx.read(reader)?)),
+            ParameterType::Curve2 => Ok(Parameter::Curve2(ctx.read(reader)?)),
+            ParameterType::Curve3 => Ok(Parameter::Curve3(ctx.read(reader)?)),
+            ParameterType::Curve4 => Ok(Parameter::Curve4(ctx.read(reader)?)),
+            ParameterType::BufferInt => Ok(Parameter::BufferInt(types::BufferInt {
+                buffer: read_buffer(reader, data_offset, ctx)?,
+            })),
+            ParameterType::BufferF32 => Ok(Parameter::BufferF32(types::BufferF32 {
+                buffer: read_buffer(reader, data_offset, ctx)?,
+            })),
             ParameterType::String256 => {
-                let name = NullString::read(reader)?.to_string();
-                add_parsed_string_to_table(&name);
-                Ok(Parameter::String256(name))
-            }
-            ParameterType::Quat => Ok(Parameter::Quat(types::Quat::read(reader)?)),
-            ParameterType::U32 => Ok(Parameter::U32(u32::read(reader)?)),
-            ParameterType::BufferU32 => {
-                reader.seek(SeekFrom::Current(-4))?;
-                Ok(Parameter::BufferU32(types::BufferU32 {
-                    buffer: ParseBufferU32::read(reader)?.content,
-                }))
-            }
-            ParameterType::BufferBinary => {
-                reader.seek(SeekFrom::Current(-4))?;
-                Ok(Parameter::BufferBinary(types::BufferBinary {
-                    buffer: ParseBufferBinary::read(reader)?.content,
-                }))
+                Ok(Parameter::String256(read_fixed_string(reader, 256, ctx)?))
             }
+            ParameterType::Quat => Ok(Parameter::Quat(ctx.read(reader)?)),
+            ParameterType::U32 => Ok(Parameter::U32(ctx.read(reader)?)),
+            ParameterType::BufferU32 => Ok(Parameter::BufferU32(types::BufferU32 {
+                buffer: read_buffer(reader, data_offset, ctx)?,
+            })),
+            ParameterType::BufferBinary => Ok(Parameter::BufferBinary(types::BufferBinary {
+                buffer: read_buffer(reader, data_offset, ctx)?,
+            })),
             ParameterType::StringRef => {
-                let name = NullString::read(reader)?.to_string();
-                add_parsed_string_to_table(&name);
-                Ok(Parameter::StringRef(name))
+                Ok(Parameter::StringRef(NullString::read(reader)?.to_string()))
             }
         }
     }
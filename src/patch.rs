@@ -0,0 +1,137 @@
+use super::parse::{ListReader, ParameterIOReader, ParseError};
+use super::types::ParameterType;
+use super::write::{get_param_type, write_param_value, writer_options, WriteError};
+use super::{ParameterIO, ParameterList, ParameterObject};
+use binwrite::WriterOption;
+use indexmap::IndexMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Seek, SeekFrom, Write};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PatchError {
+    #[error(transparent)]
+    ParseError(#[from] ParseError),
+    #[error(transparent)]
+    WriteError(#[from] WriteError),
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("No list, object, or parameter with hash {0} in the file")]
+    NotFound(u32),
+    #[error("Parameter {0} is a {1:?} in the file, not a {2:?}")]
+    TypeMismatch(u32, ParameterType, ParameterType),
+    #[error("New value of parameter {0} does not fit in the space of the old one")]
+    ValueTooLarge(u32),
+    /// Identical values are stored once and shared, so a value can only be patched if every
+    /// parameter sharing it is patched to the same new value
+    #[error("Parameter {0} shares its data with a parameter that is not patched the same way")]
+    SharedData(u32),
+}
+
+/// A pending rewrite of one value in the data or string section
+struct Rewrite {
+    crc: u32,
+    /// Where the bytes go, which is before the data offset for buffers, to cover the size prefix
+    start: u64,
+    bytes: Vec<u8>,
+    /// How many patched parameters share the value
+    params: usize,
+}
+
+impl ParameterIO {
+    /// Applies the parameter values in a patch document to a binary file in place, rewriting
+    /// only the bytes of the values that change rather than writing the whole file again. Every
+    /// list, object, and parameter in the patch must already exist in the file, and each new
+    /// value must have the same type as the old one and take up no more space: a string no
+    /// longer and a buffer no larger. Leftover bytes of the old value are zeroed. The patch's
+    /// version, type, and flags are ignored, and the file keeps its byte order. Nothing is
+    /// written unless every value can be patched. Returns the number of parameters whose values
+    /// changed.
+    pub fn patch_binary<F: Read + Write + Seek>(
+        file: &mut F,
+        patch: &ParameterIO,
+    ) -> Result<usize, PatchError> {
+        let start = file.stream_position()?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        let rewrites = {
+            let reader = ParameterIOReader::new(&data)?;
+            let mut rewrites = BTreeMap::new();
+            plan_list(
+                &reader.root()?,
+                &patch.lists,
+                &patch.objects,
+                &writer_options(reader.endian()),
+                &mut rewrites,
+            )?;
+            let mut sharing: HashMap<u64, usize> = HashMap::new();
+            for offset in reader.data_offsets()? {
+                *sharing.entry(offset).or_default() += 1;
+            }
+            for (offset, rewrite) in rewrites.iter() {
+                if sharing[offset] > rewrite.params {
+                    return Err(PatchError::SharedData(rewrite.crc));
+                }
+            }
+            rewrites
+        };
+        for rewrite in rewrites.values() {
+            file.seek(SeekFrom::Start(start + rewrite.start))?;
+            file.write_all(&rewrite.bytes)?;
+        }
+        Ok(rewrites.values().map(|rewrite| rewrite.params).sum())
+    }
+}
+
+/// Works out the rewrites for the patched values under a list, keyed by data offset
+fn plan_list(
+    list: &ListReader,
+    lists: &IndexMap<u32, ParameterList>,
+    objects: &IndexMap<u32, ParameterObject>,
+    options: &WriterOption,
+    rewrites: &mut BTreeMap<u64, Rewrite>,
+) -> Result<(), PatchError> {
+    for (crc, obj) in objects.iter() {
+        let reader = list.object(*crc)?.ok_or(PatchError::NotFound(*crc))?;
+        for (crc, new) in obj.0.iter() {
+            let (offset, old) = reader
+                .locate_param(*crc)?
+                .ok_or(PatchError::NotFound(*crc))?;
+            if &old == new {
+                continue;
+            }
+            let (old_type, new_type) = (get_param_type(&old), get_param_type(new));
+            if old_type != new_type {
+                return Err(PatchError::TypeMismatch(*crc, old_type, new_type));
+            }
+            let mut old_bytes = vec![];
+            write_param_value(&old, &mut old_bytes, options)?;
+            let mut bytes = vec![];
+            write_param_value(new, &mut bytes, options)?;
+            if bytes.len() > old_bytes.len() {
+                return Err(PatchError::ValueTooLarge(*crc));
+            }
+            bytes.resize(old_bytes.len(), 0);
+            match rewrites.get_mut(&offset) {
+                Some(rewrite) if rewrite.bytes == bytes => rewrite.params += 1,
+                Some(_) => return Err(PatchError::SharedData(*crc)),
+                None => {
+                    rewrites.insert(
+                        offset,
+                        Rewrite {
+                            crc: *crc,
+                            start: if new.is_buffer() { offset - 4 } else { offset },
+                            bytes,
+                            params: 1,
+                        },
+                    );
+                }
+            }
+        }
+    }
+    for (crc, sublist) in lists.iter() {
+        let reader = list.list(*crc)?.ok_or(PatchError::NotFound(*crc))?;
+        plan_list(&reader, &sublist.lists, &sublist.objects, options, rewrites)?;
+    }
+    Ok(())
+}
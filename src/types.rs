@@ -1,50 +1,117 @@
+use crate::ParseError;
 use binread::BinRead;
 use binwrite::BinWrite;
+use std::convert::TryFrom;
 
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone, Copy)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vec2(pub [f32; 2]);
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone, Copy)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vec3(pub [f32; 3]);
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone, Copy)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vec4(pub [f32; 4]);
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone, Copy)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color(pub [f32; 4]);
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone, Copy)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quat(pub [f32; 4]);
 
+/// The error returned when constructing a vector or curve from the wrong number of floats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Expected {expected} values, found {found}")]
+pub struct WrongValueCount {
+    pub expected: usize,
+    pub found: usize,
+}
+
+/// Collects exactly `N` floats from an iterator, failing if it yields any other number
+fn collect_floats<I: IntoIterator<Item = f32>, const N: usize>(
+    iter: I,
+) -> Result<[f32; N], WrongValueCount> {
+    let values: Vec<f32> = iter.into_iter().collect();
+    if values.len() != N {
+        return Err(WrongValueCount {
+            expected: N,
+            found: values.len(),
+        });
+    }
+    let mut floats = [0.0; N];
+    floats.copy_from_slice(&values);
+    Ok(floats)
+}
+
+macro_rules! impl_from_iter_checked {
+    ($($vector:ty),*) => {$(
+        impl $vector {
+            /// Creates a value from an iterator of floats. Fails with `WrongValueCount` if the
+            /// iterator does not yield exactly the number of components.
+            pub fn from_iter_checked<I: IntoIterator<Item = f32>>(
+                iter: I,
+            ) -> Result<Self, WrongValueCount> {
+                Ok(Self(collect_floats(iter)?))
+            }
+        }
+    )*};
+}
+
+impl_from_iter_checked!(Vec2, Vec3, Vec4, Color, Quat);
+
 #[derive(BinRead, Debug, Default, PartialEq, Clone, BinWrite)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Curve {
     pub a: u32,
     pub b: u32,
-    #[binread(count = 30)]
-    pub floats: Vec<f32>,
+    #[binwrite(with(write_curve_floats))]
+    pub floats: [f32; 30],
+}
+
+impl Curve {
+    /// Creates a curve from its two header values and an iterator of floats. Fails with
+    /// `WrongValueCount` if the iterator does not yield exactly 30 floats.
+    pub fn from_floats_checked<I: IntoIterator<Item = f32>>(
+        a: u32,
+        b: u32,
+        floats: I,
+    ) -> Result<Self, WrongValueCount> {
+        Ok(Curve {
+            a,
+            b,
+            floats: collect_floats(floats)?,
+        })
+    }
+}
+
+fn write_curve_floats<W: std::io::Write>(
+    floats: &[f32; 30],
+    writer: &mut W,
+    options: &binwrite::WriterOption,
+) -> std::io::Result<()> {
+    floats[..].write_options(writer, options)
 }
 
 #[derive(BinRead, Debug, PartialEq, Clone, BinWrite)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Curve1 {
     pub curve: Curve,
 }
 #[derive(BinRead, Debug, PartialEq, Clone, BinWrite)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Curve2 {
     pub curve1: Curve,
     pub curve2: Curve,
 }
 #[derive(BinRead, Debug, PartialEq, Clone, BinWrite)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Curve3 {
     pub curve1: Curve,
     pub curve2: Curve,
     pub curve3: Curve,
 }
 #[derive(BinRead, Debug, PartialEq, Clone, BinWrite)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Curve4 {
     pub curve1: Curve,
     pub curve2: Curve,
@@ -53,22 +120,165 @@ pub struct Curve4 {
 }
 
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BufferInt {
     pub buffer: Vec<i32>,
 }
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BufferF32 {
     pub buffer: Vec<f32>,
 }
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BufferU32 {
     pub buffer: Vec<u32>,
 }
 #[derive(BinRead, Debug, BinWrite, PartialEq, Clone)]
-#[binwrite(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BufferBinary {
     pub buffer: Vec<u8>,
 }
+
+macro_rules! impl_le_bytes_for_buffer {
+    ($buffer:ty, $item:ty) => {
+        impl $buffer {
+            /// Returns the raw little-endian bytes of the buffer contents, without the size prefix
+            pub fn to_le_bytes(&self) -> Vec<u8> {
+                self.buffer.iter().flat_map(|x| x.to_le_bytes()).collect()
+            }
+
+            /// Reads the buffer contents from raw little-endian bytes, without a size prefix.
+            /// Fails if the length is not a multiple of 4 bytes.
+            pub fn from_le_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+                if bytes.len() % 4 != 0 {
+                    return Err(ParseError::InvalidBufferLength(bytes.len()));
+                }
+                Ok(Self {
+                    buffer: bytes
+                        .chunks_exact(4)
+                        .map(|x| <$item>::from_le_bytes([x[0], x[1], x[2], x[3]]))
+                        .collect(),
+                })
+            }
+        }
+    };
+}
+
+impl_le_bytes_for_buffer!(BufferInt, i32);
+impl_le_bytes_for_buffer!(BufferF32, f32);
+impl_le_bytes_for_buffer!(BufferU32, u32);
+
+impl BufferBinary {
+    /// Returns the raw bytes of the buffer contents, without the size prefix
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        self.buffer.clone()
+    }
+
+    /// Creates a buffer from raw bytes, without a size prefix. Any length is accepted, so this
+    /// never fails; it returns a `Result` to match the other buffer types.
+    pub fn from_le_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(Self {
+            buffer: bytes.to_vec(),
+        })
+    }
+}
+
+/// The type of a parameter as stored in the binary format. Variants are ordered by their binary
+/// discriminant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParameterType {
+    Bool = 0,
+    F32,
+    Int,
+    Vec2,
+    Vec3,
+    Vec4,
+    Color,
+    String32,
+    String64,
+    Curve1,
+    Curve2,
+    Curve3,
+    Curve4,
+    BufferInt,
+    BufferF32,
+    String256,
+    Quat,
+    U32,
+    BufferU32,
+    BufferBinary,
+    StringRef,
+}
+
+const ALL_PARAMETER_TYPES: [ParameterType; 21] = [
+    ParameterType::Bool,
+    ParameterType::F32,
+    ParameterType::Int,
+    ParameterType::Vec2,
+    ParameterType::Vec3,
+    ParameterType::Vec4,
+    ParameterType::Color,
+    ParameterType::String32,
+    ParameterType::String64,
+    ParameterType::Curve1,
+    ParameterType::Curve2,
+    ParameterType::Curve3,
+    ParameterType::Curve4,
+    ParameterType::BufferInt,
+    ParameterType::BufferF32,
+    ParameterType::String256,
+    ParameterType::Quat,
+    ParameterType::U32,
+    ParameterType::BufferU32,
+    ParameterType::BufferBinary,
+    ParameterType::StringRef,
+];
+
+impl ParameterType {
+    /// Lists every parameter type in order of its binary discriminant
+    pub fn all() -> &'static [ParameterType] {
+        &ALL_PARAMETER_TYPES
+    }
+
+    /// The name of the type in snake case, e.g. `vec3` or `buffer_binary`. Each type has its own
+    /// name, spelled out in full. These are not the YAML and JSON tags, which cannot tell the
+    /// curve types apart (all are `!curve`) and abbreviate others (`!str32`, `!str` for
+    /// `string_ref`, `!u` for `u32`).
+    pub fn name(self) -> &'static str {
+        match self {
+            ParameterType::Bool => "bool",
+            ParameterType::F32 => "f32",
+            ParameterType::Int => "int",
+            ParameterType::Vec2 => "vec2",
+            ParameterType::Vec3 => "vec3",
+            ParameterType::Vec4 => "vec4",
+            ParameterType::Color => "color",
+            ParameterType::String32 => "string32",
+            ParameterType::String64 => "string64",
+            ParameterType::Curve1 => "curve1",
+            ParameterType::Curve2 => "curve2",
+            ParameterType::Curve3 => "curve3",
+            ParameterType::Curve4 => "curve4",
+            ParameterType::BufferInt => "buffer_int",
+            ParameterType::BufferF32 => "buffer_f32",
+            ParameterType::String256 => "string256",
+            ParameterType::Quat => "quat",
+            ParameterType::U32 => "u32",
+            ParameterType::BufferU32 => "buffer_u32",
+            ParameterType::BufferBinary => "buffer_binary",
+            ParameterType::StringRef => "string_ref",
+        }
+    }
+}
+
+impl TryFrom<u8> for ParameterType {
+    type Error = String;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        ALL_PARAMETER_TYPES
+            .get(value as usize)
+            .copied()
+            .ok_or_else(|| format!("Invalid parameter type: {}", value))
+    }
+}
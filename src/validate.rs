@@ -0,0 +1,204 @@
+use super::write::{writer_options, Prepared, WriteError, WriteOptions};
+use super::Endian;
+use super::{Parameter, ParameterIO, ParameterList, ParameterObject};
+use indexmap::IndexMap;
+use thiserror::Error;
+
+/// The hash of `param_root`, the root list
+const ROOT_CRC: u32 = 2_767_637_356;
+
+/// The largest distance in bytes from a parameter entry to its data that can be represented in the
+/// 24-bit offset (in 4-byte units) of the entry
+const MAX_DATA_OFFSET: usize = 0xFF_FFFF * 4;
+
+/// The largest distance in bytes from a list or object entry to its children that can be
+/// represented in the 16-bit offsets (in 4-byte units) of the entry
+const MAX_ENTRY_OFFSET: usize = u16::MAX as usize * 4;
+
+/// A problem that would prevent a document from being written as a valid binary file, found by
+/// `ParameterIO::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// The hashes of the lists, object, and parameter leading to the problem, starting below the
+    /// root list. Empty for a problem with the root list itself.
+    pub path: Vec<u32>,
+    pub kind: ValidationIssueKind,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssueKind {
+    /// A sized string needs room for its null terminator within its capacity
+    #[error("String of {len} bytes is too long for a capacity of {capacity} bytes")]
+    StringTooLong { capacity: usize, len: usize },
+    #[error("List has {0} child lists, more than the 65535 the format allows")]
+    TooManyLists(usize),
+    #[error("List has {0} objects, more than the 65535 the format allows")]
+    TooManyObjects(usize),
+    #[error("Object has {0} parameters, more than the 65535 the format allows")]
+    TooManyParams(usize),
+    /// The data written before a parameter's own pushes it too far from its entry
+    #[error("Data is {0} bytes from its entry, too far for its data offset to be represented")]
+    DataOffsetTooLarge(usize),
+    /// The entries written between a list or object and its children push them too far from it
+    #[error("Children are {0} bytes from their parent's entry, too far for its 16-bit offset")]
+    EntryOffsetTooLarge(usize),
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("param_root")?;
+        for crc in &self.path {
+            write!(f, "/{}", crc)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl From<ValidationIssue> for WriteError {
+    fn from(issue: ValidationIssue) -> WriteError {
+        let crc = issue.path.last().copied().unwrap_or(ROOT_CRC);
+        match issue.kind {
+            ValidationIssueKind::StringTooLong { len, .. } => WriteError::StringTooLong(crc, len),
+            ValidationIssueKind::TooManyLists(count)
+            | ValidationIssueKind::TooManyObjects(count)
+            | ValidationIssueKind::TooManyParams(count) => WriteError::TooManyEntries(crc, count),
+            ValidationIssueKind::DataOffsetTooLarge(_) => WriteError::DataOffsetTooLarge(crc),
+            ValidationIssueKind::EntryOffsetTooLarge(_) => WriteError::FileTooLarge,
+        }
+    }
+}
+
+impl ParameterIO {
+    /// Checks for problems that would stop the document from being written as a valid binary
+    /// file: sized strings too long for their type, lists and objects with more entries than
+    /// their 16-bit counts allow, lists and objects too far from their children for a 16-bit
+    /// offset, and parameters whose data, laid out as `to_binary` would, is too far from their
+    /// entry to be reached by a 24-bit offset. Returns every problem found, in document order,
+    /// each with the path to where it is.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let options = WriteOptions::default();
+        let prepared = self
+            .prepare(&options, &writer_options(Endian::from_flags(self.flags)))
+            .expect("the default options are valid and writing to memory cannot fail");
+        issues(self, &prepared, &options)
+    }
+}
+
+/// Finds the problems with a document laid out for writing with the given options. Sized strings
+/// are only checked if they would not be truncated, and string references only if their type is
+/// inferred.
+pub(crate) fn issues(
+    pio: &ParameterIO,
+    prepared: &Prepared<'_>,
+    options: &WriteOptions,
+) -> Vec<ValidationIssue> {
+    let list_offsets = prepared.list_offsets();
+    let mut validator = Validator {
+        options,
+        list_offsets: list_offsets.iter(),
+        object_offsets: prepared.object_offsets.iter(),
+        data_offsets: prepared.data_offsets.iter(),
+        issues: vec![],
+    };
+    validator.list(&mut vec![], &pio.lists, &pio.objects);
+    validator.issues
+}
+
+/// Walks a document in write order, which is depth first, alongside the distances from each
+/// list, object, and parameter entry to what it points to
+struct Validator<'a> {
+    options: &'a WriteOptions,
+    list_offsets: std::slice::Iter<'a, usize>,
+    object_offsets: std::slice::Iter<'a, usize>,
+    data_offsets: std::slice::Iter<'a, usize>,
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, path: &[u32], kind: ValidationIssueKind) {
+        self.issues.push(ValidationIssue {
+            path: path.to_vec(),
+            kind,
+        })
+    }
+
+    fn list(
+        &mut self,
+        path: &mut Vec<u32>,
+        lists: &IndexMap<u32, ParameterList>,
+        objects: &IndexMap<u32, ParameterObject>,
+    ) {
+        if lists.len() > u16::MAX as usize {
+            self.report(path, ValidationIssueKind::TooManyLists(lists.len()));
+        }
+        if objects.len() > u16::MAX as usize {
+            self.report(path, ValidationIssueKind::TooManyObjects(objects.len()));
+        }
+        if let Some(&offset) = self.list_offsets.next() {
+            if offset > MAX_ENTRY_OFFSET {
+                self.report(path, ValidationIssueKind::EntryOffsetTooLarge(offset));
+            }
+        }
+        for (crc, obj) in objects.iter() {
+            path.push(*crc);
+            self.object(path, obj);
+            path.pop();
+        }
+        for (crc, sublist) in lists.iter() {
+            path.push(*crc);
+            self.list(path, &sublist.lists, &sublist.objects);
+            path.pop();
+        }
+    }
+
+    fn object(&mut self, path: &mut Vec<u32>, obj: &ParameterObject) {
+        if obj.0.len() > u16::MAX as usize {
+            self.report(path, ValidationIssueKind::TooManyParams(obj.0.len()));
+        }
+        if let Some(&offset) = self.object_offsets.next() {
+            if offset > MAX_ENTRY_OFFSET {
+                self.report(path, ValidationIssueKind::EntryOffsetTooLarge(offset));
+            }
+        }
+        for (crc, param) in obj.0.iter() {
+            path.push(*crc);
+            if let Some(kind) = string_issue(param, self.options) {
+                self.report(path, kind);
+            }
+            if let Some(&offset) = self.data_offsets.next() {
+                if offset > MAX_DATA_OFFSET {
+                    self.report(path, ValidationIssueKind::DataOffsetTooLarge(offset));
+                }
+            }
+            path.pop();
+        }
+    }
+}
+
+/// Returns the value and capacity of a sized string parameter
+pub(crate) fn sized_string(param: &Parameter) -> Option<(&str, usize)> {
+    match param {
+        Parameter::String32(s) => Some((s, 32)),
+        Parameter::String64(s) => Some((s, 64)),
+        Parameter::String256(s) => Some((s, 256)),
+        _ => None,
+    }
+}
+
+/// Sized strings need room for their null terminator within their capacity, unless they are
+/// truncated, as do string references written as the largest sized string type
+fn string_issue(param: &Parameter, options: &WriteOptions) -> Option<ValidationIssueKind> {
+    let (s, capacity) = match param {
+        Parameter::StringRef(s) if options.infer_string_type => (s.as_str(), 256),
+        _ if options.truncate_strings => return None,
+        _ => sized_string(param)?,
+    };
+    if s.len() >= capacity {
+        Some(ValidationIssueKind::StringTooLong {
+            capacity,
+            len: s.len(),
+        })
+    } else {
+        None
+    }
+}
@@ -1,34 +1,40 @@
-use super::{Parameter, ParameterIO, ParameterList, ParameterObject};
-use binwrite::BinWrite;
+use super::types::ParameterType;
+use super::validate::{self, sized_string};
+use super::{
+    Endian, Parameter, ParameterIO, ParameterList, ParameterObject, FLAG_LITTLE_ENDIAN,
+    FORMAT_VERSION,
+};
+use binwrite::{BinWrite, WriterOption};
 use indexmap::IndexMap;
-use std::io::{Cursor, Read, Result, Seek, SeekFrom, Write};
-
-#[derive(Debug, Copy, Clone)]
-enum ParameterType {
-    Bool = 0,
-    F32,
-    Int,
-    Vec2,
-    Vec3,
-    Vec4,
-    Color,
-    String32,
-    String64,
-    Curve1,
-    Curve2,
-    Curve3,
-    Curve4,
-    BufferInt,
-    BufferF32,
-    String256,
-    Quat,
-    U32,
-    BufferU32,
-    BufferBinary,
-    StringRef,
+use std::collections::HashMap;
+use std::io::Write;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum WriteError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    /// Lists, objects, and parameters must start within 256 KiB (a 16-bit offset in 4-byte
+    /// units) of their parent
+    #[error("File is too large to be represented in the AAMP format")]
+    FileTooLarge,
+    /// Parameter data must start within 64 MiB (a 24-bit offset in 4-byte units) of its
+    /// parameter entry, which all the data written before it can push it past
+    #[error("Data of parameter {0} is too far from its entry for a 24-bit offset")]
+    DataOffsetTooLarge(u32),
+    #[error("String in parameter {0} is {1} bytes, too long for its type")]
+    StringTooLong(u32, usize),
+    #[error("Alignment of {0} bytes is not a power of two of at least 4")]
+    InvalidAlignment(usize),
+    /// A list can hold at most 65535 lists and 65535 objects, and an object at most 65535
+    /// parameters, as the counts are 16-bit
+    #[error("{0} has {1} entries, more than the format allows")]
+    TooManyEntries(u32, usize),
 }
 
-fn get_param_type(param: &Parameter) -> ParameterType {
+type Result<T> = std::result::Result<T, WriteError>;
+
+pub(crate) fn get_param_type(param: &Parameter) -> ParameterType {
     match param {
         Parameter::Bool(_) => ParameterType::Bool,
         Parameter::F32(_) => ParameterType::F32,
@@ -55,10 +61,9 @@ fn get_param_type(param: &Parameter) -> ParameterType {
 }
 
 #[derive(Debug, BinWrite)]
-#[binwrite(little)]
 struct WriteHeader<'a> {
     magic: &'a [u8; 4],
-    version: u32,
+    format_version: u32,
     flags: u32,
     file_size: u32,
     pio_version: u32,
@@ -72,7 +77,6 @@ struct WriteHeader<'a> {
 }
 
 #[derive(Debug, BinWrite)]
-#[binwrite(little)]
 struct WriteParameterList {
     crc: u32,
     lists_rel_offset: u16,
@@ -82,7 +86,6 @@ struct WriteParameterList {
 }
 
 #[derive(Debug, BinWrite)]
-#[binwrite(little)]
 struct WriteParameterObject {
     crc: u32,
     params_rel_offset: u16,
@@ -90,239 +93,473 @@ struct WriteParameterObject {
 }
 
 #[derive(Debug, BinWrite)]
-#[binwrite(little)]
 struct WriteParameter {
     crc: u32,
-    data_offset: [u8; 3],
-    #[binwrite(preprocessor(write_param_type))]
-    param_type: ParameterType,
+    /// The data offset in the low 24 bits and the type in the high 8
+    offset_and_type: u32,
 }
 
-#[derive(Debug, BinWrite, Clone)]
-#[binwrite(little)]
-struct WriteParamValue<'a>(&'a Parameter);
-
-#[allow(clippy::trivially_copy_pass_by_ref)]
-#[inline]
-fn write_param_type(ptype: &ParameterType) -> u8 {
-    *ptype as u8
+/// Options controlling how a parameter IO is written to its binary format
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions {
+    /// Share parameter data exactly as recorded in `ParameterIO::shared_data` when the document
+    /// was parsed with `ParseOptions::preserve_layout`, instead of sharing all identical values.
+    /// Together with an unchanged document, this reproduces the data layout of the original file.
+    pub preserve_layout: bool,
+    /// Write `StringRef` parameters as the smallest sized string type that fits them: `String32`
+    /// for up to 31 bytes, `String64` for up to 63, and `String256` for up to 255. Longer strings
+    /// fail with `WriteError::StringTooLong`.
+    pub infer_string_type: bool,
+    /// Cut `String32`, `String64`, and `String256` values too long for their type down to the
+    /// longest prefix that fits with its null terminator, on a character boundary, instead of
+    /// failing with `WriteError::StringTooLong`. Defaults to false.
+    pub truncate_strings: bool,
+    /// The alignment, relative to the start of the file, of the contents of buffer parameters
+    /// (after their size prefix). Must be a power of two of at least 4, otherwise writing fails
+    /// with `WriteError::InvalidAlignment`. Defaults to 4.
+    pub buffer_alignment: usize,
+    /// The byte order to write the document in. `FLAG_LITTLE_ENDIAN` is set or cleared in the
+    /// written flags to match. Defaults to `None`, which takes it from the
+    /// `FLAG_LITTLE_ENDIAN` bit of `ParameterIO::flags`, so parsed documents are written back in
+    /// their original byte order.
+    pub endian: Option<Endian>,
 }
 
-#[allow(clippy::trivially_copy_pass_by_ref)]
-#[inline]
-fn u24_offset(offset: &u32) -> [u8; 3] {
-    let bytes = offset.to_le_bytes();
-    [bytes[0], bytes[1], bytes[2]]
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            preserve_layout: false,
+            infer_string_type: false,
+            truncate_strings: false,
+            buffer_alignment: 4,
+            endian: None,
+        }
+    }
 }
 
 impl ParameterIO {
     /// Serializes an AAMP Parameter IO document to its binary format. Returns a result containing
-    /// a `Vec<u8>` or a boxed error.
+    /// a `Vec<u8>` or a `WriteError`.
     pub fn to_binary(&self) -> Result<Vec<u8>> {
-        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![]);
-        self.write_binary(&mut buffer)?;
+        self.to_binary_with_options(&WriteOptions::default())
+    }
+
+    /// Serializes an AAMP Parameter IO document to its binary format using the given
+    /// `WriteOptions`. Otherwise the same as `to_binary`.
+    pub fn to_binary_with_options(&self, options: &WriteOptions) -> Result<Vec<u8>> {
         let mut bytes: Vec<u8> = vec![];
-        buffer.seek(SeekFrom::Start(0))?;
-        buffer.read_to_end(&mut bytes)?;
+        self.write_binary_with_options(&mut bytes, options)?;
         Ok(bytes)
     }
 
-    /// Serializes an AAMP Parameter IO document to its binary format using a write implementing the
-    /// Write and Seek traits. Returns a result indicating success or a boxed error.
-    pub fn write_binary<W: Write + Seek>(&self, writer: &mut W) -> Result<()> {
-        let pio_type = format!("{}\0", self.pio_type);
-        let lists_size = (count_lists(&self.lists) + 1) * 12;
-        let objs_size = count_objs(&self.lists, self.objects.len()) * 8;
-        let params_size = count_params(&self.lists, &self.objects) * 8;
-        let mut list_buffer: Cursor<Vec<u8>> = Cursor::new(Vec::with_capacity(lists_size / 12));
-        let mut obj_buffer: Cursor<Vec<u8>> = Cursor::new(Vec::with_capacity(objs_size / 8));
-        let mut param_buffer: Cursor<Vec<u8>> = Cursor::new(Vec::with_capacity(params_size / 8));
-        let mut data_buffer: Cursor<Vec<u8>> = Cursor::new(vec![]);
-        WriteParameterList {
-            crc: 2_767_637_356,
-            lists_rel_offset: 3,
-            num_lists: self.lists.len() as u16,
-            objs_rel_offset: (lists_size / 4) as u16,
-            num_objs: self.objects.len() as u16,
-        }
-        .write(&mut list_buffer)?;
-        let all_params: Vec<(u32, &Parameter)> = write_list_contents(
-            0,
-            &self.lists,
-            &self.objects,
-            &mut list_buffer,
-            &mut obj_buffer,
-            &mut param_buffer,
-            lists_size,
-            objs_size,
-            params_size,
-        )?;
-        for (offset, param) in all_params.iter().filter(|(_, p)| !p.is_string()) {
-            write_param_data(param, *offset as usize, &mut param_buffer, &mut data_buffer)?;
-        }
-        let data_size = data_buffer.stream_len()? as usize;
-        for (offset, param) in all_params.iter().filter(|(_, p)| p.is_string()) {
-            write_param_string(param, *offset as usize, &mut param_buffer, &mut data_buffer)?;
+    /// Serializes an AAMP Parameter IO document to its binary format using a writer implementing
+    /// the Write trait. All offsets are computed up front, so the output is written in a single
+    /// forward pass. Returns a result containing the number of bytes written or a `WriteError`.
+    /// The count includes the reserved section at the end, which the file size in the header
+    /// does not. Because the format uses 24-bit data offsets, documents whose data section
+    /// exceeds 64 MiB cannot be written and fail with `WriteError::DataOffsetTooLarge`. Fails on
+    /// the first of the problems reported by `validate`.
+    pub fn write_binary<W: Write>(&self, writer: &mut W) -> Result<u64> {
+        self.write_binary_with_options(writer, &WriteOptions::default())
+    }
+
+    /// Serializes an AAMP Parameter IO document to its binary format using the given
+    /// `WriteOptions`. Otherwise the same as `write_binary`.
+    pub fn write_binary_with_options<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> Result<u64> {
+        let endian = options
+            .endian
+            .unwrap_or_else(|| Endian::from_flags(self.flags));
+        let flags = match endian {
+            Endian::Little => self.flags | FLAG_LITTLE_ENDIAN,
+            Endian::Big => self.flags & !FLAG_LITTLE_ENDIAN,
+        };
+        let write_options = writer_options(endian);
+        let prepared = self.prepare(options, &write_options)?;
+        // Everything that can fail is checked before the first byte is written
+        if let Some(issue) = validate::issues(self, &prepared, options)
+            .into_iter()
+            .next()
+        {
+            return Err(issue.into());
         }
-        let string_size = data_buffer.stream_len()? as usize - data_size;
-        let header = WriteHeader {
+        let Prepared {
+            layout,
+            pio_type,
+            data,
+            data_size,
+            list_offsets,
+            object_offsets,
+            data_offsets,
+        } = prepared;
+        let lists_size = layout.lists.len() * 12;
+        let objs_size = layout.objects.len() * 8;
+        let params_size = layout.params.len() * 8;
+        let pio_type_size = align(pio_type.len());
+        let string_size = data.len() - data_size;
+        let file_size = 0x30 + pio_type_size + lists_size + objs_size + params_size + data.len();
+
+        WriteHeader {
             magic: b"AAMP",
-            version: 2,
-            flags: 3,
-            file_size: (0x30
-                + align(pio_type.len() as u32) as u64
-                + list_buffer.stream_len()?
-                + obj_buffer.stream_len()?
-                + param_buffer.stream_len()?
-                + data_buffer.stream_len()?) as u32,
+            format_version: FORMAT_VERSION,
+            flags,
+            file_size: file_size as u32,
             pio_version: self.version,
-            pio_offset: align(pio_type.len() as u32),
-            num_lists: lists_size as u32 / 12,
-            num_objects: objs_size as u32 / 8,
-            num_params: params_size as u32 / 8,
+            pio_offset: pio_type_size as u32,
+            num_lists: layout.lists.len() as u32,
+            num_objects: layout.objects.len() as u32,
+            num_params: layout.params.len() as u32,
             data_section_size: data_size as u32,
             string_section_size: string_size as u32,
-            idk_section_size: 1,
-        };
-        header.write(writer)?;
-        pio_type.write(writer)?;
-        align_cursor(writer)?;
-        writer.write_all(list_buffer.get_ref())?;
-        writer.write_all(obj_buffer.get_ref())?;
-        writer.write_all(param_buffer.get_ref())?;
-        align_cursor(writer)?;
-        writer.write_all(data_buffer.get_ref())?;
-        writer.write_all(&[0])?;
-        Ok(())
-    }
-}
-
-#[allow(clippy::too_many_arguments)]
-fn write_list_contents<'a>(
-    list_offset: u64,
-    lists: &'a IndexMap<u32, ParameterList>,
-    objects: &'a IndexMap<u32, ParameterObject>,
-    list_buffer: &mut Cursor<Vec<u8>>,
-    obj_buffer: &mut Cursor<Vec<u8>>,
-    param_buffer: &mut Cursor<Vec<u8>>,
-    lists_size: usize,
-    objs_size: usize,
-    params_size: usize,
-) -> Result<Vec<(u32, &'a Parameter)>> {
-    let mut all_params: Vec<(u32, &Parameter)> = vec![];
-    let pos = list_buffer.stream_position()?;
-    if !objects.is_empty() {
-        list_buffer.set_position(list_offset + 8);
-        list_buffer.write_all(
-            &(((obj_buffer.stream_position()? + lists_size as u64 - list_offset) / 4) as u16)
-                .to_le_bytes(),
-        )?;
-        list_buffer.set_position(pos);
-        for (crc, obj) in objects.iter() {
+            idk_section_size: self.unknown_section.len() as u32,
+        }
+        .write_options(writer, &write_options)?;
+        writer.write_all(pio_type.as_bytes())?;
+        writer.write_all(&[0; 3][..pio_type_size - pio_type.len()])?;
+        for (list, (lists_offset, objs_offset)) in layout.lists.iter().zip(list_offsets) {
+            WriteParameterList {
+                crc: list.crc,
+                lists_rel_offset: (lists_offset / 4) as u16,
+                num_lists: list.num_lists as u16,
+                objs_rel_offset: (objs_offset / 4) as u16,
+                num_objs: list.num_objs as u16,
+            }
+            .write_options(writer, &write_options)?;
+        }
+        for (obj, params_offset) in layout.objects.iter().zip(object_offsets) {
             WriteParameterObject {
-                crc: *crc,
-                params_rel_offset: (((objs_size - obj_buffer.stream_position()? as usize)
-                    + param_buffer.stream_position()? as usize)
-                    / 4) as u16,
-                num_params: obj.0.len() as u16,
+                crc: obj.crc,
+                params_rel_offset: (params_offset / 4) as u16,
+                num_params: obj.num_params as u16,
             }
-            .write(obj_buffer)?;
-            for (crc, param) in obj.0.iter() {
-                all_params.push((param_buffer.stream_position()? as u32, param));
-                WriteParameter {
-                    crc: *crc,
-                    data_offset: [0, 0, 0],
-                    param_type: get_param_type(&param),
-                }
-                .write(param_buffer)?;
+            .write_options(writer, &write_options)?;
+        }
+        for ((crc, param), data_offset) in layout.params.iter().zip(data_offsets) {
+            let param_type = match param {
+                Parameter::StringRef(s) if options.infer_string_type => infer_string_type(s),
+                _ => get_param_type(param),
+            };
+            WriteParameter {
+                crc: *crc,
+                offset_and_type: (data_offset / 4) as u32 | (param_type as u32) << 24,
             }
+            .write_options(writer, &write_options)?;
         }
+        writer.write_all(&data)?;
+        writer.write_all(&self.unknown_section)?;
+        Ok((file_size + self.unknown_section.len()) as u64)
     }
-    if !lists.is_empty() {
-        let mut offset_map: IndexMap<u32, u64> = IndexMap::new();
-        list_buffer.set_position(list_offset + 4);
-        list_buffer.write_all(&(((pos - list_offset) / 4) as u16).to_le_bytes())?;
-        list_buffer.set_position(pos);
-        for (crc, sublist) in lists.iter() {
-            offset_map.insert(*crc, list_buffer.stream_position()?);
-            WriteParameterList {
-                crc: *crc,
-                lists_rel_offset: 0,
-                num_lists: sublist.lists.len() as u16,
-                objs_rel_offset: 0,
-                num_objs: sublist.objects.len() as u16,
+
+    /// Lays out the document's entries and builds its data and string sections, without checking
+    /// that the result can be represented
+    pub(crate) fn prepare(
+        &self,
+        options: &WriteOptions,
+        write_options: &WriterOption,
+    ) -> Result<Prepared<'_>> {
+        if options.buffer_alignment < 4 || !options.buffer_alignment.is_power_of_two() {
+            return Err(WriteError::InvalidAlignment(options.buffer_alignment));
+        }
+        let mut layout = Layout {
+            lists: Vec::with_capacity(count_lists(&self.lists) + 1),
+            objects: Vec::with_capacity(count_objs(&self.lists, self.objects.len())),
+            params: Vec::with_capacity(count_params(&self.lists, &self.objects)),
+            list_order: Vec::with_capacity(count_lists(&self.lists) + 1),
+        };
+        layout
+            .lists
+            .push(ListEntry::new(2_767_637_356, &self.lists, &self.objects));
+        layout_list(&mut layout, 0, &self.lists, &self.objects);
+        let lists_size = layout.lists.len() * 12;
+        let objs_size = layout.objects.len() * 8;
+        let params_size = layout.params.len() * 8;
+        let pio_type = format!("{}\0", self.pio_type);
+        let params_start = 0x30 + align(pio_type.len()) + lists_size + objs_size;
+
+        let mut section = DataSection {
+            base: params_start + params_size,
+            buffer_alignment: options.buffer_alignment,
+            write_options,
+            truncate_strings: options.truncate_strings,
+            data: vec![],
+            offsets: vec![None; layout.params.len()],
+            written: HashMap::new(),
+            shared: if options.preserve_layout {
+                Some(&self.shared_data)
+            } else {
+                None
+            },
+        };
+        for (i, (_, param)) in layout.params.iter().enumerate() {
+            if !param.is_string() {
+                section.write(i, param)?;
             }
-            .write(list_buffer)?;
         }
-        for (crc, sublist) in lists.iter() {
-            all_params.extend(write_list_contents(
-                offset_map[crc],
-                &sublist.lists,
-                &sublist.objects,
-                list_buffer,
-                obj_buffer,
-                param_buffer,
-                lists_size,
-                objs_size,
-                params_size,
-            )?);
+        let data_size = section.data.len();
+        section.written.clear();
+        for (i, (_, param)) in layout.params.iter().enumerate() {
+            if param.is_string() {
+                section.write(i, param)?;
+            }
         }
+        let list_offsets = layout
+            .lists
+            .iter()
+            .enumerate()
+            .map(|(i, list)| {
+                let offset = i * 12;
+                (
+                    if list.num_lists > 0 || i == 0 {
+                        list.lists_index * 12 - offset
+                    } else {
+                        0
+                    },
+                    if list.num_objs > 0 || i == 0 {
+                        lists_size + list.objs_index * 8 - offset
+                    } else {
+                        0
+                    },
+                )
+            })
+            .collect();
+        let object_offsets = layout
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, obj)| objs_size - i * 8 + obj.params_index * 8)
+            .collect();
+        let data_offsets = section
+            .offsets
+            .iter()
+            .zip(layout.params.iter())
+            .enumerate()
+            .map(|(i, (offset, (_, param)))| {
+                let pad = if param.is_buffer() { 4 } else { 0 };
+                params_size - i * 8 + offset.unwrap_or_default() + pad
+            })
+            .collect();
+        Ok(Prepared {
+            layout,
+            pio_type,
+            data: section.data,
+            data_size,
+            list_offsets,
+            object_offsets,
+            data_offsets,
+        })
     }
-    Ok(all_params)
 }
 
-fn write_param_data(
-    param: &Parameter,
-    parent_offset: usize,
-    param_buffer: &mut Cursor<Vec<u8>>,
-    data_buffer: &mut Cursor<Vec<u8>>,
-) -> Result<()> {
-    let offset_pad = if param.is_buffer() { 4 } else { 0 };
-    write_param_offset(
-        parent_offset,
-        data_buffer.stream_position().unwrap() as u32,
-        param_buffer,
-        offset_pad,
-    )?;
-    write_param_value(param, data_buffer)?;
-    align_cursor(data_buffer)?;
-    Ok(())
+/// A document laid out for writing, with its data and string sections built
+pub(crate) struct Prepared<'a> {
+    layout: Layout<'a>,
+    /// The pio type with its null terminator
+    pio_type: String,
+    /// The data section followed by the string section
+    data: Vec<u8>,
+    data_size: usize,
+    /// The distance in bytes from each list's entry to its child lists and to its objects, in
+    /// write order
+    list_offsets: Vec<(usize, usize)>,
+    /// The distance in bytes from each object's entry to its parameters, in write order, which
+    /// is also the order `validate` visits objects in
+    pub(crate) object_offsets: Vec<usize>,
+    /// The distance in bytes from each parameter's entry to its data, in write order, which is
+    /// also the order `validate` visits parameters in
+    pub(crate) data_offsets: Vec<usize>,
 }
 
-fn write_param_string(
-    param: &Parameter,
-    parent_offset: usize,
-    param_buffer: &mut Cursor<Vec<u8>>,
-    data_buffer: &mut Cursor<Vec<u8>>,
-) -> Result<()> {
-    write_param_offset(
-        parent_offset,
-        data_buffer.stream_position().unwrap() as u32,
-        param_buffer,
-        0,
-    )?;
-    write_param_value(param, data_buffer)?;
-    align_cursor(data_buffer)?;
-    Ok(())
+impl Prepared<'_> {
+    /// The larger of the distances from each list's entry to its child lists and objects, in the
+    /// order `validate` visits lists in: depth first, starting with the root list
+    pub(crate) fn list_offsets(&self) -> Vec<usize> {
+        self.layout
+            .list_order
+            .iter()
+            .map(|i| {
+                let (lists_offset, objs_offset) = self.list_offsets[*i];
+                lists_offset.max(objs_offset)
+            })
+            .collect()
+    }
 }
 
-fn write_param_offset(
-    parent_offset: usize,
-    param_offset: u32,
-    param_buffer: &mut Cursor<Vec<u8>>,
-    pad: usize,
-) -> Result<()> {
-    let param_pos = param_buffer.stream_position()?;
-    param_buffer.seek(SeekFrom::Start((parent_offset + 4) as u64))?;
-    let rel_offset =
-        (param_offset as usize + pad + param_buffer.stream_len()? as usize - parent_offset) as u32;
-    let red_offset = rel_offset / 4;
-    u24_offset(&red_offset).write(param_buffer)?;
-    param_buffer.seek(SeekFrom::Start(param_pos))?;
-    Ok(())
+pub(crate) fn writer_options(endian: Endian) -> WriterOption {
+    match endian {
+        Endian::Little => binwrite::writer_option_new!(endian: binwrite::Endian::Little),
+        Endian::Big => binwrite::writer_option_new!(endian: binwrite::Endian::Big),
+    }
+}
+
+/// Picks the smallest sized string type with room for a string and its null terminator. Longer
+/// strings are rejected by `validate::issues` before this is used.
+fn infer_string_type(string: &str) -> ParameterType {
+    match string.len() {
+        0..=31 => ParameterType::String32,
+        32..=63 => ParameterType::String64,
+        _ => ParameterType::String256,
+    }
+}
+
+/// The list, object, and parameter entries of a document in the order they are written
+struct Layout<'a> {
+    lists: Vec<ListEntry>,
+    objects: Vec<ObjectEntry>,
+    params: Vec<(u32, &'a Parameter)>,
+    /// The index of each list in `lists`, in depth-first order
+    list_order: Vec<usize>,
+}
+
+struct ListEntry {
+    crc: u32,
+    num_lists: usize,
+    lists_index: usize,
+    num_objs: usize,
+    objs_index: usize,
+}
+
+impl ListEntry {
+    fn new(
+        crc: u32,
+        lists: &IndexMap<u32, ParameterList>,
+        objects: &IndexMap<u32, ParameterObject>,
+    ) -> ListEntry {
+        ListEntry {
+            crc,
+            num_lists: lists.len(),
+            lists_index: 0,
+            num_objs: objects.len(),
+            objs_index: 0,
+        }
+    }
+}
+
+struct ObjectEntry {
+    crc: u32,
+    num_params: usize,
+    params_index: usize,
+}
+
+fn layout_list<'a>(
+    layout: &mut Layout<'a>,
+    index: usize,
+    lists: &'a IndexMap<u32, ParameterList>,
+    objects: &'a IndexMap<u32, ParameterObject>,
+) {
+    layout.list_order.push(index);
+    layout.lists[index].objs_index = layout.objects.len();
+    for (crc, obj) in objects.iter() {
+        layout.objects.push(ObjectEntry {
+            crc: *crc,
+            num_params: obj.0.len(),
+            params_index: layout.params.len(),
+        });
+        for (crc, param) in obj.0.iter() {
+            layout.params.push((*crc, param));
+        }
+    }
+    let lists_index = layout.lists.len();
+    layout.lists[index].lists_index = lists_index;
+    for (crc, sublist) in lists.iter() {
+        layout
+            .lists
+            .push(ListEntry::new(*crc, &sublist.lists, &sublist.objects));
+    }
+    for (i, sublist) in lists.values().enumerate() {
+        layout_list(layout, lists_index + i, &sublist.lists, &sublist.objects);
+    }
 }
 
-fn count_lists(lists: &IndexMap<u32, ParameterList>) -> usize {
+/// Returns a copy of a sized string parameter cut down to fit its type, or `None` if it already
+/// fits or is not a sized string
+fn truncate_string(param: &Parameter) -> Option<Parameter> {
+    let (s, capacity) = sized_string(param).filter(|(s, capacity)| s.len() >= *capacity)?;
+    let mut end = capacity - 1;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    let s = s[..end].to_owned();
+    Some(match param {
+        Parameter::String32(_) => Parameter::String32(s),
+        Parameter::String64(_) => Parameter::String64(s),
+        _ => Parameter::String256(s),
+    })
+}
+
+/// The data and string sections, built up one parameter value at a time
+struct DataSection<'a> {
+    /// The offset of the data section from the start of the file
+    base: usize,
+    buffer_alignment: usize,
+    write_options: &'a WriterOption,
+    truncate_strings: bool,
+    data: Vec<u8>,
+    /// The offset of each parameter's value, by index in write order
+    offsets: Vec<Option<usize>>,
+    /// Values already written, to share identical values
+    written: HashMap<Vec<u8>, usize>,
+    /// Recorded sharing to reproduce instead of sharing identical values
+    shared: Option<&'a IndexMap<usize, usize>>,
+}
+
+impl DataSection<'_> {
+    /// Writes a parameter value at the next aligned position unless it can share an earlier
+    /// value, and records its offset
+    fn write(&mut self, index: usize, param: &Parameter) -> Result<()> {
+        let truncated = if self.truncate_strings {
+            truncate_string(param)
+        } else {
+            None
+        };
+        let mut value: Vec<u8> = vec![];
+        write_param_value(
+            truncated.as_ref().unwrap_or(param),
+            &mut value,
+            self.write_options,
+        )?;
+        let existing = match self.shared {
+            // Only share if the value is still the same, in case the document was edited
+            Some(shared) => shared
+                .get(&index)
+                .and_then(|first| self.offsets[*first])
+                .filter(|offset| self.data.get(*offset..*offset + value.len()) == Some(&value)),
+            None => self.written.get(&value).copied(),
+        }
+        .filter(|offset| self.is_aligned(param, *offset));
+        let offset = match existing {
+            Some(offset) => offset,
+            None => {
+                let mut offset = align(self.data.len());
+                while !self.is_aligned(param, offset) {
+                    offset += 4;
+                }
+                self.data.resize(offset, 0);
+                self.data.extend_from_slice(&value);
+                if self.shared.is_none() {
+                    self.written.insert(value, offset);
+                }
+                offset
+            }
+        };
+        self.offsets[index] = Some(offset);
+        Ok(())
+    }
+
+    /// Checks whether a parameter's value can be placed at an offset. Buffer contents follow a
+    /// 4-byte size prefix and must meet the buffer alignment; everything else is 4-byte aligned.
+    fn is_aligned(&self, param: &Parameter, offset: usize) -> bool {
+        if param.is_buffer() {
+            (self.base + offset + 4) & (self.buffer_alignment - 1) == 0
+        } else {
+            offset & 3 == 0
+        }
+    }
+}
+
+pub(crate) fn count_lists(lists: &IndexMap<u32, ParameterList>) -> usize {
     //&ParameterList) -> usize {
     let sublist_lists: usize = lists
         .values()
@@ -331,7 +568,7 @@ fn count_lists(lists: &IndexMap<u32, ParameterList>) -> usize {
     lists.len() + sublist_lists
 }
 
-fn count_objs(lists: &IndexMap<u32, ParameterList>, objs: usize) -> usize {
+pub(crate) fn count_objs(lists: &IndexMap<u32, ParameterList>, objs: usize) -> usize {
     //&ParameterList) -> usize {
     let sublist_objs: usize = lists
         .values()
@@ -340,7 +577,7 @@ fn count_objs(lists: &IndexMap<u32, ParameterList>, objs: usize) -> usize {
     objs + sublist_objs
 }
 
-fn count_params(
+pub(crate) fn count_params(
     lists: &IndexMap<u32, ParameterList>,
     objects: &IndexMap<u32, ParameterObject>,
 ) -> usize {
@@ -359,54 +596,51 @@ fn count_params(
 }
 
 #[inline]
-fn align_cursor<W: Write + Seek>(buffer: &mut W) -> Result<()> {
-    let pos = buffer.seek(SeekFrom::Current(0))? as u32;
-    buffer.seek(SeekFrom::Start(align(pos) as u64))?;
-    Ok(())
-}
-
-#[inline]
-fn align(int: u32) -> u32 {
-    int + 4 - 1 - (int - 1) % 4
+fn align(int: usize) -> usize {
+    (int + 3) & !3
 }
 
-fn write_param_value(param: &Parameter, buffer: &mut Cursor<Vec<u8>>) -> Result<()> {
+pub(crate) fn write_param_value<W: Write>(
+    param: &Parameter,
+    buffer: &mut W,
+    options: &WriterOption,
+) -> Result<()> {
     match param {
-        Parameter::Bool(b) => (*b as u32).write(buffer)?,
-        Parameter::F32(f) => f.write(buffer)?,
-        Parameter::Int(i) => i.write(buffer)?,
-        Parameter::Vec2(v) => v.0.write(buffer)?,
-        Parameter::Vec3(v) => v.0.write(buffer)?,
-        Parameter::Vec4(v) => v.0.write(buffer)?,
-        Parameter::Color(v) => v.0.write(buffer)?,
+        Parameter::Bool(b) => (*b as u32).write_options(buffer, options)?,
+        Parameter::F32(f) => f.write_options(buffer, options)?,
+        Parameter::Int(i) => i.write_options(buffer, options)?,
+        Parameter::Vec2(v) => v.0.write_options(buffer, options)?,
+        Parameter::Vec3(v) => v.0.write_options(buffer, options)?,
+        Parameter::Vec4(v) => v.0.write_options(buffer, options)?,
+        Parameter::Color(v) => v.0.write_options(buffer, options)?,
         Parameter::String32(s)
         | Parameter::String64(s)
         | Parameter::String256(s)
         | Parameter::StringRef(s) => {
-            s.write(buffer)?;
+            s.write_options(buffer, options)?;
             buffer.write_all(b"\0")?;
         }
-        Parameter::Curve1(c) => c.write(buffer)?,
-        Parameter::Curve2(c) => c.write(buffer)?,
-        Parameter::Curve3(c) => c.write(buffer)?,
-        Parameter::Curve4(c) => c.write(buffer)?,
-        Parameter::Quat(v) => v.0.write(buffer)?,
-        Parameter::U32(u) => u.write(buffer)?,
+        Parameter::Curve1(c) => c.write_options(buffer, options)?,
+        Parameter::Curve2(c) => c.write_options(buffer, options)?,
+        Parameter::Curve3(c) => c.write_options(buffer, options)?,
+        Parameter::Curve4(c) => c.write_options(buffer, options)?,
+        Parameter::Quat(v) => v.0.write_options(buffer, options)?,
+        Parameter::U32(u) => u.write_options(buffer, options)?,
         Parameter::BufferU32(u) => {
-            (u.buffer.len() as u32).write(buffer)?;
-            u.write(buffer)?
+            (u.buffer.len() as u32).write_options(buffer, options)?;
+            u.write_options(buffer, options)?
         }
         Parameter::BufferInt(i) => {
-            (i.buffer.len() as u32).write(buffer)?;
-            i.write(buffer)?
+            (i.buffer.len() as u32).write_options(buffer, options)?;
+            i.write_options(buffer, options)?
         }
         Parameter::BufferF32(f) => {
-            (f.buffer.len() as u32).write(buffer)?;
-            f.write(buffer)?
+            (f.buffer.len() as u32).write_options(buffer, options)?;
+            f.write_options(buffer, options)?
         }
         Parameter::BufferBinary(b) => {
-            (b.buffer.len() as u32).write(buffer)?;
-            b.write(buffer)?
+            (b.buffer.len() as u32).write_options(buffer, options)?;
+            b.write_options(buffer, options)?
         }
     }
     Ok(())
@@ -1,93 +1,343 @@
-use crate::names;
-use crate::{Parameter, ParameterIO, ParameterList, ParameterObject};
+use super::parse::{is_null, key_crc, parse_float};
+use crate::names::{self, NameTable};
+use crate::{hash_name, Parameter, ParameterIO, ParameterList, ParameterObject};
+use indexmap::IndexMap;
 use std::error::Error;
 use std::io::{BufWriter, Write};
 
+/// Options controlling how a parameter IO is written as YAML
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ToTextOptions {
+    /// Parse each float back after formatting it and fail if the result is not bit-for-bit
+    /// identical to the original value, guaranteeing that the text output is lossless.
+    pub verify_float_roundtrip: bool,
+    /// Write objects with at most this many parameters on a single line in flow style, e.g.
+    /// `!obj {Life: 100, Scale: 1.5}`
+    pub inline_small_objects: Option<usize>,
+    /// Write every list, object, and parameter key as its bare hash without looking up or
+    /// guessing a name. Much faster on large documents and fully deterministic, since a guessed
+    /// name can never be wrong.
+    pub raw_keys: bool,
+    /// How to write `U32` and `BufferU32` values
+    pub u32_format: U32Format,
+}
+
+/// The radix used for `U32` and `BufferU32` values in YAML output. Both are read back the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum U32Format {
+    /// `U32` values in hex and `BufferU32` values in decimal, as `oead` writes them
+    #[default]
+    Default,
+    /// Both in hex, e.g. `!u 0x1F` and `!buffer_u32 [0x1, 0x1F]`
+    Hex,
+    /// Both in decimal, e.g. `!u 31` and `!buffer_u32 [1, 31]`
+    Decimal,
+}
+
 impl ParameterIO {
     /// Returns a YAML representation of an AAMP parameter IO as a string. The output is fully
     /// compatible with the representation used in the `oead` C++ library, and compatible with the
     /// representation used in the `aamp` Python library except where buffer types are used.
+    /// Names are looked up in the active name table (see `names::scope`) and among the
+    /// document's own string values.
+    #[cfg(feature = "global-names")]
     pub fn to_text(&self) -> Result<String, Box<dyn Error>> {
+        self.to_text_with_options(&ToTextOptions::default())
+    }
+
+    /// Returns a YAML representation of an AAMP parameter IO as a string, using the given
+    /// options.
+    #[cfg(feature = "global-names")]
+    pub fn to_text_with_options(&self, options: &ToTextOptions) -> Result<String, Box<dyn Error>> {
         let mut bytes: Vec<u8> = vec![];
-        let mut writer = BufWriter::new(&mut bytes);
-        self.write_text(&mut writer)?;
-        drop(writer);
-        Ok(std::str::from_utf8(&bytes)?.to_owned())
+        self.write_text_with_options(&mut bytes, options)?;
+        Ok(String::from_utf8(bytes)?)
     }
 
     /// Writes a YAML document representing an AAMP parameter IO into a writer. The output is fully
     /// compatible with the representation used in the `oead` C++ library, and compatible with the
     /// representation used in the `aamp` Python library except where buffer types are used.
+    /// The document is streamed to the writer through a buffer as it is emitted, without
+    /// building it in memory first or copying any lists, objects, or parameters.
+    #[cfg(feature = "global-names")]
     pub fn write_text<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
-        let param_root = ParameterList {
-            lists: self.lists.clone(),
-            objects: self.objects.clone(),
-        };
-        write!(
-            writer,
-            "!io\nversion: {}\ntype: {}\nparam_root: ",
-            self.version, self.pio_type
-        )?;
-        write_list(writer, &param_root, 2_767_637_356, 1)?;
-        writer.flush()?;
-        Ok(())
+        self.write_text_with_options(writer, &ToTextOptions::default())
+    }
+
+    /// Writes a YAML document representing an AAMP parameter IO into a writer, using the given
+    /// options.
+    #[cfg(feature = "global-names")]
+    pub fn write_text_with_options<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &ToTextOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        if options.raw_keys {
+            return write_document(writer, self, &NameTable::new(false), options);
+        }
+        write_document(writer, self, &names::table_for(self), options)
+    }
+
+    /// Writes a YAML document representing an AAMP parameter IO into a writer, resolving names
+    /// only from the given name table instead of the global one. Useful when the correct names
+    /// for a file are known ahead of time.
+    pub fn write_text_with_names<W: Write>(
+        &self,
+        writer: &mut W,
+        names: &NameTable,
+    ) -> Result<(), Box<dyn Error>> {
+        write_document(writer, self, names, &ToTextOptions::default())
     }
 }
 
-const INDENTS: [&str; 20] = [
-    "",
-    "  ",
-    "    ",
-    "      ",
-    "        ",
-    "          ",
-    "            ",
-    "              ",
-    "                ",
-    "                  ",
-    "                    ",
-    "                      ",
-    "                        ",
-    "                          ",
-    "                            ",
-    "                              ",
-    "                                ",
-    "                                  ",
-    "                                    ",
-    "                                      ",
-];
+impl ParameterIO {
+    /// Returns a wrapper whose text conversion methods resolve names only from the given name
+    /// table, e.g. `pio.with_names(&table).to_text()`
+    pub fn with_names<'a>(&'a self, names: &'a NameTable) -> WithNames<'a> {
+        WithNames {
+            pio: self,
+            names,
+            options: ToTextOptions::default(),
+        }
+    }
+}
+
+/// A parameter IO paired with the name table to use when converting it to text, created with
+/// `ParameterIO::with_names`
+pub struct WithNames<'a> {
+    pio: &'a ParameterIO,
+    names: &'a NameTable,
+    options: ToTextOptions,
+}
+
+impl WithNames<'_> {
+    /// Sets the options to use when converting to text, e.g.
+    /// `pio.with_names(&table).with_options(options).to_text()`
+    pub fn with_options(mut self, options: ToTextOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns a YAML representation of the parameter IO as a string, using the wrapped name
+    /// table
+    pub fn to_text(&self) -> Result<String, Box<dyn Error>> {
+        let mut bytes: Vec<u8> = vec![];
+        self.write_text(&mut bytes)?;
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    /// Writes a YAML document representing the parameter IO into a writer, using the wrapped
+    /// name table
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        if self.options.raw_keys {
+            return write_document(writer, self.pio, &NameTable::new(false), &self.options);
+        }
+        write_document(writer, self.pio, self.names, &self.options)
+    }
+}
+
+impl ParameterIO {
+    /// Checks that the name resolved for every list, object, and parameter using the given name
+    /// table is read back from YAML as the same hash. Returns the hashes whose names would not
+    /// survive a round trip through text, e.g. because a guessed name was wrong.
+    pub fn verify_names_roundtrip(&self, names: &NameTable) -> Result<(), Vec<u32>> {
+        let mut bad = vec![];
+        verify_list_names(&self.lists, &self.objects, 2_767_637_356, names, &mut bad);
+        if bad.is_empty() {
+            Ok(())
+        } else {
+            Err(bad)
+        }
+    }
+}
+
+fn verify_list_names(
+    lists: &IndexMap<u32, ParameterList>,
+    objects: &IndexMap<u32, ParameterObject>,
+    crc: u32,
+    names: &NameTable,
+    bad: &mut Vec<u32>,
+) {
+    let mut check = |subcrc: u32, parent: u32, idx: usize| {
+        if key_crc(&try_get_name(subcrc, parent, idx, names)) != Some(subcrc) {
+            bad.push(subcrc);
+        }
+    };
+    for (i, (subcrc, obj)) in objects.iter().enumerate() {
+        check(*subcrc, crc, i);
+        for (j, param_crc) in obj.0.keys().enumerate() {
+            check(*param_crc, *subcrc, j);
+        }
+    }
+    for (i, subcrc) in lists.keys().enumerate() {
+        check(*subcrc, crc, i);
+    }
+    for (subcrc, list) in lists {
+        verify_list_names(&list.lists, &list.objects, *subcrc, names, bad);
+    }
+}
+
+impl ParameterIO {
+    /// Returns an indented tree of the document for debugging, with names resolved using the
+    /// given name table and values shown with their types
+    pub fn pretty_debug(&self, names: &NameTable) -> String {
+        let mut out = format!(
+            "ParameterIO ({}, version {})\n",
+            self.pio_type, self.version
+        );
+        debug_list(
+            &mut out,
+            &self.lists,
+            &self.objects,
+            2_767_637_356,
+            1,
+            names,
+        );
+        out
+    }
+}
+
+fn debug_list(
+    out: &mut String,
+    lists: &IndexMap<u32, ParameterList>,
+    objects: &IndexMap<u32, ParameterObject>,
+    crc: u32,
+    level: usize,
+    names: &NameTable,
+) {
+    for (i, (subcrc, obj)) in objects.iter().enumerate() {
+        let name = try_get_name(*subcrc, crc, i, names);
+        out.push_str(&format!("{}{} (object)\n", Indent(level), name));
+        for (j, (param_crc, param)) in obj.0.iter().enumerate() {
+            let param_name = try_get_name(*param_crc, *subcrc, j, names);
+            out.push_str(&format!("{}{}: {}\n", Indent(level + 1), param_name, param));
+        }
+    }
+    for (i, (subcrc, list)) in lists.iter().enumerate() {
+        let name = try_get_name(*subcrc, crc, i, names);
+        out.push_str(&format!("{}{} (list)\n", Indent(level), name));
+        debug_list(out, &list.lists, &list.objects, *subcrc, level + 1, names);
+    }
+}
+
+impl std::fmt::Display for Parameter {
+    /// Formats the parameter as its YAML representation, e.g. `!vec3 [1.0, 2.0, 3.0]`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut bytes: Vec<u8> = vec![];
+        write_param(&mut bytes, self, &ToTextOptions::default()).map_err(|_| std::fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(&bytes))
+    }
+}
+
+fn write_document<W: Write>(
+    writer: &mut W,
+    pio: &ParameterIO,
+    names: &NameTable,
+    options: &ToTextOptions,
+) -> Result<(), Box<dyn Error>> {
+    // Values are emitted in many small writes
+    let mut writer = BufWriter::new(writer);
+    write!(
+        writer,
+        "!io\nversion: {}\ntype: {}\nparam_root: ",
+        pio.version, pio.pio_type
+    )?;
+    write_list(
+        &mut writer,
+        &pio.lists,
+        &pio.objects,
+        2_767_637_356,
+        1,
+        names,
+        options,
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+impl ParameterObject {
+    /// Returns a YAML fragment representing just this object, keyed by the given name, using the
+    /// given name table to resolve parameter names. The fragment can be parsed back with
+    /// `ParameterObject::from_text_fragment`, e.g. to copy a single object between documents.
+    pub fn to_text_fragment(&self, name: &str, names: &NameTable) -> String {
+        let mut bytes: Vec<u8> = vec![];
+        write_fragment(&mut bytes, self, name, names).expect("Writing to a Vec cannot fail");
+        String::from_utf8(bytes).expect("YAML output is always valid UTF-8")
+    }
+}
+
+fn write_fragment<W: Write>(
+    writer: &mut W,
+    obj: &ParameterObject,
+    name: &str,
+    names: &NameTable,
+) -> Result<(), Box<dyn Error>> {
+    write!(writer, "{}: ", quote_key(name.to_owned()))?;
+    write_object(
+        writer,
+        obj,
+        hash_name(name),
+        1,
+        names,
+        &ToTextOptions::default(),
+    )?;
+    Ok(())
+}
+
+/// Indentation for the given nesting level, two spaces per level
+struct Indent(usize);
+
+impl std::fmt::Display for Indent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for _ in 0..self.0 {
+            f.write_str("  ")?;
+        }
+        Ok(())
+    }
+}
 
 fn write_list<W: Write>(
     writer: &mut W,
-    list: &ParameterList,
+    lists: &IndexMap<u32, ParameterList>,
+    objects: &IndexMap<u32, ParameterObject>,
     crc: u32,
     level: usize,
+    names: &NameTable,
+    options: &ToTextOptions,
 ) -> Result<(), Box<dyn Error>> {
     write!(writer, "!list")?;
-    write!(writer, "\n{}objects:", &INDENTS[level])?;
-    if !list.objects.is_empty() {
-        for (i, (subcrc, obj)) in list.objects.iter().enumerate() {
+    write!(writer, "\n{}objects:", Indent(level))?;
+    if !objects.is_empty() {
+        for (i, (subcrc, obj)) in objects.iter().enumerate() {
             write!(
                 writer,
                 "\n{}{}: ",
-                &INDENTS[level + 1],
-                try_get_name(*subcrc, crc, i)
+                Indent(level + 1),
+                resolve_key(*subcrc, crc, i, names, options)?
             )?;
-            write_object(writer, obj, *subcrc, level + 2)?;
+            write_object(writer, obj, *subcrc, level + 2, names, options)?;
         }
     } else {
         write!(writer, " {{}}")?;
     }
-    write!(writer, "\n{}lists:", &INDENTS[level])?;
-    if !list.lists.is_empty() {
-        for (i, (subcrc, sublist)) in list.lists.iter().enumerate() {
+    write!(writer, "\n{}lists:", Indent(level))?;
+    if !lists.is_empty() {
+        for (i, (subcrc, sublist)) in lists.iter().enumerate() {
             write!(
                 writer,
                 "\n{}{}: ",
-                &INDENTS[level + 1],
-                try_get_name(*subcrc, crc, i)
+                Indent(level + 1),
+                resolve_key(*subcrc, crc, i, names, options)?
+            )?;
+            write_list(
+                writer,
+                &sublist.lists,
+                &sublist.objects,
+                *subcrc,
+                level + 2,
+                names,
+                options,
             )?;
-            write_list(writer, sublist, *subcrc, level + 2)?;
         }
     } else {
         write!(writer, " {{}}")?;
@@ -100,17 +350,34 @@ fn write_object<W: Write>(
     obj: &ParameterObject,
     crc: u32,
     level: usize,
+    names: &NameTable,
+    options: &ToTextOptions,
 ) -> Result<(), Box<dyn Error>> {
     write!(writer, "!obj")?;
-    if !obj.0.is_empty() {
+    let inline = matches!(options.inline_small_objects, Some(max) if obj.0.len() <= max);
+    if !obj.0.is_empty() && inline {
+        write!(writer, " {{")?;
+        for (i, (subcrc, param)) in obj.0.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ", ")?;
+            }
+            write!(
+                writer,
+                "{}: ",
+                resolve_key(*subcrc, crc, i, names, options)?
+            )?;
+            write_param(writer, param, options)?;
+        }
+        write!(writer, "}}")?;
+    } else if !obj.0.is_empty() {
         for (i, (subcrc, param)) in obj.0.iter().enumerate() {
             write!(
                 writer,
                 "\n{}{}: ",
-                &INDENTS[level],
-                try_get_name(*subcrc, crc, i)
+                Indent(level),
+                resolve_key(*subcrc, crc, i, names, options)?
             )?;
-            write_param(writer, param)?;
+            write_param(writer, param, options)?;
         }
     } else {
         write!(writer, " {{}}")?;
@@ -118,68 +385,73 @@ fn write_object<W: Write>(
     Ok(())
 }
 
-fn write_param<W: Write>(writer: &mut W, param: &Parameter) -> Result<(), Box<dyn Error>> {
+fn write_param<W: Write>(
+    writer: &mut W,
+    param: &Parameter,
+    options: &ToTextOptions,
+) -> Result<(), Box<dyn Error>> {
     match param {
         Parameter::Bool(b) => write!(writer, "{}", if *b { "true" } else { "false" })?,
         Parameter::BufferBinary(bb) => {
             write!(writer, "!buffer_binary ")?;
-            write_seq(writer, bb.buffer.iter(), bb.buffer.len())?;
+            write_seq(writer, bb.buffer.iter(), bb.buffer.len(), false)?;
         }
         Parameter::BufferF32(bf) => {
             write!(writer, "!buffer_f32 ")?;
-            write_float_seq(writer, bf.buffer.iter(), bf.buffer.len())?;
+            write_float_seq(writer, bf.buffer.iter(), bf.buffer.len(), options)?;
         }
         Parameter::BufferInt(bi) => {
             write!(writer, "!buffer_int ")?;
-            write_seq(writer, bi.buffer.iter(), bi.buffer.len())?
+            write_seq(writer, bi.buffer.iter(), bi.buffer.len(), false)?
         }
         Parameter::BufferU32(bu) => {
             write!(writer, "!buffer_u32 ")?;
-            write_seq(writer, bu.buffer.iter(), bu.buffer.len())?
+            let hex = options.u32_format == U32Format::Hex;
+            write_seq(writer, bu.buffer.iter(), bu.buffer.len(), hex)?
         }
         Parameter::Color(c) => {
             write!(writer, "!color ")?;
-            write_float_seq(writer, c.0.iter(), 4)?;
+            write_float_seq(writer, c.0.iter(), 4, options)?;
         }
         Parameter::Curve1(c) => {
             write!(writer, "!curve [")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve))?;
+            write_curve(writer, &c.curve, options)?;
             write!(writer, "]")?;
         }
         Parameter::Curve2(c) => {
             write!(writer, "!curve [")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve1))?;
+            write_curve(writer, &c.curve1, options)?;
             write!(writer, ", ")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve2))?;
+            write_curve(writer, &c.curve2, options)?;
             write!(writer, "]")?;
         }
         Parameter::Curve3(c) => {
             write!(writer, "!curve [")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve1))?;
+            write_curve(writer, &c.curve1, options)?;
             write!(writer, ", ")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve2))?;
+            write_curve(writer, &c.curve2, options)?;
             write!(writer, ", ")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve3))?;
+            write_curve(writer, &c.curve3, options)?;
             write!(writer, "]")?;
         }
         Parameter::Curve4(c) => {
             write!(writer, "!curve [")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve1))?;
+            write_curve(writer, &c.curve1, options)?;
             write!(writer, ", ")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve2))?;
+            write_curve(writer, &c.curve2, options)?;
             write!(writer, ", ")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve3))?;
+            write_curve(writer, &c.curve3, options)?;
             write!(writer, ", ")?;
-            write!(writer, "{}", &curve_to_vec(&c.curve4))?;
+            write_curve(writer, &c.curve4, options)?;
             write!(writer, "]")?;
         }
-        Parameter::F32(f) => write!(writer, "{}", ryu::Buffer::new().format(*f))?,
+        Parameter::F32(f) => write_float(writer, *f, options)?,
         Parameter::Int(i) => {
             write!(writer, "{}", i)?;
         }
         Parameter::Quat(q) => {
             write!(writer, "!quat ")?;
-            write_float_seq(writer, q.0.iter(), 4)?
+            write_float_seq(writer, q.0.iter(), 4, options)?
         }
         Parameter::String32(s) => {
             write!(writer, "!str32 ")?;
@@ -194,43 +466,80 @@ fn write_param<W: Write>(writer: &mut W, param: &Parameter) -> Result<(), Box<dy
             write_string(writer, s)?
         }
         Parameter::StringRef(s) => write_string(writer, s)?,
-        Parameter::U32(u) => write!(writer, "!u 0x{:X}", u)?,
+        Parameter::U32(u) => match options.u32_format {
+            U32Format::Default | U32Format::Hex => write!(writer, "!u 0x{:X}", u)?,
+            U32Format::Decimal => write!(writer, "!u {}", u)?,
+        },
         Parameter::Vec2(v) => {
             write!(writer, "!vec2 ")?;
-            write_float_seq(writer, v.0.iter(), 2)
+            write_float_seq(writer, v.0.iter(), 2, options)
         }?,
         Parameter::Vec3(v) => {
             write!(writer, "!vec3 ")?;
-            write_float_seq(writer, v.0.iter(), 3)
+            write_float_seq(writer, v.0.iter(), 3, options)
         }?,
         Parameter::Vec4(v) => {
             write!(writer, "!vec4 ")?;
-            write_float_seq(writer, v.0.iter(), 4)
+            write_float_seq(writer, v.0.iter(), 4, options)
         }?,
     };
     Ok(())
 }
 
 fn write_string<W: Write>(writer: &mut W, string: &str) -> Result<(), Box<dyn Error>> {
-    if string.contains(' ') || parse_int::parse::<usize>(string).is_ok() || string.is_empty() {
-        write!(writer, "\"")?;
-        write!(writer, "{}", string)?;
-        write!(writer, "\"")?;
+    if string.contains(' ')
+        || string.contains(char::is_control)
+        || string.contains(&[',', '[', ']', '{', '}'][..])
+        || parse_int::parse::<usize>(string).is_ok()
+        || parse_float(string).is_ok()
+        || is_null(string)
+        || string.is_empty()
+    {
+        write_string_quoted(writer, string)?;
     } else {
         write!(writer, "{}", string)?;
     }
     Ok(())
 }
 
-fn write_seq<'a, I, T, W>(writer: &mut W, seq: I, count: usize) -> Result<(), Box<dyn Error>>
+/// Writes a string in double quotes, escaping it as needed
+fn write_string_quoted<W: Write>(writer: &mut W, string: &str) -> Result<(), Box<dyn Error>> {
+    write!(writer, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(writer, "\\\"")?,
+            '\\' => write!(writer, "\\\\")?,
+            '\0' => write!(writer, "\\0")?,
+            '\t' => write!(writer, "\\t")?,
+            '\n' => write!(writer, "\\n")?,
+            '\r' => write!(writer, "\\r")?,
+            c if c.is_control() => write!(writer, "\\x{:02X}", c as u32)?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    write!(writer, "\"")?;
+    Ok(())
+}
+
+/// Writes a sequence of integers, in hex if `hex` is set
+fn write_seq<'a, I, T, W>(
+    writer: &mut W,
+    seq: I,
+    count: usize,
+    hex: bool,
+) -> Result<(), Box<dyn Error>>
 where
     I: Iterator<Item = &'a T>,
-    T: 'a + num_format::ToFormattedStr + std::fmt::Display,
+    T: 'a + num_format::ToFormattedStr + std::fmt::Display + std::fmt::UpperHex,
     W: Write,
 {
     write!(writer, "[")?;
     for (i, x) in seq.enumerate() {
-        write!(writer, "{}", x)?;
+        if hex {
+            write!(writer, "0x{:X}", x)?;
+        } else {
+            write!(writer, "{}", x)?;
+        }
         if i < count - 1 {
             write!(writer, ", ")?;
         }
@@ -239,16 +548,63 @@ where
     Ok(())
 }
 
-fn write_float_seq<'a, I, T, W>(writer: &mut W, seq: I, count: usize) -> Result<(), Box<dyn Error>>
+fn write_float<W: Write>(
+    writer: &mut W,
+    float: f32,
+    options: &ToTextOptions,
+) -> Result<(), Box<dyn Error>> {
+    let text = format_float(float);
+    if options.verify_float_roundtrip
+        && parse_float(&text).map(f32::to_bits).ok() != Some(float.to_bits())
+    {
+        return Err(format!("Float {} does not survive a round trip through text", text).into());
+    }
+    write!(writer, "{}", text)?;
+    Ok(())
+}
+
+/// Formats a float the way oead does: the shortest digits that read back as the same value, in
+/// fixed notation when the decimal exponent is in `-4..16` and in exponent notation with a signed,
+/// two-digit exponent otherwise, e.g. `0.0001`, `1.0`, `1e-07` and `3.4028235e+38`
+fn format_float(float: f32) -> String {
+    if float.is_nan() {
+        return ".nan".to_owned();
+    } else if float.is_infinite() {
+        return if float > 0.0 { ".inf" } else { "-.inf" }.to_owned();
+    }
+    let sci = format!("{:e}", float);
+    let (mantissa, exp) = sci.split_at(sci.find('e').expect("exponent form always has an e"));
+    let exp: i32 = exp[1..].parse().expect("exponent is always an integer");
+    if (-4..16).contains(&exp) {
+        let fixed = float.to_string();
+        if fixed.contains('.') {
+            fixed
+        } else {
+            fixed + ".0"
+        }
+    } else {
+        format!(
+            "{}e{}{:02}",
+            mantissa,
+            if exp < 0 { '-' } else { '+' },
+            exp.abs()
+        )
+    }
+}
+
+fn write_float_seq<'a, I, W>(
+    writer: &mut W,
+    seq: I,
+    count: usize,
+    options: &ToTextOptions,
+) -> Result<(), Box<dyn Error>>
 where
-    I: Iterator<Item = &'a T>,
-    T: 'a + ryu::Float,
+    I: Iterator<Item = &'a f32>,
     W: Write,
 {
-    let mut buf = ryu::Buffer::new();
     write!(writer, "[")?;
     for (i, x) in seq.enumerate() {
-        write!(writer, "{}", buf.format(*x))?;
+        write_float(writer, *x, options)?;
         if i < count - 1 {
             write!(writer, ", ")?;
         }
@@ -257,38 +613,85 @@ where
     Ok(())
 }
 
-fn curve_to_vec(curve: &crate::types::Curve) -> String {
-    let mut vec = Vec::with_capacity(3);
-    vec.push(format!("{}", curve.a));
-    vec.push(format!("{}", curve.b));
-    let mut buf = ryu::Buffer::new();
-    vec.push(
-        curve
-            .floats
-            .iter()
-            .map(|f| buf.format(*f).to_string())
-            .collect::<Vec<String>>()
-            .join(", "),
-    );
-    vec.join(", ")
+fn write_curve<W: Write>(
+    writer: &mut W,
+    curve: &crate::types::Curve,
+    options: &ToTextOptions,
+) -> Result<(), Box<dyn Error>> {
+    write!(writer, "{}, {}", curve.a, curve.b)?;
+    for f in &curve.floats {
+        write!(writer, ", ")?;
+        write_float(writer, *f, options)?;
+    }
+    Ok(())
+}
+
+/// Returns the key to write for a hash, failing if the name found for it would be read back as
+/// something else
+fn resolve_key(
+    crc: u32,
+    parent: u32,
+    idx: usize,
+    names: &NameTable,
+    options: &ToTextOptions,
+) -> Result<String, Box<dyn Error>> {
+    if options.raw_keys {
+        return Ok(crc.to_string());
+    }
+    let name = try_get_name(crc, parent, idx, names);
+    if key_crc(&name) != Some(crc) {
+        return Err(format!("Name {} is not read back as {}", name, crc).into());
+    }
+    Ok(name)
+}
+
+fn try_get_name(crc: u32, parent: u32, idx: usize, names: &NameTable) -> String {
+    match names
+        .get_name(crc)
+        .or_else(|| names::guess_name_with_table(names, crc, parent, idx))
+    {
+        Some(name) => quote_key(name),
+        None => format!("{}", crc),
+    }
 }
 
-fn try_get_name(crc: u32, parent: u32, idx: usize) -> String {
-    let table = names::TABLE.lock().unwrap();
-    match table.get_name(crc) {
-        Some(s) => match s.parse::<u32>() {
-            Ok(s) => format!("\"{}\"", s),
-            Err(_) => s.to_string(),
-        },
-        None => {
-            drop(table);
-            match names::guess_name(crc, parent, idx) {
-                Some(s) => match s.parse::<u32>() {
-                    Ok(s) => format!("\"{}\"", s),
-                    Err(_) => s.to_string(),
-                },
-                None => format!("{}", crc),
-            }
+/// Quotes a name that would otherwise be read back as a bare hash or as some other key. Bare
+/// integer keys are always hashes, so a name made only of digits must be quoted, as must any
+/// name that is not a plain YAML scalar.
+fn quote_key(name: String) -> String {
+    if needs_quotes(&name) {
+        let mut quoted = vec![];
+        match write_string_quoted(&mut quoted, &name) {
+            Ok(()) => String::from_utf8(quoted).unwrap_or(name),
+            Err(_) => name,
         }
+    } else {
+        name
     }
 }
+
+/// Whether a key must be quoted to be read back exactly as written: a name that is empty, is a
+/// decimal integer, is one of the section keys `objects` and `lists`, starts with a YAML
+/// indicator or a document marker, has leading or trailing whitespace, contains control
+/// characters or flow indicators, or contains `: ` or ` #`, which would end the key or start a
+/// comment.
+fn needs_quotes(name: &str) -> bool {
+    name.is_empty()
+        || name.parse::<u32>().is_ok()
+        || name == "objects"
+        || name == "lists"
+        || name.starts_with(
+            &[
+                '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"',
+                '%', '@', '`',
+            ][..],
+        )
+        || name.starts_with("...")
+        || name.starts_with(char::is_whitespace)
+        || name.ends_with(char::is_whitespace)
+        || name.ends_with(':')
+        || name.contains(char::is_control)
+        || name.contains(&[',', '[', ']', '{', '}'][..])
+        || name.contains(": ")
+        || name.contains(" #")
+}
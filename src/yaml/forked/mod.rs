@@ -1,3 +1,2 @@
-#![allow(dead_code)]
 pub(crate) mod parser;
 pub(crate) mod scanner;
@@ -30,11 +30,9 @@ enum State {
 }
 
 /// `Event` is used with the low-level event base parsing API,
-/// see `EventReceiver` trait.
+/// see `MarkedEventReceiver` trait.
 #[derive(Clone, PartialEq, Debug, Eq)]
 pub enum Event {
-    /// Reserved for internal use
-    Nothing,
     StreamStart,
     StreamEnd,
     DocumentStart,
@@ -67,27 +65,16 @@ pub struct Parser<T> {
     scanner: Scanner<T>,
     states: Vec<State>,
     state: State,
-    marks: Vec<Marker>,
     token: Option<Token>,
     current: Option<(Event, Marker)>,
     anchors: HashMap<String, usize>,
     anchor_id: usize,
 }
 
-pub trait EventReceiver {
-    fn on_event(&mut self, ev: Event);
-}
-
 pub trait MarkedEventReceiver {
     fn on_event(&mut self, ev: Event, _mark: Marker);
 }
 
-impl<R: EventReceiver> MarkedEventReceiver for R {
-    fn on_event(&mut self, ev: Event, _mark: Marker) {
-        self.on_event(ev)
-    }
-}
-
 pub type ParseResult = Result<(Event, Marker), ScanError>;
 
 impl<T: Iterator<Item = char>> Parser<T> {
@@ -96,7 +83,6 @@ impl<T: Iterator<Item = char>> Parser<T> {
             scanner: Scanner::new(src),
             states: Vec::new(),
             state: State::StreamStart,
-            marks: Vec::new(),
             token: None,
             current: None,
 
@@ -106,16 +92,6 @@ impl<T: Iterator<Item = char>> Parser<T> {
         }
     }
 
-    pub fn peek(&mut self) -> Result<&(Event, Marker), ScanError> {
-        match self.current {
-            Some(ref x) => Ok(x),
-            None => {
-                self.current = Some(self.next()?);
-                self.peek()
-            }
-        }
-    }
-
     pub fn next(&mut self) -> ParseResult {
         match self.current {
             None => self.parse(),
@@ -170,58 +146,6 @@ impl<T: Iterator<Item = char>> Parser<T> {
         Ok((ev, mark))
     }
 
-    pub fn load<R: MarkedEventReceiver>(
-        &mut self,
-        recv: &mut R,
-        multi: bool,
-    ) -> Result<(), ScanError> {
-        if !self.scanner.stream_started() {
-            let (ev, mark) = self.next()?;
-            assert_eq!(ev, Event::StreamStart);
-            recv.on_event(ev, mark);
-        }
-
-        if self.scanner.stream_ended() {
-            // XXX has parsed?
-            recv.on_event(Event::StreamEnd, self.scanner.mark());
-            return Ok(());
-        }
-        loop {
-            let (ev, mark) = self.next()?;
-            if ev == Event::StreamEnd {
-                recv.on_event(ev, mark);
-                return Ok(());
-            }
-            // clear anchors before a new document
-            self.anchors.clear();
-            self.load_document(ev, mark, recv)?;
-            if !multi {
-                break;
-            }
-        }
-        Ok(())
-    }
-
-    fn load_document<R: MarkedEventReceiver>(
-        &mut self,
-        first_ev: Event,
-        mark: Marker,
-        recv: &mut R,
-    ) -> Result<(), ScanError> {
-        assert_eq!(first_ev, Event::DocumentStart);
-        recv.on_event(first_ev, mark);
-
-        let (ev, mark) = self.next()?;
-        self.load_node(ev, mark, recv)?;
-
-        // DOCUMENT-END is expected.
-        let (ev, mark) = self.next()?;
-        assert_eq!(ev, Event::DocumentEnd);
-        recv.on_event(ev, mark);
-
-        Ok(())
-    }
-
     pub fn load_node<R: MarkedEventReceiver>(
         &mut self,
         first_ev: Event,
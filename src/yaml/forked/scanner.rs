@@ -9,7 +9,6 @@ pub enum TEncoding {
 
 #[derive(Clone, Copy, PartialEq, Debug, Eq)]
 pub enum TScalarStyle {
-    Any,
     Plain,
     SingleQuoted,
     DoubleQuoted,
@@ -87,7 +86,6 @@ impl fmt::Display for ScanError {
 
 #[derive(Clone, PartialEq, Debug, Eq)]
 pub enum TokenType {
-    NoToken,
     StreamStart(TEncoding),
     StreamEnd,
     /// major, minor
@@ -194,7 +192,7 @@ fn is_blankz(c: char) -> bool {
 }
 #[inline]
 fn is_digit(c: char) -> bool {
-    ('0'..='9').contains(&c)
+    c.is_ascii_digit()
 }
 #[inline]
 fn is_alpha(c: char) -> bool {
@@ -202,7 +200,7 @@ fn is_alpha(c: char) -> bool {
 }
 #[inline]
 fn is_hex(c: char) -> bool {
-    ('0'..='9').contains(&c) || ('a'..='f').contains(&c) | ('A'..='F').contains(&c)
+    c.is_ascii_hexdigit()
 }
 #[inline]
 fn as_hex(c: char) -> u32 {
@@ -244,10 +242,7 @@ impl<T: Iterator<Item = char>> Scanner<T> {
     }
     #[inline]
     pub fn get_error(&self) -> Option<ScanError> {
-        match self.error {
-            None => None,
-            Some(ref e) => Some(e.clone()),
-        }
+        self.error.clone()
     }
 
     #[inline]
@@ -288,19 +283,6 @@ impl<T: Iterator<Item = char>> Scanner<T> {
     fn ch_is(&self, c: char) -> bool {
         self.buffer[0] == c
     }
-    #[allow(dead_code)]
-    #[inline]
-    fn eof(&self) -> bool {
-        self.ch_is('\0')
-    }
-    #[inline]
-    pub fn stream_started(&self) -> bool {
-        self.stream_start_produced
-    }
-    #[inline]
-    pub fn stream_ended(&self) -> bool {
-        self.stream_end_produced
-    }
     #[inline]
     pub fn mark(&self) -> Marker {
         self.mark
@@ -690,7 +672,7 @@ impl<T: Iterator<Item = char>> Scanner<T> {
         }
 
         let is_secondary = handle == "!!";
-        let prefix = self.scan_tag_uri(true, is_secondary, &String::new(), mark)?;
+        let prefix = self.scan_tag_uri(true, is_secondary, "", mark)?;
 
         self.lookahead(1);
 
@@ -726,7 +708,7 @@ impl<T: Iterator<Item = char>> Scanner<T> {
             // Eat '!<'
             self.skip();
             self.skip();
-            suffix = self.scan_tag_uri(false, false, &String::new(), &start_mark)?;
+            suffix = self.scan_tag_uri(false, false, "", &start_mark)?;
 
             if self.ch() != '>' {
                 return Err(ScanError::new(
@@ -744,7 +726,7 @@ impl<T: Iterator<Item = char>> Scanner<T> {
                 if handle == "!!" {
                     secondary = true;
                 }
-                suffix = self.scan_tag_uri(false, secondary, &String::new(), &start_mark)?;
+                suffix = self.scan_tag_uri(false, secondary, "", &start_mark)?;
             } else {
                 suffix = self.scan_tag_uri(false, false, &handle, &start_mark)?;
                 handle = "!".to_owned();
@@ -1,14 +1,15 @@
 use super::forked::parser::*;
 use super::forked::scanner::*;
+use crate::names::NameTable;
 use crate::types::*;
-use crate::{Parameter, ParameterIO, ParameterList, ParameterObject};
-use crc::{crc32, Hasher32};
+use crate::{hash_name, Parameter, ParameterIO, ParameterList, ParameterObject};
 use indexmap::IndexMap;
 use thiserror::Error;
 
 type Result<T> = std::result::Result<T, YamlParseError>;
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum YamlParseError {
     #[error("YAML document not a valid ParameterIO: {0}")]
     InvalidPio(String),
@@ -24,20 +25,57 @@ impl ParameterIO {
     /// Parses an AAMP Parameter IO document from a YAML representation. Takes a string slice and
     /// returns a result containing a `ParameterIO` or a boxed error.
     pub fn from_text(text: &str) -> Result<ParameterIO> {
-        let mut parser = Parser::new(text.chars());
-        let (pio_type, version) = parse_header(&mut parser)?;
-        let mut pio_parser = PioYamlParser::new(version, pio_type);
-        let next = parser.next()?;
-        parser.load_node(next.0, next.1, &mut pio_parser)?;
-        match pio_parser.error {
-            Some(err) => Err(err),
-            None => match pio_parser.pio {
-                Some(pio) => Ok(pio),
-                None => Err(YamlParseError::InvalidPio(
-                    "Could not parse document".to_owned(),
-                )),
-            },
+        parse_text(text).map(|(pio, _)| pio)
+    }
+
+    /// Parses an AAMP Parameter IO document from a YAML representation like `from_text`, and
+    /// adds its string values to the given name table as possible names
+    pub fn from_text_with_names(text: &str, names: &mut NameTable) -> Result<ParameterIO> {
+        let (pio, found) = parse_text(text)?;
+        for name in &found {
+            names.add_name(name);
         }
+        Ok(pio)
+    }
+}
+
+/// Parses a document, returning it along with the string values found that may be names
+fn parse_text(text: &str) -> Result<(ParameterIO, Vec<String>)> {
+    let mut parser = Parser::new(text.chars());
+    let (pio_type, version) = parse_header(&mut parser)?;
+    let mut pio_parser = PioYamlParser::new(version, pio_type);
+    let next = parser.next()?;
+    parser.load_node(next.0, next.1, &mut pio_parser)?;
+    match pio_parser.error {
+        Some(err) => Err(err),
+        None => match pio_parser.pio {
+            Some(pio) => Ok((pio, pio_parser.names)),
+            None => Err(YamlParseError::InvalidPio(
+                "Could not parse document".to_owned(),
+            )),
+        },
+    }
+}
+
+impl ParameterObject {
+    /// Parses a YAML fragment containing a single named object, as produced by
+    /// `ParameterObject::to_text_fragment`. Returns the hash of the object's name along with the
+    /// object itself.
+    pub fn from_text_fragment(text: &str) -> Result<(u32, ParameterObject)> {
+        let indented: String = text.lines().map(|l| format!("    {}\n", l)).collect();
+        let doc = format!(
+            "!io\nversion: 0\ntype: xml\nparam_root: !list\n  objects:\n{}  lists: {{}}\n",
+            indented
+        );
+        let mut pio = ParameterIO::from_text(&doc)?;
+        if pio.objects.len() != 1 || !pio.lists.is_empty() {
+            return Err(YamlParseError::InvalidPio(
+                "Fragment must contain exactly one object".to_owned(),
+            ));
+        }
+        pio.objects.pop().ok_or_else(|| {
+            YamlParseError::InvalidPio("Fragment must contain exactly one object".to_owned())
+        })
     }
 }
 
@@ -51,57 +89,54 @@ fn parse_header<T: Iterator<Item = char>>(parser: &mut Parser<T>) -> Result<(Str
         _ => return Err(YamlParseError::InvalidPio("No doc start".to_owned())),
     };
     match parser.next()?.0 {
-        Event::MappingStart(_, tag) => match tag {
-            Some(TokenType::Tag(ref _handle, ref suffix)) => {
-                assert_eq!(suffix.as_str(), "io");
-                match parser.next()?.0 {
-                    Event::Scalar(v, _, _, _) => {
-                        assert_eq!(&v, "version");
-                        match parser.next()?.0 {
-                            Event::Scalar(v, _, _, _) => {
-                                let version = v.parse::<u32>()?;
-                                match parser.next()?.0 {
-                                    Event::Scalar(v, _, _, _) => {
-                                        assert_eq!(&v, "type");
-                                        match parser.next()?.0 {
-                                            Event::Scalar(v, _, _, _) => {
-                                                let pio_type = v;
-                                                match parser.next()?.0 {
-                                                    Event::Scalar(v, _, _, _) => {
-                                                        assert_eq!(&v, "param_root");
-                                                        Ok((pio_type, version))
-                                                    }
-                                                    _ => Err(YamlParseError::InvalidPio(
-                                                        "Missing param root".to_owned(),
-                                                    )),
-                                                }
-                                            }
-                                            _ => Err(YamlParseError::InvalidPio(
-                                                "Missing type".to_owned(),
-                                            )),
-                                        }
-                                    }
-                                    _ => Err(YamlParseError::InvalidPio("Missing type".to_owned())),
-                                }
-                            }
-                            _ => Err(YamlParseError::InvalidPio("Missing version".to_owned())),
-                        }
-                    }
-                    _ => Err(YamlParseError::InvalidPio("Missing version".to_owned())),
-                }
+        Event::MappingStart(_, Some(TokenType::Tag(_, suffix))) => {
+            if suffix != "io" {
+                return Err(YamlParseError::InvalidPio(format!(
+                    "Expected !io tag, found !{}",
+                    suffix
+                )));
             }
-            _ => Err(YamlParseError::InvalidPio(
+        }
+        Event::MappingStart(..) => {
+            return Err(YamlParseError::InvalidPio(
                 "Not a Parameter IO document".to_owned(),
-            )),
-        },
-        _ => Err(YamlParseError::InvalidPio("No mapping start".to_owned())),
+            ))
+        }
+        _ => return Err(YamlParseError::InvalidPio("No mapping start".to_owned())),
+    };
+    expect_header_key(parser, "version")?;
+    let version = header_value(parser, "version")?.parse::<u32>()?;
+    expect_header_key(parser, "type")?;
+    let pio_type = header_value(parser, "type")?;
+    expect_header_key(parser, "param_root")?;
+    Ok((pio_type, version))
+}
+
+fn expect_header_key<T: Iterator<Item = char>>(parser: &mut Parser<T>, key: &str) -> Result<()> {
+    match parser.next()?.0 {
+        Event::Scalar(v, _, _, _) if v == key => Ok(()),
+        Event::Scalar(v, _, _, _) => Err(YamlParseError::InvalidPio(format!(
+            "Expected header key {}, found {}",
+            key, v
+        ))),
+        _ => Err(YamlParseError::InvalidPio(format!("Missing {}", key))),
+    }
+}
+
+fn header_value<T: Iterator<Item = char>>(parser: &mut Parser<T>, key: &str) -> Result<String> {
+    match parser.next()?.0 {
+        Event::Scalar(v, _, _, _) => Ok(v),
+        _ => Err(YamlParseError::InvalidPio(format!(
+            "Missing value for {}",
+            key
+        ))),
     }
 }
 
 struct PioYamlParser {
     open_seq: Option<Vec<String>>,
     open_tag: Option<String>,
-    open_keys: Vec<String>,
+    open_keys: Vec<Key>,
     open_params: Option<IndexMap<u32, Parameter>>,
     open_objs: Vec<IndexMap<u32, ParameterObject>>,
     open_list_maps: Vec<IndexMap<u32, ParameterList>>,
@@ -114,6 +149,7 @@ struct PioYamlParser {
     pio: Option<ParameterIO>,
     error: Option<YamlParseError>,
     last_event: Option<Event>,
+    names: Vec<String>,
 }
 
 impl MarkedEventReceiver for PioYamlParser {
@@ -166,7 +202,7 @@ impl MarkedEventReceiver for PioYamlParser {
                         self.open_objs
                             .last_mut()
                             .ok_or_else(|| YamlParseError::InvalidPio("No objcts".to_owned()))?
-                            .insert(hashit(&key), ParameterObject(params));
+                            .insert(key.crc, ParameterObject(params));
                         self.doing_param_key = false;
                     } else if self.doing_objects {
                         self.doing_objects = false;
@@ -193,7 +229,7 @@ impl MarkedEventReceiver for PioYamlParser {
                                     YamlParseError::InvalidPio("No list maps".to_owned())
                                 })?
                                 .insert(
-                                    hashit(&key),
+                                    key.crc,
                                     ParameterList {
                                         lists: list_map,
                                         objects: obj_map,
@@ -209,6 +245,7 @@ impl MarkedEventReceiver for PioYamlParser {
                                 objects: self.open_objs.pop().ok_or_else(|| {
                                     YamlParseError::InvalidPio("No objects".to_owned())
                                 })?,
+                                ..Default::default()
                             })
                         }
                         self.doing_lists = !self.doing_lists;
@@ -239,31 +276,25 @@ impl MarkedEventReceiver for PioYamlParser {
                         .ok_or_else(|| YamlParseError::InvalidPio("No sequence tag".to_owned()))?;
                     let param: Parameter = match tag.as_str() {
                         "vec2" => {
-                            Parameter::Vec2(Vec2([seq[0].parse::<f32>()?, seq[1].parse::<f32>()?]))
+                            let f = parse_floats(&seq, 2, &tag)?;
+                            Parameter::Vec2(Vec2([f[0], f[1]]))
+                        }
+                        "vec3" => {
+                            let f = parse_floats(&seq, 3, &tag)?;
+                            Parameter::Vec3(Vec3([f[0], f[1], f[2]]))
+                        }
+                        "vec4" => {
+                            let f = parse_floats(&seq, 4, &tag)?;
+                            Parameter::Vec4(Vec4([f[0], f[1], f[2], f[3]]))
+                        }
+                        "quat" => {
+                            let f = parse_floats(&seq, 4, &tag)?;
+                            Parameter::Quat(Quat([f[0], f[1], f[2], f[3]]))
+                        }
+                        "color" => {
+                            let f = parse_floats(&seq, 4, &tag)?;
+                            Parameter::Color(Color([f[0], f[1], f[2], f[3]]))
                         }
-                        "vec3" => Parameter::Vec3(Vec3([
-                            seq[0].parse::<f32>()?,
-                            seq[1].parse::<f32>()?,
-                            seq[2].parse::<f32>()?,
-                        ])),
-                        "vec4" => Parameter::Vec4(Vec4([
-                            seq[0].parse::<f32>()?,
-                            seq[1].parse::<f32>()?,
-                            seq[2].parse::<f32>()?,
-                            seq[3].parse::<f32>()?,
-                        ])),
-                        "quat" => Parameter::Quat(Quat([
-                            seq[0].parse::<f32>()?,
-                            seq[1].parse::<f32>()?,
-                            seq[2].parse::<f32>()?,
-                            seq[3].parse::<f32>()?,
-                        ])),
-                        "color" => Parameter::Color(Color([
-                            seq[0].parse::<f32>()?,
-                            seq[1].parse::<f32>()?,
-                            seq[2].parse::<f32>()?,
-                            seq[3].parse::<f32>()?,
-                        ])),
                         "curve" => vec_to_curve(seq)?,
                         "buffer_int" => Parameter::BufferInt(BufferInt {
                             buffer: seq
@@ -274,19 +305,19 @@ impl MarkedEventReceiver for PioYamlParser {
                         "buffer_u32" => Parameter::BufferU32(BufferU32 {
                             buffer: seq
                                 .iter()
-                                .map(|x| parse_int::parse::<u32>(&x).map_err(|e| e.into()))
+                                .map(|x| parse_int::parse::<u32>(x).map_err(|e| e.into()))
                                 .collect::<Result<Vec<u32>>>()?,
                         }),
                         "buffer_binary" => Parameter::BufferBinary(BufferBinary {
                             buffer: seq
                                 .iter()
-                                .map(|x| parse_int::parse::<u8>(&x).map_err(|e| e.into()))
+                                .map(|x| parse_int::parse::<u8>(x).map_err(|e| e.into()))
                                 .collect::<Result<Vec<u8>>>()?,
                         }),
                         "buffer_f32" => Parameter::BufferF32(BufferF32 {
                             buffer: seq
                                 .iter()
-                                .map(|x| x.parse::<f32>().map_err(|e| e.into()))
+                                .map(|x| parse_float(x))
                                 .collect::<Result<Vec<f32>>>()?,
                         }),
                         _ => return Err(YamlParseError::InvalidPio("Unknown type tag".to_owned())),
@@ -296,7 +327,7 @@ impl MarkedEventReceiver for PioYamlParser {
                             self.open_params
                                 .as_mut()
                                 .ok_or_else(|| YamlParseError::InvalidPio("No params".to_owned()))?
-                                .insert(hashit(key), param);
+                                .insert(key.crc, param);
                         }
                         _ => return Err(YamlParseError::InvalidPio("No key for value".to_owned())),
                     }
@@ -318,98 +349,67 @@ impl MarkedEventReceiver for PioYamlParser {
     }
 }
 
+fn parse_floats(seq: &[String], count: usize, tag: &str) -> Result<Vec<f32>> {
+    if seq.len() != count {
+        return Err(YamlParseError::InvalidPio(format!(
+            "!{} needs {} values, found {}",
+            tag,
+            count,
+            seq.len()
+        )));
+    }
+    seq.iter()
+        .map(|x| parse_float(x))
+        .collect::<Result<Vec<f32>>>()
+}
+
+/// Parses a float, accepting the YAML forms `.nan`, `.inf`, and `-.inf` for non-finite values
+/// in addition to the forms accepted by `str::parse`
+pub(crate) fn parse_float(text: &str) -> Result<f32> {
+    match text {
+        ".nan" | ".NaN" | ".NAN" => Ok(f32::NAN),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => Ok(f32::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => Ok(f32::NEG_INFINITY),
+        _ => Ok(text.parse::<f32>()?),
+    }
+}
+
+fn parse_curve(seq: &[String]) -> Result<Curve> {
+    let mut floats = [0.0; 30];
+    floats.copy_from_slice(&parse_floats(&seq[2..], 30, "curve")?);
+    Ok(Curve {
+        a: seq[0].parse::<u32>()?,
+        b: seq[1].parse::<u32>()?,
+        floats,
+    })
+}
+
 fn vec_to_curve(seq: Vec<String>) -> Result<Parameter> {
-    assert_eq!(seq.len() % 32, 0);
-    Ok(match seq.len() / 32 {
-        1 => Parameter::Curve1(Curve1 {
-            curve: Curve {
-                a: seq[0].parse::<u32>()?,
-                b: seq[1].parse::<u32>()?,
-                floats: seq[2..]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
+    Ok(match seq.len() {
+        32 => Parameter::Curve1(Curve1 {
+            curve: parse_curve(&seq[0..32])?,
         }),
-        2 => Parameter::Curve2(Curve2 {
-            curve1: Curve {
-                a: seq[0].parse::<u32>()?,
-                b: seq[1].parse::<u32>()?,
-                floats: seq[2..32]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
-            curve2: Curve {
-                a: seq[32].parse::<u32>()?,
-                b: seq[33].parse::<u32>()?,
-                floats: seq[34..64]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
+        64 => Parameter::Curve2(Curve2 {
+            curve1: parse_curve(&seq[0..32])?,
+            curve2: parse_curve(&seq[32..64])?,
         }),
-        3 => Parameter::Curve3(Curve3 {
-            curve1: Curve {
-                a: seq[0].parse::<u32>()?,
-                b: seq[1].parse::<u32>()?,
-                floats: seq[2..32]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
-            curve2: Curve {
-                a: seq[32].parse::<u32>()?,
-                b: seq[33].parse::<u32>()?,
-                floats: seq[34..64]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
-            curve3: Curve {
-                a: seq[64].parse::<u32>()?,
-                b: seq[65].parse::<u32>()?,
-                floats: seq[66..96]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
+        96 => Parameter::Curve3(Curve3 {
+            curve1: parse_curve(&seq[0..32])?,
+            curve2: parse_curve(&seq[32..64])?,
+            curve3: parse_curve(&seq[64..96])?,
         }),
-        4 => Parameter::Curve4(Curve4 {
-            curve1: Curve {
-                a: seq[0].parse::<u32>()?,
-                b: seq[1].parse::<u32>()?,
-                floats: seq[2..32]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
-            curve2: Curve {
-                a: seq[32].parse::<u32>()?,
-                b: seq[33].parse::<u32>()?,
-                floats: seq[34..64]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
-            curve3: Curve {
-                a: seq[64].parse::<u32>()?,
-                b: seq[65].parse::<u32>()?,
-                floats: seq[66..96]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
-            curve4: Curve {
-                a: seq[96].parse::<u32>()?,
-                b: seq[97].parse::<u32>()?,
-                floats: seq[98..128]
-                    .iter()
-                    .map(|x| x.parse::<f32>().map_err(|e| e.into()))
-                    .collect::<Result<Vec<f32>>>()?,
-            },
+        128 => Parameter::Curve4(Curve4 {
+            curve1: parse_curve(&seq[0..32])?,
+            curve2: parse_curve(&seq[32..64])?,
+            curve3: parse_curve(&seq[64..96])?,
+            curve4: parse_curve(&seq[96..128])?,
         }),
-        _ => panic!("Invalid curve length"),
+        n => {
+            return Err(YamlParseError::InvalidPio(format!(
+                "Curve has {} values, expected 32, 64, 96, or 128",
+                n
+            )))
+        }
     })
 }
 
@@ -424,13 +424,14 @@ impl PioYamlParser {
             open_seq: None,
             open_tag: None,
             open_params: None,
-            open_keys: vec![String::from("param_root")],
+            open_keys: vec![Key::new("param_root".to_owned(), TScalarStyle::Plain)],
             open_lists: vec![],
             open_objs: vec![],
             open_list_maps: vec![],
             error: None,
             pio: None,
             last_event: None,
+            names: vec![],
         }
     }
 
@@ -440,27 +441,26 @@ impl PioYamlParser {
                 seq.push(val);
                 Ok(())
             } else {
-                let mut table = crate::names::TABLE.lock().unwrap();
                 if let Some(params) = self.open_params.as_mut() {
                     if !self.doing_param_key {
                         let param: Parameter = match tag {
                             Some(TokenType::Tag(ref _handle, ref suffix)) => {
                                 match suffix.as_str() {
                                     "str32" => {
-                                        table.add_name(&val);
+                                        self.names.push(val.clone());
                                         Parameter::String32(val)
                                     }
                                     "str64" => {
-                                        table.add_name(&val);
+                                        self.names.push(val.clone());
                                         Parameter::String64(val)
                                     }
                                     "str256" => {
-                                        table.add_name(&val);
+                                        self.names.push(val.clone());
                                         Parameter::String256(val)
                                     }
                                     "u" => Parameter::U32(parse_int::parse::<u32>(&val)?),
                                     _ => {
-                                        table.add_name(&val);
+                                        self.names.push(val.clone());
                                         Parameter::StringRef(val)
                                     }
                                 }
@@ -469,15 +469,24 @@ impl PioYamlParser {
                                 TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted => {
                                     Parameter::StringRef(val)
                                 }
+                                _ if is_null(&val) => {
+                                    return Err(YamlParseError::InvalidPio(format!(
+                                        "Parameter {} has no value",
+                                        self.open_keys
+                                            .last()
+                                            .map(|k| k.name.as_str())
+                                            .unwrap_or("")
+                                    )))
+                                }
                                 _ => match val.parse::<i32>() {
                                     Ok(v) => Parameter::Int(v),
-                                    Err(_) => match val.parse::<f32>() {
+                                    Err(_) => match parse_float(&val) {
                                         Ok(v) => Parameter::F32(v),
                                         Err(_) => match val.as_str() {
                                             "true" => Parameter::Bool(true),
                                             "false" => Parameter::Bool(false),
                                             _ => {
-                                                table.add_name(&val);
+                                                self.names.push(val.clone());
                                                 Parameter::StringRef(val)
                                             }
                                         },
@@ -487,7 +496,7 @@ impl PioYamlParser {
                         };
                         match &self.open_keys.pop() {
                             Some(key) => {
-                                params.insert(hashit(key), param);
+                                params.insert(key.crc, param);
                             }
                             None => {
                                 return Err(YamlParseError::InvalidPio(
@@ -499,28 +508,19 @@ impl PioYamlParser {
                         return Ok(());
                     }
                 }
-                match val.as_str() {
-                    "objects" => {
+                // Only the bare section keys are structure, so quoted ones can be used as names
+                match (style, val.as_str()) {
+                    (TScalarStyle::Plain, "objects") => {
                         self.doing_objects = true;
                         self.open_objs.push(IndexMap::new());
                     }
-                    "lists" => {
+                    (TScalarStyle::Plain, "lists") => {
                         self.doing_lists = true;
                         self.open_list_maps.push(IndexMap::new());
                     }
                     _ => {
                         self.doing_param_key = false;
-                        match style {
-                            TScalarStyle::DoubleQuoted | TScalarStyle::SingleQuoted => {
-                                match val.parse::<u32>() {
-                                    Ok(u) => {
-                                        self.open_keys.push(["\"", &u.to_string(), "\""].join(""))
-                                    }
-                                    Err(_) => self.open_keys.push(val),
-                                }
-                            }
-                            _ => self.open_keys.push(val),
-                        }
+                        self.open_keys.push(Key::new(val, style));
                     }
                 };
                 Ok(())
@@ -533,19 +533,42 @@ impl PioYamlParser {
     }
 }
 
-#[inline]
-fn hashit(string: &str) -> u32 {
-    return match string.parse::<u32>() {
-        Ok(crc) => crc,
-        Err(_) => {
-            let unquoted = string.replace("\"", "");
-            do_hash(&unquoted)
-        }
-    };
-    #[inline(always)]
-    fn do_hash(string: &str) -> u32 {
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(string.as_bytes());
-        digest.sum32()
+/// Returns whether a plain scalar is a YAML null, which is also what an empty value is read as
+pub(crate) fn is_null(val: &str) -> bool {
+    matches!(val, "~" | "null" | "Null" | "NULL")
+}
+
+/// A mapping key for a list, object, or parameter, and the hash it stands for. A quoted key is
+/// always a name and is hashed as written, even if it looks like a number, so `"42"` is the hash
+/// of the name `42`. A bare key that is a decimal `u32` is the hash itself, so `42` is the hash
+/// 42, and any other bare key is a name.
+struct Key {
+    name: String,
+    crc: u32,
+}
+
+impl Key {
+    fn new(name: String, style: TScalarStyle) -> Key {
+        let crc = match style {
+            TScalarStyle::DoubleQuoted | TScalarStyle::SingleQuoted => hash_name(&name),
+            _ => name.parse::<u32>().unwrap_or_else(|_| hash_name(&name)),
+        };
+        Key { name, crc }
+    }
+}
+
+/// Returns the hash a mapping key written as `key` is read back as, or None if it would not be
+/// read back as a single key
+pub(crate) fn key_crc(key: &str) -> Option<u32> {
+    let text = format!("{}: 0", key);
+    let mut tokens = Scanner::new(text.chars()).map(|Token(_, token)| token);
+    let (style, val) = tokens.find_map(|token| match token {
+        TokenType::Scalar(style, val) => Some((style, val)),
+        _ => None,
+    })?;
+    match (tokens.next(), style, val.as_str()) {
+        (_, TScalarStyle::Plain, "objects") | (_, TScalarStyle::Plain, "lists") => None,
+        (Some(TokenType::Value), _, _) => Some(Key::new(val, style).crc),
+        _ => None,
     }
 }
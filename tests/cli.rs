@@ -0,0 +1,145 @@
+//! Runs the `aamp` binary on a small generated document
+use aamp::names::NameTable;
+use aamp::{Parameter, ParameterIO};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn fixture() -> ParameterIO {
+    ParameterIO::builder()
+        .object("General", |o| {
+            o.param("Life", Parameter::Int(100))
+                .param("Scale", Parameter::F32(1.5))
+                .param("Name", Parameter::StringRef("Enemy_Lizalfos".to_owned()))
+        })
+        .list("Child", |l| {
+            l.object("Param", |o| o.param("Speed", Parameter::F32(2.0)))
+        })
+        .build()
+        .unwrap()
+}
+
+/// Creates an empty directory for one test's files
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("aamp-cli-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn aamp(args: &[&Path]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_aamp"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "aamp {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn read_binary(path: &Path) -> ParameterIO {
+    ParameterIO::from_binary(&mut Cursor::new(fs::read(path).unwrap())).unwrap()
+}
+
+#[test]
+fn yaml_and_binary_roundtrip() {
+    let dir = temp_dir("roundtrip");
+    let (binary, yaml, back) = (
+        dir.join("in.bxml"),
+        dir.join("out.yml"),
+        dir.join("back.bxml"),
+    );
+    let pio = fixture();
+    fs::write(&binary, pio.to_binary().unwrap()).unwrap();
+
+    aamp(&[Path::new("to-yaml"), &binary, &yaml]);
+    let text = fs::read_to_string(&yaml).unwrap();
+    assert!(text.starts_with("!io"), "{}", text);
+    assert_eq!(ParameterIO::from_text(&text).unwrap(), pio);
+
+    aamp(&[Path::new("to-binary"), &yaml, &back]);
+    assert_eq!(fs::read(&back).unwrap(), fs::read(&binary).unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn merge_applies_patches_in_order() {
+    let dir = temp_dir("merge");
+    let (base, first, second, merged) = (
+        dir.join("base.bxml"),
+        dir.join("first.bxml"),
+        dir.join("second.yml"),
+        dir.join("merged.bxml"),
+    );
+    let pio = fixture();
+    fs::write(&base, pio.to_binary().unwrap()).unwrap();
+    let mut patch = ParameterIO::builder()
+        .object("General", |o| o.param("Life", Parameter::Int(200)))
+        .build()
+        .unwrap();
+    fs::write(&first, patch.to_binary().unwrap()).unwrap();
+    patch
+        .object_mut("General")
+        .unwrap()
+        .set_param("Life", Parameter::Int(300));
+    let text = patch.with_names(&NameTable::new(true)).to_text().unwrap();
+    fs::write(&second, text).unwrap();
+
+    aamp(&[
+        Path::new("merge"),
+        &base,
+        &first,
+        &second,
+        Path::new("-o"),
+        &merged,
+    ]);
+    let mut expected = pio;
+    expected
+        .object_mut("General")
+        .unwrap()
+        .set_param("Life", Parameter::Int(300));
+    assert_eq!(read_binary(&merged), expected);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff_prints_summary_and_patch() {
+    let dir = temp_dir("diff");
+    let (base, other) = (dir.join("base.bxml"), dir.join("other.bxml"));
+    let pio = fixture();
+    fs::write(&base, pio.to_binary().unwrap()).unwrap();
+    let mut changed = pio.clone();
+    changed
+        .object_mut("General")
+        .unwrap()
+        .set_param("Life", Parameter::Int(200));
+    fs::write(&other, changed.to_binary().unwrap()).unwrap();
+
+    let output = aamp(&[Path::new("diff"), &base, &other]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap().trim_end(),
+        "0 added, 0 removed, 1 modified"
+    );
+    let patch = ParameterIO::from_text(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    assert_eq!(patch, pio.diff(&changed));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn info_prints_counts() {
+    let dir = temp_dir("info");
+    let input = dir.join("in.bxml");
+    fs::write(&input, fixture().to_binary().unwrap()).unwrap();
+
+    let output = aamp(&[Path::new("info"), &input]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "type: xml\nversion: 0\nlists: 1\nobjects: 2\nparameters: 4\n"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}